@@ -1,12 +1,54 @@
 use std::fmt;
 
-use crate::LogLevel;
+use crate::{format::FORMATS, log::LogFormat, LogLevel};
 use clap::{Arg, ArgAction, ArgGroup, Command};
 
 #[derive(Debug)]
 pub struct Args {
-    pub config: String,
+    pub config: Option<String>,
+    /// directory whose every config is checked in place of `config`
+    pub config_dir: Option<String>,
+    pub bundle: bool,
+    pub stdin: bool,
+    pub completions: Option<String>,
+    /// exit code to describe, or `errors` to describe every exit code
+    pub explain: Option<String>,
+    /// rule to describe in plain English
+    pub explain_rule: Option<String>,
+    pub list_platforms: bool,
+    /// prints a JSON Schema of the site configuration
+    pub json_schema: bool,
+    pub template: Option<String>,
+    /// output format id, selecting its template in place of the make's
+    pub format: Option<String>,
+    /// renders a single device of this name in place of the `devicelist`
+    pub device_name: Option<String>,
+    /// spaces per indent level in templates that honor it
+    pub indent: Option<usize>,
+    /// column at which templates that honor it wrap long port sets
+    pub line_width: Option<usize>,
+    pub sort: bool,
+    /// keeps port lists & ranges as one rule each, for templates rendering them natively
+    pub no_expand: bool,
+    /// file of rules prepended to every ruleset
+    pub seed_rules: Option<String>,
+    /// keeps disabled rules in rulesets passed to templates
+    pub include_disabled: bool,
+    pub count: bool,
+    /// prints the line count & byte size of each rendered output to stderr
+    pub report_size: bool,
+    /// re-renders as inputs change, when built with the `watch` feature
+    pub watch: bool,
+    pub auto_seq: bool,
+    pub max_rules: usize,
+    pub strict: bool,
+    /// fails platforms with interface patterns lacking `^` & `$` anchors
+    pub strict_anchors: bool,
+    pub allow_asymmetric_default: bool,
+    pub diagnostics: bool,
+    pub diff: Option<(String, String)>,
     pub loglevel: LogLevel,
+    pub log_format: LogFormat,
     pub env: EnvVars,
 }
 
@@ -14,12 +56,76 @@ impl fmt::Display for Args {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "config: {}, loglevel: {}, env: {}",
-            self.config, self.loglevel, self.env
+            "config: {:?}, config_dir: {:?}, bundle: {}, stdin: {}, template: {:?}, format: {:?}, device_name: {:?}, indent: {:?}, line_width: {:?}, sort: {}, no_expand: {}, seed_rules: {:?}, include_disabled: {}, count: {}, report_size: {}, watch: {}, auto_seq: {}, max_rules: {}, strict: {}, strict_anchors: {}, allow_asymmetric_default: {}, diagnostics: {}, diff: {:?}, loglevel: {}, log_format: {:?}, env: {}",
+            self.config,
+            self.config_dir,
+            self.bundle,
+            self.stdin,
+            self.template,
+            self.format,
+            self.device_name,
+            self.indent,
+            self.line_width,
+            self.sort,
+            self.no_expand,
+            self.seed_rules,
+            self.include_disabled,
+            self.count,
+            self.report_size,
+            self.watch,
+            self.auto_seq,
+            self.max_rules,
+            self.strict,
+            self.strict_anchors,
+            self.allow_asymmetric_default,
+            self.diagnostics,
+            self.diff,
+            self.loglevel,
+            self.log_format,
+            self.env
         )
     }
 }
 
+/// arguments as parsed from a bare command line, with `env` at its defaults
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            config: None,
+            config_dir: None,
+            bundle: false,
+            stdin: false,
+            completions: None,
+            explain: None,
+            explain_rule: None,
+            list_platforms: false,
+            json_schema: false,
+            template: None,
+            format: None,
+            device_name: None,
+            indent: None,
+            line_width: None,
+            sort: false,
+            no_expand: false,
+            seed_rules: None,
+            include_disabled: false,
+            count: false,
+            report_size: false,
+            watch: false,
+            auto_seq: false,
+            max_rules: 10000,
+            strict: false,
+            strict_anchors: false,
+            allow_asymmetric_default: false,
+            diagnostics: false,
+            diff: None,
+            loglevel: LogLevel::Info,
+            log_format: LogFormat::Text,
+            env: EnvVars::default(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct EnvVars {
     pub platforms: String,
@@ -41,40 +147,126 @@ impl fmt::Display for EnvVars {
 pub fn parse_args() -> Args {
     let matches: clap::ArgMatches = build().get_matches();
 
-    let config: String = matches.get_one::<String>("config").unwrap().to_string();
+    let config: Option<String> = matches.get_one::<String>("config").cloned();
+    let config_dir: Option<String> = matches.get_one::<String>("config-dir").cloned();
+    let bundle: bool = matches.get_flag("bundle");
+    let stdin: bool = matches.get_flag("stdin");
+    let completions: Option<String> = matches.get_one::<String>("completions").cloned();
+    let explain: Option<String> = matches.get_one::<String>("explain").cloned();
+    let explain_rule: Option<String> = matches.get_one::<String>("explain-rule").cloned();
+    let list_platforms: bool = matches.get_flag("list-platforms");
+    let json_schema: bool = matches.get_flag("json-schema");
+    let template: Option<String> = matches.get_one::<String>("template").cloned();
+    let format: Option<String> = matches.get_one::<String>("format").cloned();
+    let device_name: Option<String> = matches.get_one::<String>("device-name").cloned();
+    let indent: Option<usize> = matches.get_one::<usize>("indent").copied();
+    let line_width: Option<usize> = matches.get_one::<usize>("line-width").copied();
+    let sort: bool = matches.get_flag("sort");
+    let no_expand: bool = matches.get_flag("no-expand");
+    let seed_rules: Option<String> = matches.get_one::<String>("seed-rules").cloned();
+    let include_disabled: bool = matches.get_flag("include-disabled");
+    let count: bool = matches.get_flag("count");
+    let report_size: bool = matches.get_flag("report-size");
+    let watch: bool = cfg!(feature = "watch") && matches.get_flag("watch");
+    let auto_seq: bool = matches.get_flag("auto-seq");
+    let max_rules: usize = *matches.get_one::<usize>("max-rules").unwrap();
+    let strict: bool = matches.get_flag("strict");
+    let strict_anchors: bool = matches.get_flag("strict-anchors");
+    let allow_asymmetric_default: bool = matches.get_flag("allow-asymmetric-default");
+    let diagnostics: bool = matches.get_one::<String>("diagnostics").is_some();
+    let diff: Option<(String, String)> = matches.subcommand_matches("diff").map(|diff| {
+        (
+            diff.get_one::<String>("old").unwrap().clone(),
+            diff.get_one::<String>("new").unwrap().clone(),
+        )
+    });
 
+    // flags are mutually exclusive, but precedence is debug > verbose > quiet
     let loglevel: LogLevel = match matches.get_flag("debug") {
         true => LogLevel::Debug,
         false => match matches.get_flag("verbose") {
             true => LogLevel::Verbose,
-            false => LogLevel::Info,
+            false => match matches.get_flag("quiet") || diagnostics {
+                true => LogLevel::Critical,
+                false => LogLevel::Info,
+            },
         },
     };
 
+    let log_format: LogFormat = match matches.get_one::<String>("log-format").map(String::as_str) {
+        Some("json") => LogFormat::Json,
+        _ => LogFormat::Text,
+    };
+
     let env: EnvVars = parse_env();
 
     Args {
         config,
+        config_dir,
+        bundle,
+        stdin,
+        completions,
+        explain,
+        explain_rule,
+        list_platforms,
+        json_schema,
+        template,
+        format,
+        device_name,
+        indent,
+        line_width,
+        sort,
+        no_expand,
+        seed_rules,
+        include_disabled,
+        count,
+        report_size,
+        watch,
+        auto_seq,
+        max_rules,
+        strict,
+        strict_anchors,
+        allow_asymmetric_default,
+        diagnostics,
+        diff,
         loglevel,
+        log_format,
         env,
     }
 }
 
+/// directories read when their environment variables are unset
+impl Default for EnvVars {
+    fn default() -> Self {
+        EnvVars {
+            platforms: String::from(PLATFORMS_PATH),
+            rulesets: String::from(RULESETS_PATH),
+            templates: String::from(TEMPLATES_PATH),
+        }
+    }
+}
+
+/// directories read when their environment variables are unset, as listed in `ENV_MSG`
+const PLATFORMS_PATH: &str = "./platform";
+const RULESETS_PATH: &str = "./acls";
+const TEMPLATES_PATH: &str = "./tmpl";
+
 /// loads only environment variables
+/// - rulesets are read from the documented `AM3K_RULESETS_PATH`, or else the singular
+///   `AM3K_RULESET_PATH` read by earlier releases
 pub fn parse_env() -> EnvVars {
+    let path = |names: &[&str], default: &str| -> String {
+        names
+            .iter()
+            .find_map(|name| std::env::var(name).ok())
+            .map_or(String::from(default), |path| {
+                path.trim_end_matches('/').to_string()
+            })
+    };
     EnvVars {
-        platforms: match std::env::var("AM3K_PLATFORMS_PATH") {
-            Ok(path) => path.trim_end_matches('/').to_string(),
-            Err(_) => String::from("./platform"),
-        },
-        rulesets: match std::env::var("AM3K_RULESET_PATH") {
-            Ok(path) => path.trim_end_matches('/').to_string(),
-            Err(_) => String::from("./acls"),
-        },
-        templates: match std::env::var("AM3K_TEMPLATES_PATH") {
-            Ok(path) => path.trim_end_matches('/').to_string(),
-            Err(_) => String::from("./tmpl"),
-        },
+        platforms: path(&["AM3K_PLATFORMS_PATH"], PLATFORMS_PATH),
+        rulesets: path(&["AM3K_RULESETS_PATH", "AM3K_RULESET_PATH"], RULESETS_PATH),
+        templates: path(&["AM3K_TEMPLATES_PATH"], TEMPLATES_PATH),
     }
 }
 
@@ -83,11 +275,163 @@ const ENV_MSG: &str = r#"Environment:
     AM3K_PLATFORMS_PATH     Path to the directory containing platform definitions. Defaults to "./platform".
     AM3K_RULESETS_PATH      Path to the directory containing ACL definitions. Defaults to "./acls".
     AM3K_TEMPLATES_PATH     Path to the directory containing template definitions. Defaults to "./tmpl".
+    AM3K_LOG_TIMESTAMPS     Set to "1" to prefix log lines with a UTC timestamp and level name.
 "#;
 
+/// generates a completion script for the provided shell from the argument parser
+/// - supports `bash`, `zsh`, and `fish`, hidden arguments are omitted
+/// - options taking a value complete its possible values, or files for a `FILE` or `DIR`
+/// - subcommands complete in place of the config file
+pub fn completions(shell: &str) -> String {
+    let cmd: Command = build();
+    let name: &str = cmd.get_name();
+    let args: Vec<&Arg> = cmd
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set() && !arg.is_positional())
+        .collect();
+    let subcommands: Vec<(&str, String)> = cmd
+        .get_subcommands()
+        .map(|sub| {
+            let about: String = sub.get_about().map(|a| a.to_string()).unwrap_or_default();
+            (sub.get_name(), about)
+        })
+        .collect();
+    let help = |arg: &Arg| -> String { arg.get_help().map(|h| h.to_string()).unwrap_or_default() };
+    let values = |arg: &Arg| -> Vec<String> {
+        arg.get_possible_values()
+            .iter()
+            .map(|value| value.get_name().to_string())
+            .collect()
+    };
+    let value_name = |arg: &Arg| -> String {
+        arg.get_value_names()
+            .and_then(|names| names.first())
+            .map_or(String::from("VALUE"), |name| name.to_string())
+    };
+
+    match shell {
+        "bash" => {
+            let flags: Vec<String> = args
+                .iter()
+                .flat_map(|arg| {
+                    let short = arg.get_short().map(|s| format!("-{}", s));
+                    let long = arg.get_long().map(|l| format!("--{}", l));
+                    short.into_iter().chain(long)
+                })
+                .chain(subcommands.iter().map(|(sub, _)| sub.to_string()))
+                .collect();
+            // options with possible values complete only those after the option
+            let cases: String = args
+                .iter()
+                .filter(|arg| arg.get_action().takes_values() && !values(arg).is_empty())
+                .filter_map(|arg| {
+                    let long: &str = arg.get_long()?;
+                    Some(format!(
+                        "        --{})\n            COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n            return\n            ;;\n",
+                        long,
+                        values(arg).join(" ")
+                    ))
+                })
+                .collect();
+            format!(
+                "_{name}() {{\n    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"\n    \
+                 local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"\n    \
+                 case \"$prev\" in\n{cases}    esac\n    \
+                 COMPREPLY=($(compgen -W \"{}\" -- \"$cur\") $(compgen -f -- \"$cur\"))\n}}\n\
+                 complete -F _{name} {name}\n",
+                flags.join(" ")
+            )
+        }
+        "zsh" => {
+            // descriptions sit in single quotes, where `[`, `]` & `:` delimit _arguments specs
+            let escape = |s: &str| -> String {
+                s.replace('\\', "\\\\")
+                    .replace('[', "\\[")
+                    .replace(']', "\\]")
+                    .replace(':', "\\:")
+                    .replace('\'', "'\\''")
+            };
+            let action = |arg: &Arg| -> String {
+                if !arg.get_action().takes_values() {
+                    return String::new();
+                }
+                let values: Vec<String> = values(arg);
+                let value_name: String = value_name(arg);
+                let complete: String = match value_name.as_str() {
+                    _ if !values.is_empty() => format!("({})", values.join(" ")),
+                    "FILE" => String::from("_files"),
+                    "DIR" => String::from("_files -/"),
+                    _ => String::new(),
+                };
+                format!(":{}:{}", value_name.to_lowercase(), complete)
+            };
+            let mut script: String = format!("#compdef {name}\n\n");
+            script.push_str(&format!(
+                "_{name}_commands() {{\n    local -a commands\n    commands=(\n"
+            ));
+            // _describe splits each entry at its first unescaped `:`
+            for (sub, about) in &subcommands {
+                let about: String = about.replace(':', "\\:").replace('\'', "'\\''");
+                script.push_str(&format!("        '{}:{}'\n", sub, about));
+            }
+            script.push_str("    )\n    _describe -t commands command commands\n    _files\n}\n\n");
+            script.push_str("_arguments \\\n");
+            for arg in &args {
+                if let Some(s) = arg.get_short() {
+                    script.push_str(&format!(
+                        "    '-{}[{}]{}' \\\n",
+                        s,
+                        escape(&help(arg)),
+                        action(arg)
+                    ));
+                }
+                if let Some(l) = arg.get_long() {
+                    script.push_str(&format!(
+                        "    '--{}[{}]{}' \\\n",
+                        l,
+                        escape(&help(arg)),
+                        action(arg)
+                    ));
+                }
+            }
+            script.push_str(&format!("    '1: :_{name}_commands' \\\n"));
+            script.push_str("    '*:file:_files'\n");
+            script
+        }
+        _ => {
+            let escape = |s: &str| -> String { s.replace('\\', "\\\\").replace('\'', "\\'") };
+            let options = args.iter().map(|arg| {
+                let mut line: String = format!("complete -c {name}");
+                if let Some(s) = arg.get_short() {
+                    line.push_str(&format!(" -s {}", s));
+                }
+                if let Some(l) = arg.get_long() {
+                    line.push_str(&format!(" -l {}", l));
+                }
+                if arg.get_action().takes_values() {
+                    line.push_str(" -r");
+                    let values: Vec<String> = values(arg);
+                    if !values.is_empty() {
+                        line.push_str(&format!(" -f -a '{}'", values.join(" ")));
+                    }
+                }
+                format!("{} -d '{}'\n", line, escape(&help(arg)))
+            });
+            let commands = subcommands.iter().map(|(sub, about)| {
+                format!(
+                    "complete -c {name} -n __fish_use_subcommand -a {} -d '{}'\n",
+                    sub,
+                    escape(about)
+                )
+            });
+            options.chain(commands).collect()
+        }
+    }
+}
+
 /// builds a custom command line argument parser
 fn build() -> Command {
-    Command::new("app")
+    let command: Command = Command::new(env!("CARGO_PKG_NAME"))
         .about(ABOUT_MSG)
         .version(env!("CARGO_PKG_VERSION"))
         .author("rskntroot")
@@ -95,7 +439,207 @@ fn build() -> Command {
             Arg::new("config")
                 .value_name("FILE")
                 .help("Sets a custom config file")
-                .required(true),
+                .required_unless_present_any(["stdin", "completions", "explain", "explain-rule", "list-platforms", "json-schema", "config-dir"]),
+        )
+        .arg(
+            Arg::new("config-dir")
+                .long("config-dir")
+                .value_name("DIR")
+                .help("Checks every .yml & .yaml config in DIR, printing a pass/fail summary instead of rendering")
+                .conflicts_with_all(["config", "stdin"])
+                .required(false),
+        )
+        .arg(
+            Arg::new("bundle")
+                .long("bundle")
+                .help("Reads rulesets inline from the config file's `rulesets` instead of ruleset files")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("stdin")
+                .required(false),
+        )
+        .arg(
+            Arg::new("stdin")
+                .long("stdin")
+                .help("Read rules from stdin and print them expanded, without a config file")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("config")
+                .required(false),
+        )
+        .arg(
+            Arg::new("completions")
+                .long("completions")
+                .value_name("SHELL")
+                .help("Print a shell completion script")
+                .value_parser(["bash", "zsh", "fish"])
+                .hide(true)
+                .required(false),
+        )
+        .arg(
+            Arg::new("list-platforms")
+                .long("list-platforms")
+                .help("Lists the makes & models found in AM3K_PLATFORMS_PATH")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["config", "stdin"])
+                .required(false),
+        )
+        .arg(
+            Arg::new("json-schema")
+                .long("json-schema")
+                .help("Prints a JSON Schema of the site configuration yaml")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["config", "stdin"])
+                .required(false),
+        )
+        .arg(
+            Arg::new("explain")
+                .long("explain")
+                .value_name("CODE")
+                .help("Describes what an exit code means, or every exit code given `errors`")
+                .value_parser(|s: &str| match s == "errors" || s.parse::<i32>().is_ok() {
+                    true => Ok(String::from(s)),
+                    false => Err(String::from("expected an exit code or `errors`")),
+                })
+                .required(false),
+        )
+        .arg(
+            Arg::new("explain-rule")
+                .long("explain-rule")
+                .value_name("RULE")
+                .help("Describes what a rule matches in plain English, e.g. 'allow tcp any any 10.0.0.0/8 443'")
+                .conflicts_with_all(["config", "stdin", "explain"])
+                .required(false),
+        )
+        .arg(
+            Arg::new("template")
+                .short('t')
+                .long("template")
+                .value_name("NAME")
+                .help("Sets the template rendered for every device, in place of its format's")
+                .conflicts_with("stdin")
+                .required(false),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_name("ID")
+                .help("Sets the output format rendered for every device [default: yaml, arista for arista, cisco for cisco]")
+                .value_parser(FORMATS.iter().map(|format| format.id).collect::<Vec<&str>>())
+                .conflicts_with_all(["stdin", "template"])
+                .required(false),
+        )
+        .arg(
+            Arg::new("device-name")
+                .long("device-name")
+                .value_name("NAME")
+                .help("Renders a single device of this name instead of the config's devicelist")
+                .conflicts_with("stdin")
+                .required(false),
+        )
+        .arg(
+            Arg::new("indent")
+                .long("indent")
+                .value_name("SPACES")
+                .help("Indents each level of rendered output by SPACES, in templates that honor it")
+                .value_parser(clap::value_parser!(usize))
+                .required(false),
+        )
+        .arg(
+            Arg::new("line-width")
+                .long("line-width")
+                .value_name("COLS")
+                .help("Wraps port sets longer than COLS in rendered output, in templates that honor it")
+                .value_parser(clap::value_parser!(usize))
+                .required(false),
+        )
+        .arg(
+            Arg::new("sort")
+                .long("sort")
+                .help("Sort rules into a canonical order before rendering")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("no-expand")
+                .long("no-expand")
+                .help("Keeps port lists & ranges intact in rendered output instead of a rule per port")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("seed-rules")
+                .long("seed-rules")
+                .value_name("FILE")
+                .help("Prepends the rules of FILE to every ruleset, e.g. permitting management")
+                .conflicts_with("stdin")
+                .required(false),
+        )
+        .arg(
+            Arg::new("include-disabled")
+                .long("include-disabled")
+                .help("Passes disabled rules to templates as `rule.enabled: false`, instead of dropping them")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("count")
+                .long("count")
+                .help("Prints the rules of each ruleset before & after expansion instead of rendering")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("stdin")
+                .required(false),
+        )
+        .arg(
+            Arg::new("report-size")
+                .long("report-size")
+                .help("Prints the lines & bytes of each rendered output to stderr, after rendering")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["stdin", "count"])
+                .required(false),
+        )
+        .arg(
+            Arg::new("auto-seq")
+                .long("auto-seq")
+                .help("Numbers rules without a `seq=N` in steps of 10, after sorting")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("max-rules")
+                .long("max-rules")
+                .value_name("N")
+                .help("Fails any ruleset expanding to more than N rules")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("10000")
+                .required(false),
+        )
+        .arg(
+            Arg::new("strict")
+                .long("strict")
+                .help("Fails with exit code 8 when any warning is found, e.g. unreachable rules")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("strict-anchors")
+                .long("strict-anchors")
+                .help("Fails platforms whose interface patterns lack `^` & `$` anchors, instead of warning")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("allow-asymmetric-default")
+                .long("allow-asymmetric-default")
+                .help("Skips the warning for ingress & egress defaults that differ")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("diagnostics")
+                .long("diagnostics")
+                .value_name("FORMAT")
+                .help("Prints rule errors to stdout in a machine-readable format, implies -q")
+                .value_parser(["json"])
+                .required(false),
         )
         .arg(
             Arg::new("debug")
@@ -113,10 +657,97 @@ fn build() -> Command {
                 .action(ArgAction::SetTrue)
                 .required(false),
         )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .help("Print only critical information and rendered output")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("log-format")
+                .long("log-format")
+                .value_name("FORMAT")
+                .help("Sets the log line format, rendered output is unaffected")
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .required(false),
+        )
         .group(
             ArgGroup::new("loglevel")
-                .args(&["debug", "verbose"])
+                .args(["debug", "verbose", "quiet"])
                 .required(false),
         )
-        .after_help(ENV_MSG)
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("diff")
+                .about("Prints a unified diff of the output rendered from two config files, exiting 7 if they differ")
+                .arg(Arg::new("old").value_name("OLD").help("Sets the baseline config file").required(true))
+                .arg(Arg::new("new").value_name("NEW").help("Sets the changed config file").required(true)),
+        )
+        .after_help(ENV_MSG);
+    #[cfg(feature = "watch")]
+    let command: Command = command.arg(
+        Arg::new("watch")
+            .long("watch")
+            .help("Re-renders each time the config, rulesets, or templates change")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(["stdin", "count"])
+            .required(false),
+    );
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bash_completions_name_program() {
+        let script: String = completions("bash");
+        assert!(!script.is_empty());
+        assert!(script.contains("complete -F _am3k am3k"));
+        assert!(script.contains("--verbose"));
+        assert!(!script.contains("--completions"));
+    }
+
+    #[test]
+    fn completions_escape_help_and_complete_values() {
+        let zsh: String = completions("zsh");
+        assert!(zsh.contains(
+            "'--format[Sets the output format rendered for every device \\[default\\: yaml, arista for arista, cisco for cisco\\]]:id:(yaml arista cisco junos nftables iptables)'"
+        ));
+        assert!(zsh.contains("in place of its format'\\''s]:name:'"));
+        assert!(zsh.contains("'--seed-rules["));
+        assert!(zsh.contains("]:file:_files'"));
+        assert!(zsh.contains("'--max-rules[Fails any ruleset expanding to more than N rules]:n:'"));
+        assert!(zsh.contains("'--sort[Sort rules into a canonical order before rendering]' \\\n"));
+
+        let fish: String = completions("fish");
+        assert!(fish.contains("-l format -r -f -a 'yaml arista cisco junos nftables iptables'"));
+        assert!(fish.contains("in place of its format\\'s'\n"));
+
+        assert!(zsh.contains("\n        'diff:Prints a unified diff"));
+        assert!(fish.contains("-n __fish_use_subcommand -a diff -d 'Prints a unified diff"));
+
+        let bash: String = completions("bash");
+        assert!(bash.contains(" --log-format diff\" -- "));
+        assert!(bash.contains("--format)\n"));
+    }
+
+    #[test]
+    fn env_help_lists_default_paths() {
+        for path in [PLATFORMS_PATH, RULESETS_PATH, TEMPLATES_PATH] {
+            assert!(ENV_MSG.contains(&format!("Defaults to \"{}\".", path)));
+        }
+    }
+
+    #[test]
+    fn config_still_required_without_short_circuit() {
+        assert!(build().try_get_matches_from(["am3k"]).is_err());
+        assert!(build()
+            .try_get_matches_from(["am3k", "--completions", "zsh"])
+            .is_ok());
+    }
 }
@@ -1,38 +1,200 @@
-use crate::{crit, dbug, verb, LogLevel};
+use crate::{
+    crit, dbug,
+    device::PlatformCatalog,
+    ruleset::{Action, Family, ObjectMap, PortType, Prefix, PrefixMap, Syntax},
+    verb, warn, LogLevel,
+};
 use regex::Regex;
-use serde::{Deserialize, Serialize};
-use std::{fs, path::PathBuf};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 use thiserror::Error;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Configuration {
     pub deployment: Deployment,
     pub defaults: Defaults,
+    /// address objects referenced from rule prefixes as `@name`
+    #[serde(default)]
+    pub objects: ObjectMap,
+    /// inline rulesets keyed by name, read in place of `.acl` files by `load_bundle`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rulesets: BTreeMap<String, Vec<String>>,
 }
 
 impl Configuration {
     /// loads a site configuration yaml
-    /// - checks `are_names_complaint` & `do_rulesets_exist`
+    /// - errs with `DeviceRegexInvalid` before deserializing when the pattern fails to compile
+    /// - checks `are_names_complaint`, defined platforms, `is_default_valid` & `do_rulesets_exist`
+    /// - writes cidrs of direction filters & transforms as rule prefixes are written
     pub fn load(
         file_path: &str,
         acls_path: &str,
         dbg: LogLevel,
+    ) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        Self::load_from(file_path, Some(acls_path), dbg)
+    }
+
+    /// loads a site configuration yaml bundling its rulesets inline
+    /// - checks as `load`, except each deployment ruleset must name an inline ruleset
+    pub fn load_bundle(
+        file_path: &str,
+        dbg: LogLevel,
+    ) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        Self::load_from(file_path, None, dbg)
+    }
+
+    /// loads a site configuration, finding rulesets in `acls_path` or inline when `None`
+    fn load_from(
+        file_path: &str,
+        acls_path: Option<&str>,
+        dbg: LogLevel,
     ) -> Result<Option<Self>, Box<dyn std::error::Error>> {
         let mut valid_config: bool = true;
-        let cfg: Configuration =
-            serde_yml::from_str(&fs::read_to_string(PathBuf::from(file_path))?)?;
+        let contents: String = read_file(Path::new(file_path))?;
+        let contents: String = substitute_env(&contents, |var| std::env::var(var).ok())?;
+        check_device_regex(&contents)?;
+        let mut cfg: Configuration = parse_yaml(Path::new(file_path), &contents)?;
+        cfg.deployment.ingress.normalize_prefixes();
+        cfg.deployment.egress.normalize_prefixes();
         dbug!(dbg, "{:#?}", cfg);
 
         verb!(dbg, "  Checking devicelist naming convention...");
-        match are_names_complaint(&cfg.deployment.devicelist, &cfg.defaults.device_regex, dbg) {
-            true => verb!(dbg, "  Devices matched convention."),
-            false => valid_config = false,
+        match are_names_complaint(&cfg.deployment.devicelist, &cfg.defaults.device_regex) {
+            Ok(()) => verb!(dbg, "  Devices matched convention."),
+            Err(names) => {
+                for name in &names {
+                    match prefix_hint(name, &cfg.defaults.device_regex) {
+                        Some(hint) => crit!(
+                            dbg,
+                            "* {}: {} (expected prefix '{}')",
+                            ConfigInvalid::DeviceNamesInvalid,
+                            name,
+                            hint
+                        ),
+                        None => crit!(dbg, "* {}: {}", ConfigInvalid::DeviceNamesInvalid, name),
+                    }
+                }
+                valid_config = false
+            }
         }
 
-        verb!(dbg, "\n  Checking ruleset files exist...");
-        match do_rulesets_exist(&cfg.deployment.rulesets, &acls_path, dbg) {
-            true => verb!(dbg, "  Ruleset files exist."),
-            false => valid_config = false,
+        verb!(dbg, "\n  Checking platforms are defined...");
+        match cfg.deployment.platforms().is_empty() {
+            false => verb!(dbg, "  Platforms defined."),
+            true => {
+                crit!(dbg, "* {}", ConfigInvalid::PlatformUndefined);
+                valid_config = false
+            }
+        }
+
+        verb!(dbg, "\n  Checking direction defaults are valid actions...");
+        let invalid_defaults: Vec<ConfigInvalid> = check_defaults(&cfg.deployment);
+        match invalid_defaults.is_empty() {
+            true => verb!(dbg, "  Direction defaults are valid."),
+            false => {
+                for e in invalid_defaults {
+                    crit!(dbg, "* {}", e);
+                }
+                valid_config = false
+            }
+        }
+
+        verb!(dbg, "\n  Checking transforms provide mappings...");
+        for (name, direction) in [
+            ("ingress", &cfg.deployment.ingress),
+            ("egress", &cfg.deployment.egress),
+        ] {
+            for (field, transform) in [
+                ("src", &direction.transforms.src),
+                ("dst", &direction.transforms.dst),
+            ] {
+                if let Transform::Toggle(true) = transform {
+                    warn!(
+                        dbg,
+                        "  {}.transforms.{} enabled without a mapping, no prefixes rewritten",
+                        name,
+                        field
+                    );
+                }
+            }
+        }
+
+        verb!(dbg, "\n  Checking interfaces belong to a single zone...");
+        for (name, direction) in [
+            ("ingress", &cfg.deployment.ingress),
+            ("egress", &cfg.deployment.egress),
+        ] {
+            let (unmapped, conflicts) = check_zones(name, direction);
+            for iface in unmapped {
+                warn!(
+                    dbg,
+                    "  {} interface {} is not mapped to a zone", name, iface
+                );
+            }
+            for e in conflicts {
+                crit!(dbg, "* {}", e);
+                valid_config = false;
+            }
+        }
+
+        match acls_path {
+            Some(acls_path) => {
+                verb!(dbg, "\n  Checking ruleset names are safe file stems...");
+                let unsafe_names: Vec<&String> = cfg
+                    .deployment
+                    .rulesets
+                    .iter()
+                    .filter(|name| !is_name_safe(name))
+                    .collect();
+                if !unsafe_names.is_empty() {
+                    for name in unsafe_names {
+                        crit!(dbg, "* {}: {}", ConfigInvalid::RulesetNameInvalid, name);
+                    }
+                    return Ok(None);
+                }
+
+                verb!(dbg, "\n  Checking ruleset files exist...");
+                match expand_rulesets(&cfg.deployment.rulesets, acls_path) {
+                    Ok(rulesets) => cfg.deployment.rulesets = rulesets,
+                    Err(patterns) => {
+                        for pattern in patterns {
+                            crit!(dbg, "* {}: {}", ConfigInvalid::RulesetFileDNE, pattern);
+                        }
+                        // patterns match existing files only, so the plain names are left to check
+                        cfg.deployment
+                            .rulesets
+                            .retain(|name| !name.contains(['*', '?']));
+                        valid_config = false
+                    }
+                }
+                match do_rulesets_exist(&cfg.deployment.rulesets, acls_path, dbg) {
+                    true => verb!(dbg, "  Ruleset files exist."),
+                    false => valid_config = false,
+                }
+            }
+            None => {
+                verb!(dbg, "\n  Checking inline rulesets exist...");
+                let undefined: Vec<&String> = cfg
+                    .deployment
+                    .rulesets
+                    .iter()
+                    .filter(|name| !cfg.rulesets.contains_key(*name))
+                    .collect();
+                match undefined.is_empty() {
+                    true => verb!(dbg, "  Inline rulesets exist."),
+                    false => {
+                        for name in undefined {
+                            crit!(dbg, "* {}: {}", ConfigInvalid::RulesetUndefined, name);
+                        }
+                        valid_config = false
+                    }
+                }
+            }
         }
 
         match valid_config {
@@ -40,24 +202,86 @@ impl Configuration {
             false => Ok(None),
         }
     }
+
+    /// cross-checks no platform names a model listed under a different make in the catalog
+    /// - makes or models missing from the catalog are left for `Device::build` to report
+    pub fn check_platforms(&self, catalog: &PlatformCatalog) -> Vec<ConfigInvalid> {
+        let lists = |make: &str, model: &str| {
+            catalog
+                .load(make)
+                .is_ok_and(|supported| supported.lookup_model(model).is_some())
+        };
+        self.deployment
+            .platforms()
+            .into_iter()
+            .filter(|platform| catalog.file(&platform.make).is_ok())
+            .filter(|platform| !lists(&platform.make, &platform.model))
+            .filter_map(|platform| {
+                let owner: String = catalog
+                    .makes()
+                    .into_iter()
+                    .find(|make| lists(make, &platform.model))?;
+                Some(ConfigInvalid::PlatformModelMismatch {
+                    make: platform.make.clone(),
+                    model: platform.model.clone(),
+                    owner,
+                })
+            })
+            .collect()
+    }
+
+    /// checks a device name supplied outside of the `devicelist` against `device_regex`
+    pub fn check_device_name(&self, name: &str) -> Result<(), ConfigInvalid> {
+        are_names_complaint(&[String::from(name)], &self.defaults.device_regex)
+            .map_err(|_| ConfigInvalid::DeviceNamesInvalid)
+    }
+
+    /// describes differing ingress & egress default actions, when both directions deploy
+    pub fn asymmetric_default(&self) -> Option<String> {
+        let (ingress, egress) = (&self.deployment.ingress, &self.deployment.egress);
+        if !ingress.deployable || !egress.deployable {
+            return None;
+        }
+        match ingress.default_action() != egress.default_action() {
+            true => Some(format!(
+                "ingress default {} differs from egress default {}",
+                ingress.default, egress.default
+            )),
+            false => None,
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Deployment {
     pub rulesets: Vec<String>,
-    pub platform: Platform,
+    /// address family each named ruleset is limited to, where unnamed rulesets are `both`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub families: BTreeMap<String, Family>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+    #[serde(default)]
+    pub platforms: Vec<Platform>,
     pub devicelist: Vec<String>,
     pub ingress: Direction,
     pub egress: Direction,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl Deployment {
+    /// lists every platform targeted by the deployment
+    /// - singular `platform` is kept for backward compat and listed first
+    pub fn platforms(&self) -> Vec<&Platform> {
+        self.platform.iter().chain(self.platforms.iter()).collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Platform {
     pub make: String,
     pub model: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Direction {
     pub interfaces: Vec<String>,
     pub filters: Filters,
@@ -65,26 +289,105 @@ pub struct Direction {
     pub established: bool,
     pub default: String,
     pub transforms: Transforms,
+    /// optional zone names, each listing member interfaces
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub zones: BTreeMap<String, Vec<String>>,
+}
+
+impl Direction {
+    /// writes the cidrs of `filters` & `transforms` mappings as rule prefixes are written,
+    /// so `2001:DB8::/32` matches rules of `2001:db8::/32` or any other spelling
+    fn normalize_prefixes(&mut self) {
+        let normal = |prefix: &String| -> String { Prefix::from(prefix.as_str()).to_string() };
+        for filter in [&mut self.filters.src, &mut self.filters.dst] {
+            *filter = filter.iter().map(normal).collect();
+        }
+        for transform in [&mut self.transforms.src, &mut self.transforms.dst] {
+            if let Transform::Mapping(map) = transform {
+                *map = map
+                    .iter()
+                    .map(|(from, to)| (normal(from), to.clone()))
+                    .collect();
+            }
+        }
+    }
+
+    /// lists the zones each declared interface belongs to, in `interfaces` order
+    pub fn interface_zones(&self) -> Vec<(&str, Vec<&str>)> {
+        self.interfaces
+            .iter()
+            .map(|iface| {
+                let zones: Vec<&str> = self
+                    .zones
+                    .iter()
+                    .filter(|(_, members)| members.contains(iface))
+                    .map(|(zone, _)| zone.as_str())
+                    .collect();
+                (iface.as_str(), zones)
+            })
+            .collect()
+    }
+
+    /// zones limited to declared interfaces
+    pub fn resolved_zones(&self) -> BTreeMap<&str, Vec<&str>> {
+        let mut resolved: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (iface, zones) in self.interface_zones() {
+            for zone in zones {
+                resolved.entry(zone).or_default().push(iface);
+            }
+        }
+        resolved
+    }
+
+    /// parses `default` into the action appended as the terminal rule
+    pub fn default_action(&self) -> Option<Action> {
+        Action::from_str(&self.default).ok()
+    }
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Filters {
     pub src: Vec<String>,
     pub dst: Vec<String>,
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Transforms {
-    pub src: bool,
-    pub dst: bool,
+    pub src: Transform,
+    pub dst: Transform,
+}
+
+/// prefix rewrites for a direction
+/// - a bare bool is accepted for backward compat, but rewrites nothing
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Transform {
+    Toggle(bool),
+    Mapping(PrefixMap),
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl Transform {
+    /// returns the from→to prefix mapping, if any was provided
+    pub fn mapping(&self) -> Option<&PrefixMap> {
+        match self {
+            Transform::Toggle(_) => None,
+            Transform::Mapping(map) => Some(map),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Defaults {
     #[serde(with = "regex_serde")]
     pub device_regex: Regex,
+    /// `comment_prefix` & `case_sensitive` used when reading ruleset files
+    #[serde(default, flatten)]
+    pub syntax: Syntax,
+    /// service ports warned on by the port advisory, besides port `0`, e.g. `49152-65535`
+    #[serde(default, with = "ports_serde", skip_serializing_if = "Vec::is_empty")]
+    pub unexpected_ports: Vec<PortType>,
 }
 
 #[derive(Debug, Error)]
@@ -93,22 +396,361 @@ pub enum ConfigInvalid {
     DeviceNamesInvalid,
     #[error("RulesetFileDNE: failed to find matching ruleset file")]
     RulesetFileDNE,
+    #[error("RulesetNameInvalid: expected a ruleset name without path separators or `..`")]
+    RulesetNameInvalid,
+    #[error("RulesetUndefined: failed to find matching inline ruleset in `rulesets`")]
+    RulesetUndefined,
+    #[error("PlatformUndefined: expected `platform` or `platforms` in deployment")]
+    PlatformUndefined,
+    #[error("DefaultActionInvalid: expected 'allow', 'deny', 'allowlog', or 'denylog' for {direction}.default: {value}")]
+    DefaultActionInvalid { direction: String, value: String },
+    #[error("DeviceRegexInvalid: failed to compile `defaults.device_regex` pattern '{pattern}'\n{error}")]
+    DeviceRegexInvalid {
+        pattern: String,
+        error: regex::Error,
+    },
+    #[error("PlatformModelMismatch: expected a {make} model, found {model} listed under {owner}")]
+    PlatformModelMismatch {
+        make: String,
+        model: String,
+        owner: String,
+    },
+    #[error(
+        "EnvVarUndefined: expected ${{{var}}} to be set, or given a default as ${{{var}:-default}}"
+    )]
+    EnvVarUndefined { var: String },
+    #[error("ZoneConflict: expected {direction} interface {interface} in exactly one zone, found: {}", zones.join(", "))]
+    ZoneConflict {
+        direction: String,
+        interface: String,
+        zones: Vec<String>,
+    },
     #[error(
-        "FailedPostChecks: Loaded, but failed on DeviceNamesInvalid and/or RulesetFileDoesNotExist"
+        "FailedPostChecks: Loaded, but failed on DeviceNamesInvalid, PlatformUndefined, PlatformModelMismatch, DefaultActionInvalid, ZoneConflict, RulesetNameInvalid, RulesetUndefined and/or RulesetFileDoesNotExist"
     )]
     FailedPostChecks,
 }
 
+/// describes the site configuration yaml as a JSON Schema, for editors to validate against
+/// - written out by hand, mirroring the serde attributes of `Configuration` and its fields
+/// - tests serialize a `Configuration` with every field set, so a field added to either fails them
+pub fn json_schema() -> serde_json::Value {
+    let strings = || serde_json::json!({ "type": "array", "items": { "type": "string" } });
+    let platform = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "make": { "type": "string" },
+            "model": { "type": "string" },
+        },
+        "required": ["make", "model"],
+        "additionalProperties": false,
+    });
+    let transform = serde_json::json!({
+        "oneOf": [
+            { "type": "boolean" },
+            { "type": "object", "additionalProperties": { "type": "string" } },
+        ],
+    });
+    let direction = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "interfaces": strings(),
+            "filters": {
+                "type": "object",
+                "properties": { "src": strings(), "dst": strings() },
+                "required": ["src", "dst"],
+            },
+            "deployable": { "type": "boolean" },
+            "established": { "type": "boolean" },
+            "default": { "enum": ["allow", "deny", "allowlog", "denylog"] },
+            "transforms": {
+                "type": "object",
+                "properties": { "src": transform, "dst": transform },
+                "required": ["src", "dst"],
+            },
+            "zones": { "type": "object", "additionalProperties": strings() },
+        },
+        "required": ["interfaces", "filters", "deployable", "established", "default", "transforms"],
+    });
+    serde_json::json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "am3k site configuration",
+        "type": "object",
+        "properties": {
+            "deployment": {
+                "type": "object",
+                "properties": {
+                    "rulesets": strings(),
+                    "families": {
+                        "type": "object",
+                        "additionalProperties": { "enum": ["inet", "inet6", "both"] },
+                    },
+                    "platform": platform,
+                    "platforms": { "type": "array", "items": platform },
+                    "devicelist": strings(),
+                    "ingress": direction,
+                    "egress": direction,
+                },
+                "required": ["rulesets", "devicelist", "ingress", "egress"],
+            },
+            "defaults": {
+                "type": "object",
+                "properties": {
+                    "device_regex": { "type": "string", "format": "regex" },
+                    "comment_prefix": { "type": "string" },
+                    "case_sensitive": { "type": "boolean" },
+                    "unexpected_ports": {
+                        "type": "array",
+                        "items": { "type": ["string", "integer"] },
+                    },
+                },
+                "required": ["device_regex"],
+            },
+            "objects": { "type": "object", "additionalProperties": strings() },
+            "rulesets": { "type": "object", "additionalProperties": strings() },
+        },
+        "required": ["deployment", "defaults"],
+    })
+}
+
+/// yaml files that fail to read or deserialize, named by path
+#[derive(Debug, Error)]
+pub enum YamlInvalid {
+    #[error("YamlUnreadable: failed to read {file}\n{error}")]
+    YamlUnreadable { file: String, error: std::io::Error },
+    /// - `file` is suffixed with the `:line:column` of the error, when serde_yml locates it
+    #[error("YamlMalformed: {file} is malformed\n{error}")]
+    YamlMalformed { file: String, error: String },
+}
+
+/// reads & deserializes a yaml file, naming the file in any error
+pub fn read_yaml<T: DeserializeOwned>(path: &Path) -> Result<T, YamlInvalid> {
+    parse_yaml(path, &read_file(path)?)
+}
+
+/// reads a file to a string, naming the file in any error
+fn read_file(path: &Path) -> Result<String, YamlInvalid> {
+    fs::read_to_string(path).map_err(|error| YamlInvalid::YamlUnreadable {
+        file: path.display().to_string(),
+        error,
+    })
+}
+
+/// deserializes yaml read from `path`, locating any error at its file line & column
+fn parse_yaml<T: DeserializeOwned>(path: &Path, contents: &str) -> Result<T, YamlInvalid> {
+    serde_yml::from_str(contents).map_err(|e| {
+        let file: String = match e.location() {
+            Some(at) => format!("{}:{}:{}", path.display(), at.line(), at.column()),
+            None => path.display().to_string(),
+        };
+        YamlInvalid::YamlMalformed {
+            file,
+            error: e.to_string(),
+        }
+    })
+}
+
+/// replaces each `${VAR}` in the configuration with the value `lookup` finds for it
+/// - `${VAR:-fallback}` substitutes `fallback` when `VAR` is unset
+/// - errs with the first variable found unset without a default
+fn substitute_env(
+    contents: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, ConfigInvalid> {
+    let pattern: Regex = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)(?::-([^}]*))?\}").unwrap();
+    let mut substituted: String = String::with_capacity(contents.len());
+    let mut last: usize = 0;
+    for caps in pattern.captures_iter(contents) {
+        let (whole, var) = (caps.get(0).unwrap(), &caps[1]);
+        let value: String = match (lookup(var), caps.get(2)) {
+            (Some(value), _) => value,
+            (None, Some(fallback)) => String::from(fallback.as_str()),
+            (None, None) => {
+                return Err(ConfigInvalid::EnvVarUndefined {
+                    var: String::from(var),
+                })
+            }
+        };
+        substituted.push_str(&contents[last..whole.start()]);
+        substituted.push_str(&value);
+        last = whole.end();
+    }
+    substituted.push_str(&contents[last..]);
+    Ok(substituted)
+}
+
+/// compiles `defaults.device_regex` ahead of deserializing the configuration
+/// - a missing or non-string pattern is left for deserialization to report
+fn check_device_regex(contents: &str) -> Result<(), ConfigInvalid> {
+    let yaml: serde_yml::Value = match serde_yml::from_str(contents) {
+        Ok(yaml) => yaml,
+        Err(_) => return Ok(()),
+    };
+    match yaml["defaults"]["device_regex"].as_str() {
+        Some(pattern) => match Regex::new(pattern) {
+            Ok(_) => Ok(()),
+            Err(error) => Err(ConfigInvalid::DeviceRegexInvalid {
+                pattern: String::from(pattern),
+                error,
+            }),
+        },
+        None => Ok(()),
+    }
+}
+
 /// regex lookup for devices against provided pattern
-fn are_names_complaint(devicelist: &Vec<String>, pattern: &Regex, dbg: LogLevel) -> bool {
-    let mut name_valid = true;
-    for device in devicelist {
-        if !pattern.is_match(&device) {
-            crit!(dbg, "* {}: {}", ConfigInvalid::DeviceNamesInvalid, &device);
-            name_valid = false;
+/// - errs with every device name failing to match, or unsafe to use in a path
+fn are_names_complaint(devicelist: &[String], pattern: &Regex) -> Result<(), Vec<String>> {
+    let invalid: Vec<String> = devicelist
+        .iter()
+        .filter(|device| !pattern.is_match(device) || !is_name_safe(device))
+        .cloned()
+        .collect();
+    match invalid.is_empty() {
+        true => Ok(()),
+        false => Err(invalid),
+    }
+}
+
+/// checks a name is usable as a single path component
+/// - rejects empty names, path separators, and `..`
+pub(crate) fn is_name_safe(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['/', '\\', '\0']) && !name.contains("..")
+}
+
+/// suggests the literal prefix of an anchored pattern when a name starts close to it
+/// - close is an edit distance of at most 2 against the name's leading chars
+fn prefix_hint(name: &str, pattern: &Regex) -> Option<String> {
+    let literal: String = pattern
+        .as_str()
+        .strip_prefix('^')?
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    if literal.is_empty() || name.starts_with(&literal) {
+        return None;
+    }
+    let leading: String = name.chars().take(literal.chars().count()).collect();
+    match edit_distance(&leading, &literal) <= 2 {
+        true => Some(literal),
+        false => None,
+    }
+}
+
+/// levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row: Vec<usize> = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost: usize = usize::from(ca != *cb);
+            row.push((prev[j] + cost).min(prev[j + 1] + 1).min(row[j] + 1));
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
+
+/// action lookup for a direction default
+fn is_default_valid(direction: &str, default: &str) -> Result<(), ConfigInvalid> {
+    match Action::from_str(default) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ConfigInvalid::DefaultActionInvalid {
+            direction: String::from(direction),
+            value: String::from(default),
+        }),
+    }
+}
+
+/// checks the ingress & egress defaults, collecting an error per invalid direction
+fn check_defaults(deployment: &Deployment) -> Vec<ConfigInvalid> {
+    [
+        ("ingress", &deployment.ingress),
+        ("egress", &deployment.egress),
+    ]
+    .into_iter()
+    .filter_map(|(name, direction)| is_default_valid(name, &direction.default).err())
+    .collect()
+}
+
+/// checks each interface of a direction with zones belongs to exactly one zone
+/// - returns interfaces missing from every zone, and an error per multi-zone interface
+/// - directions without zones are not checked
+fn check_zones(name: &str, direction: &Direction) -> (Vec<String>, Vec<ConfigInvalid>) {
+    let mut unmapped: Vec<String> = vec![];
+    let mut conflicts: Vec<ConfigInvalid> = vec![];
+    if direction.zones.is_empty() {
+        return (unmapped, conflicts);
+    }
+    for (iface, zones) in direction.interface_zones() {
+        match zones.len() {
+            0 => unmapped.push(String::from(iface)),
+            1 => (),
+            _ => conflicts.push(ConfigInvalid::ZoneConflict {
+                direction: String::from(name),
+                interface: String::from(iface),
+                zones: zones.into_iter().map(String::from).collect(),
+            }),
+        }
+    }
+    (unmapped, conflicts)
+}
+
+/// expands glob patterns, e.g. `site-*`, into the sorted stems of matching ruleset files
+/// - names without `*` or `?` are kept as is, and duplicates keep their first position
+/// - errs with every pattern matching no ruleset file
+fn expand_rulesets(rulesets: &[String], acls_path: &str) -> Result<Vec<String>, Vec<String>> {
+    let mut stems: Vec<String> = match fs::read_dir(acls_path) {
+        Ok(entries) => entries
+            .filter_map(|entry| {
+                let path: PathBuf = entry.ok()?.path();
+                match path.extension()?.to_str()? {
+                    "acl" => Some(path.file_stem()?.to_str()?.to_string()),
+                    _ => None,
+                }
+            })
+            .collect(),
+        Err(_) => vec![],
+    };
+    stems.sort();
+
+    let mut expanded: Vec<String> = vec![];
+    let mut unmatched: Vec<String> = vec![];
+    for ruleset in rulesets {
+        let matches: Vec<String> = match ruleset.contains(['*', '?']) {
+            false => vec![ruleset.clone()],
+            true => {
+                let glob: String = ruleset
+                    .chars()
+                    .map(|c| match c {
+                        '*' => String::from(".*"),
+                        '?' => String::from("."),
+                        c => regex::escape(&c.to_string()),
+                    })
+                    .collect();
+                let pattern: Regex = Regex::new(&format!("^{}$", glob)).unwrap();
+                stems
+                    .iter()
+                    .filter(|s| pattern.is_match(s))
+                    .cloned()
+                    .collect()
+            }
         };
+        if matches.is_empty() {
+            unmatched.push(ruleset.clone());
+        }
+        for name in matches {
+            if !expanded.contains(&name) {
+                expanded.push(name);
+            }
+        }
+    }
+
+    match unmatched.is_empty() {
+        true => Ok(expanded),
+        false => Err(unmatched),
     }
-    name_valid
 }
 
 /// pathbuf exists check for all rulesets
@@ -145,31 +787,387 @@ mod regex_serde {
     }
 }
 
+mod ports_serde {
+    use crate::ruleset::PortType;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use std::str::FromStr;
+
+    /// a port list entry, written as a bare number or a port string
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Ports {
+        Num(u16),
+        Str(String),
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<PortType>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let entries: Vec<Ports> = Deserialize::deserialize(deserializer)?;
+        entries
+            .into_iter()
+            .map(|entry| {
+                let s: String = match entry {
+                    Ports::Num(n) => n.to_string(),
+                    Ports::Str(s) => s,
+                };
+                PortType::from_str(&s)
+                    .map_err(|e| de::Error::custom(format!("Invalid port '{}': {}", s, e)))
+            })
+            .collect()
+    }
+
+    pub fn serialize<S>(ports: &[PortType], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(ports.iter().map(|p| p.to_string()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn device_has_valid_name() {
-        let dbg = LogLevel::Debug;
         let devicelist: Vec<String> = vec![String::from("rsk101-example-fw1")];
         let pattern: Regex = Regex::new(
-            "^[a-z]{1,3}([0-9]{1, 10}-){1,2}([a-z]{2, 9}-){1,4}[a-z]{1,5}[1-9]([0-9]{0, 9})?",
+            "^[a-z]{1,3}([0-9]{1,10}-){1,2}([a-z]{2,9}-){1,4}[a-z]{1,5}[1-9]([0-9]{0,9})?",
         )
         .unwrap();
 
-        assert!(are_names_complaint(&devicelist, &pattern, dbg));
+        assert!(are_names_complaint(&devicelist, &pattern).is_ok());
     }
 
     #[test]
     fn device_has_invalid_name() {
-        let dbg: LogLevel = LogLevel::Debug;
         let devicelist: Vec<String> = vec![String::from("firewall1")];
         let pattern: Regex = Regex::new(
-            "^[a-z]{1,3}([0-9]{1, 10}-){1,2}([a-z]{2, 9}-){1,4}[a-z]{1,5}[1-9]([0-9]{0, 9})?",
+            "^[a-z]{1,3}([0-9]{1,10}-){1,2}([a-z]{2,9}-){1,4}[a-z]{1,5}[1-9]([0-9]{0,9})?",
+        )
+        .unwrap();
+
+        assert!(are_names_complaint(&devicelist, &pattern).is_err());
+    }
+
+    #[test]
+    fn invalid_names_returned_exactly() {
+        let devicelist: Vec<String> = [
+            "rsk101-ext-fw1",
+            "firewall1",
+            "rsk102-ext-fw2",
+            "RSK103-EXT-FW3",
+        ]
+        .iter()
+        .map(|d| d.to_string())
+        .collect();
+        let pattern: Regex = Regex::new("^rsk[0-9]{3}-[a-z]+-fw[0-9]$").unwrap();
+
+        assert_eq!(
+            are_names_complaint(&devicelist, &pattern).unwrap_err(),
+            vec![String::from("firewall1"), String::from("RSK103-EXT-FW3")]
+        );
+    }
+
+    #[test]
+    fn unsafe_names_rejected() {
+        assert!(is_name_safe("site-core"));
+        assert!(is_name_safe("site-*"));
+        assert!(!is_name_safe("../etc/passwd"));
+        assert!(!is_name_safe("sub/dir"));
+        assert!(!is_name_safe("/etc/passwd"));
+        assert!(!is_name_safe(""));
+
+        let pattern: Regex = Regex::new("^rsk").unwrap();
+        let devicelist: Vec<String> = vec![String::from("rsk1"), String::from("rsk/../fw1")];
+        assert_eq!(
+            are_names_complaint(&devicelist, &pattern).unwrap_err(),
+            vec![String::from("rsk/../fw1")]
+        );
+    }
+
+    #[test]
+    fn prefix_hint_suggests_close_literal() {
+        let pattern: Regex = Regex::new("^rsk[0-9]{3}-[a-z]+-fw[0-9]$").unwrap();
+
+        assert_eq!(
+            prefix_hint("rks101-ext-fw1", &pattern),
+            Some(String::from("rsk"))
+        );
+        assert_eq!(prefix_hint("rsk101-ext", &pattern), None);
+        assert_eq!(prefix_hint("firewall1", &pattern), None);
+    }
+
+    #[test]
+    fn device_regex_invalid_names_pattern() {
+        let contents: &str = "defaults:\n  device_regex: '^[a-z{1,3}'\n";
+        let e = check_device_regex(contents).unwrap_err();
+
+        assert!(matches!(e, ConfigInvalid::DeviceRegexInvalid { .. }));
+        assert!(e.to_string().starts_with(
+            "DeviceRegexInvalid: failed to compile `defaults.device_regex` pattern '^[a-z{1,3}'\n"
+        ));
+    }
+
+    #[test]
+    fn ruleset_globs_expand_sorted_and_deduplicated() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["site-b.acl", "site-a.acl", "core.acl", "site-c.txt"] {
+            fs::write(dir.path().join(file), "").unwrap();
+        }
+        let acls_path: &str = dir.path().to_str().unwrap();
+        let rulesets =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|n| n.to_string()).collect() };
+
+        assert_eq!(
+            expand_rulesets(&rulesets(&["core", "site-*", "site-a"]), acls_path).unwrap(),
+            rulesets(&["core", "site-a", "site-b"])
+        );
+        assert_eq!(
+            expand_rulesets(&rulesets(&["site-?", "edge-*"]), acls_path).unwrap_err(),
+            rulesets(&["edge-*"])
+        );
+    }
+
+    #[test]
+    fn zones_assign_and_flag_unmapped_interfaces() {
+        let direction: Direction = serde_yml::from_str(
+            "interfaces: [ae10, ae11, ae20, ae30]
+filters: { src: [any], dst: [any] }
+deployable: true
+established: false
+default: deny
+transforms: { src: false, dst: false }
+zones:
+  trust: [ae10, ae11]
+  untrust: [ae20, ae11]
+",
         )
         .unwrap();
+        let (unmapped, conflicts) = check_zones("ingress", &direction);
+
+        assert_eq!(unmapped, vec![String::from("ae30")]);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(
+            conflicts[0].to_string(),
+            "ZoneConflict: expected ingress interface ae11 in exactly one zone, found: trust, untrust"
+        );
+        assert_eq!(
+            direction.resolved_zones(),
+            BTreeMap::from([
+                ("trust", vec!["ae10", "ae11"]),
+                ("untrust", vec!["ae11", "ae20"])
+            ])
+        );
+    }
+
+    #[test]
+    fn transform_accepts_toggle_or_mapping() {
+        let toggle: Transform = serde_yml::from_str("true").unwrap();
+        assert!(toggle.mapping().is_none());
+
+        let mapping: Transform = serde_yml::from_str("{ 10.0.0.0/24: 192.168.0.0/24 }").unwrap();
+        assert_eq!(
+            mapping.mapping().unwrap().get("10.0.0.0/24").unwrap(),
+            "192.168.0.0/24"
+        );
+    }
+
+    #[test]
+    fn default_is_valid_action() {
+        assert!(is_default_valid("ingress", "deny").is_ok());
+    }
+
+    #[test]
+    fn default_is_invalid_action() {
+        assert!(is_default_valid("ingress", "denny").is_err());
+    }
+
+    #[test]
+    fn model_from_another_make_is_mismatched() {
+        let contents: String = fs::read_to_string("site/example.yaml").unwrap().replacen(
+            "model: srx1500",
+            "model: 7050sx3-48yc8",
+            1,
+        );
+        let cfg: Configuration = serde_yml::from_str(&contents).unwrap();
+        let errors: Vec<ConfigInvalid> = cfg.check_platforms(&PlatformCatalog::new("./platform"));
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "PlatformModelMismatch: expected a juniper model, found 7050sx3-48yc8 listed under arista"
+        );
+
+        let cfg: Configuration =
+            serde_yml::from_str(&fs::read_to_string("site/example.yaml").unwrap()).unwrap();
+        assert!(cfg
+            .check_platforms(&PlatformCatalog::new("./platform"))
+            .is_empty());
+    }
+
+    #[test]
+    fn malformed_config_names_file_and_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("site.yaml");
+        fs::write(&file, "deployment:\n  rulesets: [web\n  platform: {").unwrap();
+        let path: &str = file.to_str().unwrap();
+
+        let e = Configuration::load(path, "./acls", LogLevel::None).unwrap_err();
+        assert!(e
+            .to_string()
+            .starts_with(&format!("YamlMalformed: {}:3:", path)));
+
+        let missing: String = format!("{}/missing.yaml", dir.path().display());
+        let e = Configuration::load(&missing, "./acls", LogLevel::None).unwrap_err();
+        assert!(e
+            .to_string()
+            .starts_with(&format!("YamlUnreadable: failed to read {}\n", missing)));
+    }
+
+    #[test]
+    fn env_vars_substitute_into_config() {
+        let lookup = |var: &str| (var == "SITE").then(|| String::from("rsk101"));
+
+        assert_eq!(
+            substitute_env("device_regex: ^${SITE}-core$\n", lookup).unwrap(),
+            "device_regex: ^rsk101-core$\n"
+        );
+        assert_eq!(
+            substitute_env("rulesets: [${RULESET:-web}, ${SITE:-lab}]", lookup).unwrap(),
+            "rulesets: [web, rsk101]"
+        );
+        assert_eq!(
+            substitute_env("rulesets: [${RULESET}]", lookup)
+                .unwrap_err()
+                .to_string(),
+            "EnvVarUndefined: expected ${RULESET} to be set, or given a default as ${RULESET:-default}"
+        );
+        assert_eq!(
+            substitute_env("cost: $5 {}", lookup).unwrap(),
+            "cost: $5 {}"
+        );
+    }
+
+    #[test]
+    fn json_schema_describes_every_configuration_field() {
+        // lists properties `schema` describes that are missing from the fields of `value`,
+        // and fields of `value` missing from the described properties
+        fn mismatched(
+            value: &serde_json::Value,
+            schema: &serde_json::Value,
+            at: &str,
+        ) -> Vec<String> {
+            match (value, &schema["properties"], &schema["items"]) {
+                (serde_json::Value::Object(fields), serde_json::Value::Object(properties), _) => {
+                    let unserialized = properties
+                        .keys()
+                        .filter(|key| !fields.contains_key(*key))
+                        .map(|key| format!("{}.{} unserialized", at, key));
+                    let described =
+                        fields
+                            .iter()
+                            .flat_map(|(key, field)| match properties.get(key) {
+                                Some(schema) => {
+                                    mismatched(field, schema, &format!("{}.{}", at, key))
+                                }
+                                None => vec![format!("{}.{} undescribed", at, key)],
+                            });
+                    unserialized.chain(described).collect()
+                }
+                (serde_json::Value::Array(items), _, schema) if schema.is_object() => items
+                    .iter()
+                    .flat_map(|item| mismatched(item, schema, &format!("{}[]", at)))
+                    .collect(),
+                _ => vec![],
+            }
+        }
+        let strings = |s: &[&str]| -> Vec<String> { s.iter().map(|s| s.to_string()).collect() };
+        let platform = || Platform {
+            make: String::from("juniper"),
+            model: String::from("srx1500"),
+        };
+        // every field is listed, and every optional field set, so each serializes
+        let direction = || Direction {
+            interfaces: strings(&["ae101"]),
+            filters: Filters {
+                src: strings(&["outside"]),
+                dst: strings(&["inside"]),
+            },
+            deployable: true,
+            established: true,
+            default: String::from("deny"),
+            transforms: Transforms {
+                src: Transform::Toggle(false),
+                dst: Transform::Mapping(PrefixMap::from([(
+                    String::from("inside"),
+                    String::from("10.0.0.0/8"),
+                )])),
+            },
+            zones: BTreeMap::from([(String::from("dmz"), strings(&["ae101"]))]),
+        };
+        let cfg = Configuration {
+            deployment: Deployment {
+                rulesets: strings(&["core"]),
+                families: BTreeMap::from([(String::from("core"), Family::Inet)]),
+                platform: Some(platform()),
+                platforms: vec![platform()],
+                devicelist: strings(&["rsk101-ext-fw1"]),
+                ingress: direction(),
+                egress: direction(),
+            },
+            defaults: Defaults {
+                device_regex: Regex::new("^rsk").unwrap(),
+                syntax: Syntax {
+                    comment_prefix: String::from(";"),
+                    case_sensitive: true,
+                },
+                unexpected_ports: vec![PortType::from_str("49152-65535").unwrap()],
+            },
+            objects: ObjectMap::from([(String::from("web"), strings(&["10.1.0.0/24"]))]),
+            rulesets: BTreeMap::from([(
+                String::from("core"),
+                strings(&["allow tcp outside any inside 22"]),
+            )]),
+        };
+        let value: serde_json::Value = serde_json::to_value(&cfg).unwrap();
+        let schema: serde_json::Value = json_schema();
+
+        assert_eq!(mismatched(&value, &schema, "site"), Vec::<String>::new());
+        assert_eq!(
+            schema["required"],
+            serde_json::json!(["deployment", "defaults"])
+        );
+    }
+
+    #[test]
+    fn crlf_config_parses_like_lf() {
+        let lf: String = fs::read_to_string("site/example.yaml").unwrap();
+        let crlf: String = lf.replace('\n', "\r\n");
+        let parse = |contents: &str| {
+            let cfg: Configuration = serde_yml::from_str(contents).unwrap();
+            serde_yml::to_string(&cfg).unwrap()
+        };
+
+        assert_eq!(parse(&crlf), parse(&lf));
+    }
+
+    #[test]
+    fn bad_ingress_default_names_direction() {
+        let contents: String = fs::read_to_string("site/example.yaml").unwrap().replacen(
+            "default: deny",
+            "default: denny",
+            1,
+        );
+        let cfg: Configuration = serde_yml::from_str(&contents).unwrap();
+        let errors: Vec<ConfigInvalid> = check_defaults(&cfg.deployment);
 
-        assert_eq!(are_names_complaint(&devicelist, &pattern, dbg), false);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "DefaultActionInvalid: expected 'allow', 'deny', 'allowlog', or 'denylog' for ingress.default: denny"
+        );
     }
 }
@@ -0,0 +1,186 @@
+use crate::{
+    config::{Configuration, Direction},
+    device::Device,
+    ruleset::Ruleset,
+};
+use serde_json::{to_value as contextualize, Map, Value};
+
+/// output layout options, inserted as `indent` & `line_width` globals when set
+/// - `indent` is inserted as a string of spaces, so templates may concatenate levels
+/// - templates fall back to their own layout for unset options
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Layout {
+    pub indent: Option<usize>,
+    pub line_width: Option<usize>,
+}
+
+impl Layout {
+    /// inserts the set options into a packed context
+    pub fn insert(&self, context: &mut tera::Context) {
+        if let Some(spaces) = self.indent {
+            context.insert("indent", &" ".repeat(spaces));
+        }
+        if let Some(width) = self.line_width {
+            context.insert("line_width", &width);
+        }
+    }
+}
+
+/// packs validated inputs into the tera context used for rendering
+/// - `rulesets`, `stats`, `devices`, `directions`, and `config` are inserted as top level keys
+/// - `stats` holds the `RulesetStats` of each ruleset, in `rulesets` order
+/// - `directions.<direction>.rulesets` holds each ruleset with `transforms` applied,
+///   `established` return rules, and closed with the direction `default`
+/// - `directions.<direction>.zones` maps each zone to its declared interfaces
+/// - maps are ordered, so the same inputs always render byte-identical output
+/// - a direction with `deployable: false` is emptied before packing, such that
+///   `config.deployment.<direction>.interfaces`, `devices[].paths.<direction>`,
+///   and `directions.<direction>.rulesets` are `[]`
+/// - `rulesets` & `stats` are `[]` when neither direction is deployable
+pub fn pack(
+    cfg: &Configuration,
+    devices: &[Device],
+    rulesets: &[Ruleset],
+) -> Result<tera::Context, serde_json::Error> {
+    let mut cfg: Configuration = cfg.clone();
+    let mut devices: Vec<Device> = devices.to_vec();
+
+    if !cfg.deployment.ingress.deployable {
+        cfg.deployment.ingress.interfaces.clear();
+        devices.iter_mut().for_each(|d| d.paths.ingress.clear());
+    }
+    if !cfg.deployment.egress.deployable {
+        cfg.deployment.egress.interfaces.clear();
+        devices.iter_mut().for_each(|d| d.paths.egress.clear());
+    }
+
+    let mut directions: Map<String, Value> = Map::new();
+    for (name, direction) in [
+        ("ingress", &cfg.deployment.ingress),
+        ("egress", &cfg.deployment.egress),
+    ] {
+        let mut packed: Map<String, Value> = Map::new();
+        packed.insert(
+            String::from("rulesets"),
+            contextualize(close_rulesets(direction, rulesets))?,
+        );
+        packed.insert(
+            String::from("zones"),
+            contextualize(direction.resolved_zones())?,
+        );
+        directions.insert(String::from(name), Value::Object(packed));
+    }
+
+    let rulesets: &[Ruleset] =
+        match cfg.deployment.ingress.deployable || cfg.deployment.egress.deployable {
+            true => rulesets,
+            false => &[],
+        };
+    let mut context = tera::Context::new();
+    context.insert("rulesets", &contextualize(rulesets)?);
+    context.insert(
+        "stats",
+        &contextualize(rulesets.iter().map(Ruleset::stats).collect::<Vec<_>>())?,
+    );
+    context.insert("directions", &directions);
+    context.insert("devices", &contextualize(&devices)?);
+    context.insert("config", &contextualize(&cfg)?);
+    Ok(context)
+}
+
+/// closes each ruleset for a direction, see `close_ruleset`
+/// - returns nothing when the direction is not deployable
+fn close_rulesets(direction: &Direction, rulesets: &[Ruleset]) -> Vec<Ruleset> {
+    if !direction.deployable {
+        return vec![];
+    }
+    rulesets
+        .iter()
+        .map(|ruleset| close_ruleset(direction, ruleset))
+        .collect()
+}
+
+/// rewrites transformed prefixes, then appends established return rules
+/// and the direction default, as the ruleset is deployed to the direction
+pub(crate) fn close_ruleset(direction: &Direction, ruleset: &Ruleset) -> Ruleset {
+    let rs: Ruleset = ruleset.clone().transform(
+        direction.transforms.src.mapping(),
+        direction.transforms.dst.mapping(),
+    );
+    let rs: Ruleset = match direction.established {
+        true => rs.with_established(),
+        false => rs,
+    };
+    match direction.default_action() {
+        Some(action) => rs.with_default(action),
+        None => rs,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LogLevel;
+
+    #[test]
+    fn non_deployable_egress_omits_interfaces() {
+        let dbg = LogLevel::Debug;
+        let mut cfg = Configuration::load("site/example.yaml", "./acls", dbg)
+            .unwrap()
+            .unwrap();
+        cfg.deployment.egress.deployable = false;
+        let device = Device::build(
+            "test-device",
+            "juniper",
+            "srx1500",
+            &cfg.deployment.ingress.interfaces,
+            &cfg.deployment.egress.interfaces,
+            &crate::device::PlatformCatalog::new("./platform"),
+            dbg,
+        )
+        .unwrap();
+
+        let context = pack(&cfg, &[device], &[]).unwrap().into_json();
+
+        assert_eq!(
+            context["devices"][0]["paths"]["egress"],
+            serde_json::json!([])
+        );
+        assert_eq!(
+            context["config"]["deployment"]["egress"]["interfaces"],
+            serde_json::json!([])
+        );
+        assert_eq!(
+            context["directions"]["egress"]["rulesets"],
+            serde_json::json!([])
+        );
+        assert_eq!(
+            context["devices"][0]["paths"]["ingress"],
+            serde_json::json!(["ae101", "ae102"])
+        );
+    }
+
+    #[test]
+    fn non_deployable_directions_omit_rulesets() {
+        let dbg = LogLevel::Debug;
+        let mut cfg = Configuration::load("site/example.yaml", "./acls", dbg)
+            .unwrap()
+            .unwrap();
+        let rulesets: Vec<Ruleset> =
+            vec![Ruleset::from_vec(&[String::from("allow tcp outside any inside 443")]).unwrap()];
+
+        cfg.deployment.egress.deployable = false;
+        let context = pack(&cfg, &[], &rulesets).unwrap().into_json();
+        assert_eq!(context["rulesets"].as_array().unwrap().len(), 1);
+        assert_eq!(context["stats"].as_array().unwrap().len(), 1);
+
+        cfg.deployment.ingress.deployable = false;
+        let context = pack(&cfg, &[], &rulesets).unwrap().into_json();
+        assert_eq!(context["rulesets"], serde_json::json!([]));
+        assert_eq!(context["stats"], serde_json::json!([]));
+        assert_eq!(
+            context["directions"]["ingress"]["rulesets"],
+            serde_json::json!([])
+        );
+    }
+}
@@ -1,27 +1,127 @@
-use crate::{crit, dbug, verb, LogLevel};
+use crate::{
+    config::{read_yaml, YamlInvalid},
+    crit, dbug, verb, LogLevel,
+};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::{fmt, fs, path::PathBuf};
+use std::{
+    cell::{Cell, OnceCell, RefCell},
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 use thiserror::Error;
 
 #[derive(Debug, Deserialize)]
 pub struct SupportedPlatform {
     pub make: String,
+    /// base interface patterns shared by every model of the make
+    #[serde(default, with = "regex_serde")]
+    pub interfaces: Vec<Regex>,
     pub models: Vec<Models>,
+    /// onboarding notes such as ownership or EOL, copied onto each device built for the make
+    #[serde(default)]
+    pub metadata: Option<serde_yml::Value>,
+    /// unknown top level keys, kept rather than rejected
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_yml::Value>,
 }
 
 impl SupportedPlatform {
-    pub fn from_file(file_path: &PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
-        Ok(serde_yml::from_str(&fs::read_to_string(PathBuf::from(
-            file_path,
-        ))?)?)
+    /// loads a supported platform yaml
+    /// - each model's interfaces are extended with the base `interfaces`
+    /// - yaml that fails to parse errs as `PlatformFileMalformed`, naming the file, line & column
+    pub fn from_file(file_path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let platform: SupportedPlatform = read_platform_yaml(file_path)?;
+        Ok(platform.with_base_interfaces())
+    }
+
+    /// extends each model's interfaces with the base `interfaces`
+    fn with_base_interfaces(mut self) -> Self {
+        for model in self.models.iter_mut() {
+            model.interfaces.extend(self.interfaces.iter().cloned());
+        }
+        self
+    }
+
+    pub fn lookup_model(&self, model_name: &str) -> Option<&Models> {
+        self.models.iter().find(|model| model.name == model_name)
     }
 
     pub fn lookup_model_regex(&self, model_name: &str) -> Option<&Vec<Regex>> {
-        self.models
-            .iter()
-            .find(|model| model.name == model_name)
-            .map(|model| &model.interfaces)
+        self.lookup_model(model_name).map(|model| &model.interfaces)
+    }
+}
+
+/// reads a platform file, naming its line & column as `PlatformFileMalformed` on parse errors
+fn read_platform_yaml<T: serde::de::DeserializeOwned>(
+    file_path: &Path,
+) -> Result<T, Box<dyn std::error::Error>> {
+    read_yaml(file_path).map_err(|e| match e {
+        YamlInvalid::YamlMalformed { file, error } => {
+            Box::new(PlatformUnsupported::PlatformFileMalformed { file, error })
+        }
+        e => Box::<dyn std::error::Error>::from(e),
+    })
+}
+
+/// the platforms of a file, either one make or several listed under `platforms`
+#[derive(Debug)]
+enum PlatformFile {
+    One(Rc<SupportedPlatform>),
+    Many(Vec<Rc<SupportedPlatform>>),
+}
+
+/// a platform file covering several makes, e.g. a vendor family
+#[derive(Debug, Deserialize)]
+struct PlatformList {
+    platforms: Vec<SupportedPlatform>,
+}
+
+/// the yaml of a platform file, a list when it has a top level `platforms` key, else one make
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum PlatformYaml {
+    Many(PlatformList),
+    One(SupportedPlatform),
+}
+
+impl PlatformFile {
+    /// loads a platform file, parsing it once as either a list of makes or a single make
+    /// - each platform's models are extended with its own base `interfaces`
+    fn from_file(file_path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let platform = |platform: SupportedPlatform| Rc::new(platform.with_base_interfaces());
+        match read_platform_yaml(file_path) {
+            Ok(PlatformYaml::Many(list)) => Ok(PlatformFile::Many(
+                list.platforms.into_iter().map(platform).collect(),
+            )),
+            Ok(PlatformYaml::One(one)) => Ok(PlatformFile::One(platform(one))),
+            Err(e) => Err(Self::locate_error(file_path).unwrap_or(e)),
+        }
+    }
+
+    /// re-reads a file failing to parse as the form it takes, as untagged errors name neither
+    /// the offending field nor its line & column
+    /// - a file failing to parse as yaml at all is read as a single platform
+    fn locate_error(file_path: &Path) -> Option<Box<dyn std::error::Error>> {
+        let listed: bool = read_platform_yaml::<serde_yml::Value>(file_path)
+            .is_ok_and(|yaml| yaml.get("platforms").is_some());
+        match listed {
+            true => read_platform_yaml::<PlatformList>(file_path).err(),
+            false => read_platform_yaml::<SupportedPlatform>(file_path).err(),
+        }
+    }
+
+    /// the platform for a make, where a single platform file answers for its file stem
+    fn platform(&self, make: &str) -> Option<Rc<SupportedPlatform>> {
+        match self {
+            PlatformFile::One(platform) => Some(Rc::clone(platform)),
+            PlatformFile::Many(platforms) => platforms
+                .iter()
+                .find(|platform| platform.make == make)
+                .map(Rc::clone),
+        }
     }
 }
 
@@ -31,11 +131,173 @@ impl fmt::Display for SupportedPlatform {
     }
 }
 
+/// supported platform files discovered in the platforms directory
+/// - the directory is scanned on first lookup only, and each file is parsed at most once
+/// - a make is found by file stem first, then among the makes listed by `platforms` files,
+///   which are indexed only once a make is missing by stem
+#[derive(Debug)]
+pub struct PlatformCatalog {
+    dir: PathBuf,
+    files: OnceCell<BTreeMap<String, PathBuf>>,
+    makes: OnceCell<BTreeMap<String, PathBuf>>,
+    parsed: RefCell<BTreeMap<PathBuf, Rc<PlatformFile>>>,
+    scans: Cell<usize>,
+    parses: Cell<usize>,
+}
+
+impl PlatformCatalog {
+    pub fn new(platforms_path: &str) -> Self {
+        PlatformCatalog {
+            dir: PathBuf::from(platforms_path),
+            files: OnceCell::new(),
+            makes: OnceCell::new(),
+            parsed: RefCell::new(BTreeMap::new()),
+            scans: Cell::new(0),
+            parses: Cell::new(0),
+        }
+    }
+
+    /// builds a catalog from `AM3K_PLATFORMS_PATH`
+    pub fn from_env() -> Self {
+        Self::new(&crate::cli::parse_env().platforms)
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    /// maps each make to its platform file by file stem, scanning the directory once
+    fn files(&self) -> Result<&BTreeMap<String, PathBuf>, Box<dyn std::error::Error>> {
+        if let Some(files) = self.files.get() {
+            return Ok(files);
+        }
+
+        self.scans.set(self.scans.get() + 1);
+        let files: BTreeMap<String, PathBuf> = contains_yaml_files(&self.dir)?
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|file| {
+                let path = PathBuf::from(file);
+                let stem = path.file_stem()?.to_str()?.to_owned();
+                Some((stem, path))
+            })
+            .collect();
+        Ok(self.files.get_or_init(|| files))
+    }
+
+    /// maps every make to its platform file, parsing each file once
+    /// - single platform files, and files failing to parse, are indexed by file stem
+    /// - `platforms` files are indexed by each listed make
+    fn index(&self) -> Result<&BTreeMap<String, PathBuf>, Box<dyn std::error::Error>> {
+        if let Some(makes) = self.makes.get() {
+            return Ok(makes);
+        }
+
+        let mut makes: BTreeMap<String, PathBuf> = BTreeMap::new();
+        for (stem, file) in self.files()? {
+            match self.parse(file).as_deref() {
+                Ok(PlatformFile::Many(platforms)) => {
+                    for platform in platforms {
+                        makes.insert(platform.make.clone(), file.to_owned());
+                    }
+                }
+                _ => {
+                    makes.insert(stem.clone(), file.to_owned());
+                }
+            }
+        }
+        Ok(self.makes.get_or_init(|| makes))
+    }
+
+    /// lists every make with a platform file, in sorted order
+    pub fn makes(&self) -> Vec<String> {
+        self.index()
+            .map(|makes| makes.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// lists each make with its models and their interface pattern counts, one make per block
+    /// - files failing to load are logged and left out, a missing directory lists nothing
+    pub fn list(&self, dbg: LogLevel) -> String {
+        let mut listing: String = String::new();
+        for make in self.makes() {
+            let platform: Rc<SupportedPlatform> = match self.load(&make) {
+                Ok(platform) => platform,
+                Err(e) => {
+                    crit!(dbg, "* {}: {}", make, e);
+                    continue;
+                }
+            };
+            listing.push_str(&format!("{}\n", make));
+            for model in &platform.models {
+                listing.push_str(&format!(
+                    "  {}\t{} interface patterns\n",
+                    model.name,
+                    model.interfaces.len()
+                ));
+            }
+        }
+        listing
+    }
+
+    /// returns the platform file for a make
+    pub fn file(&self, make: &str) -> Result<&PathBuf, Box<dyn std::error::Error>> {
+        if let Some(file) = self.files()?.get(make) {
+            return Ok(file);
+        }
+        match self.index()?.get(make) {
+            Some(file) => Ok(file),
+            None => Err(Box::new(PlatformUnsupported::MakeNotSupported)),
+        }
+    }
+
+    /// loads the supported platform for a make, reusing previously parsed files
+    pub fn load(&self, make: &str) -> Result<Rc<SupportedPlatform>, Box<dyn std::error::Error>> {
+        let file: PathBuf = self.file(make)?.to_owned();
+        match self.parse(&file)?.platform(make) {
+            Some(platform) => Ok(platform),
+            None => Err(Box::new(PlatformUnsupported::MakeNotSupported)),
+        }
+    }
+
+    /// parses a platform file, reusing it once parsed
+    fn parse(&self, file: &Path) -> Result<Rc<PlatformFile>, Box<dyn std::error::Error>> {
+        if let Some(parsed) = self.parsed.borrow().get(file) {
+            return Ok(Rc::clone(parsed));
+        }
+
+        let parsed = Rc::new(PlatformFile::from_file(file)?);
+        self.parses.set(self.parses.get() + 1);
+        self.parsed
+            .borrow_mut()
+            .insert(file.to_owned(), Rc::clone(&parsed));
+        Ok(parsed)
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Models {
     pub name: String,
-    #[serde(with = "regex_serde")]
+    #[serde(default, with = "regex_serde")]
     pub interfaces: Vec<Regex>,
+    /// most interfaces a single direction may declare on the model
+    #[serde(default)]
+    pub max_interfaces: Option<usize>,
+    /// most entries a single expanded ruleset may hold in the model's hardware acls
+    #[serde(default)]
+    pub max_acl_entries: Option<usize>,
+}
+
+impl Models {
+    /// lists interface patterns missing a leading `^` or trailing `$`, which match interfaces
+    /// merely containing them, e.g. `xe` matches `prefix-xe-suffix`
+    pub fn unanchored_interfaces(&self) -> Vec<&str> {
+        self.interfaces
+            .iter()
+            .map(Regex::as_str)
+            .filter(|pattern| !(pattern.starts_with('^') && pattern.ends_with('$')))
+            .collect()
+    }
 }
 
 impl fmt::Display for Models {
@@ -50,14 +312,26 @@ pub enum PlatformUnsupported {
     MakeNotSupported,
     #[error("ModelNotSupported: see `Device Onboarding` for more information")]
     ModelNotSupported,
+    #[error("PlatformFileMalformed: platform file {file} is malformed\n{error}")]
+    PlatformFileMalformed { file: String, error: String },
+    #[error("PlatformsDirMissing: platforms directory {path} not found; set AM3K_PLATFORMS_PATH")]
+    PlatformsDirMissing { path: String },
+    #[error("PlatformsDirUnreadable: platforms directory {path} could not be read; check AM3K_PLATFORMS_PATH\n{error}")]
+    PlatformsDirUnreadable { path: String, error: std::io::Error },
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Device {
     pub name: String,
     pub make: String,
     pub model: String,
     pub paths: Paths,
+    /// `metadata` of the supported platform file, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_yml::Value>,
+    /// `max_acl_entries` of the supported model, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_acl_entries: Option<usize>,
 }
 
 impl Device {
@@ -67,15 +341,15 @@ impl Device {
         model: &str,
         ingress: &Vec<String>,
         egress: &Vec<String>,
-        platforms_path: &str,
+        catalog: &PlatformCatalog,
         dbg: LogLevel,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        verb!(dbg, "  Loading path to supported platforms...");
-        let dir = PathBuf::from(platforms_path);
-        verb!(dbg, "  Found path: {}", &dir.display());
+        let dir: &PathBuf = catalog.dir();
 
         verb!(dbg, "\n  Searching for matching supported platform file...");
-        let file = match get_supported_platform_file(&dir, &make) {
+        // a missing platforms directory is reported as such, rather than as an unsupported make
+        catalog.files()?;
+        let file: &PathBuf = match catalog.file(make) {
             Ok(file) => file,
             Err(e) => {
                 crit!(
@@ -90,12 +364,15 @@ impl Device {
         verb!(dbg, "  Found {}", &file.display());
 
         verb!(dbg, "\n  Loading supported platforms file...");
-        let platform_cfg: SupportedPlatform = SupportedPlatform::from_file(&file)?;
+        let platform_cfg: Rc<SupportedPlatform> = catalog.load(make)?;
         verb!(dbg, "  Platforms file loaded successfully from yaml.");
+        if let Some(metadata) = &platform_cfg.metadata {
+            dbug!(dbg, "  Platform metadata: {:?}", metadata);
+        }
 
         verb!(dbg, "\n  Checking supported model...");
-        let patterns = match platform_cfg.lookup_model_regex(model) {
-            Some(regex) => regex,
+        let supported: &Models = match platform_cfg.lookup_model(model) {
+            Some(supported) => supported,
             None => {
                 crit!(
                     dbg,
@@ -108,11 +385,30 @@ impl Device {
         };
         verb!(dbg, "  Model supported.");
 
+        if let Some(max) = supported.max_interfaces {
+            verb!(dbg, "\n  Checking interface counts within model limit...");
+            for (direction, interfaces) in [("ingress", ingress), ("egress", egress)] {
+                if interfaces.len() > max {
+                    let e = InterfaceErrors::TooManyInterfaces {
+                        direction: String::from(direction),
+                        count: interfaces.len(),
+                        model: model.to_owned(),
+                        max,
+                    };
+                    crit!(dbg, "  {}", e);
+                    return Err(Box::new(e));
+                }
+            }
+            verb!(dbg, "  Interface counts are within limit.");
+        }
+
         Ok(Device {
             name: name.to_owned(),
             make: make.to_owned(),
             model: model.to_owned(),
-            paths: Paths::build(ingress, egress, patterns, dbg)?,
+            paths: Paths::build(ingress, egress, &supported.interfaces, dbg)?,
+            metadata: platform_cfg.metadata.clone(),
+            max_acl_entries: supported.max_acl_entries,
         })
     }
 }
@@ -127,7 +423,7 @@ impl fmt::Display for Device {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Paths {
     pub ingress: Vec<String>,
     pub egress: Vec<String>,
@@ -143,7 +439,7 @@ impl Paths {
         verb!(dbg, "\n  Confirming interfaces are valid...");
         dbug!(dbg, "{:#?}", patterns);
         let mut invalid_ifaces_detected: bool = false;
-        if let Some(ifaces) = Self::list_invalid_ifaces(ingress, &patterns) {
+        if let Some(ifaces) = Self::list_invalid_ifaces(ingress, patterns) {
             crit!(
                 dbg,
                 "  Ingress{}: {:?}",
@@ -152,7 +448,7 @@ impl Paths {
             );
             invalid_ifaces_detected = true;
         }
-        if let Some(ifaces) = Self::list_invalid_ifaces(egress, &patterns) {
+        if let Some(ifaces) = Self::list_invalid_ifaces(egress, patterns) {
             crit!(
                 dbg,
                 "  Egress{}: {:?}",
@@ -173,7 +469,7 @@ impl Paths {
     }
 
     /// return list of interfaces that dont match provided regexes
-    fn list_invalid_ifaces(interfaces: &Vec<String>, patterns: &Vec<Regex>) -> Option<Vec<String>> {
+    fn list_invalid_ifaces(interfaces: &[String], patterns: &[Regex]) -> Option<Vec<String>> {
         let errors: Vec<String> = interfaces
             .iter()
             .filter_map(|iface| {
@@ -185,14 +481,14 @@ impl Paths {
             })
             .collect();
 
-        match errors.len() > 0 {
-            true => Some(errors),
-            false => None,
+        match errors.is_empty() {
+            true => None,
+            false => Some(errors),
         }
     }
 
     /// shorthand check single interface against provided regexes
-    fn is_valid_iface(iface: &str, patterns: &Vec<Regex>) -> bool {
+    fn is_valid_iface(iface: &str, patterns: &[Regex]) -> bool {
         for exp in patterns {
             if exp.is_match(iface) {
                 return true;
@@ -208,27 +504,20 @@ impl fmt::Display for Paths {
     }
 }
 
-fn get_supported_platform_file(
-    path: &PathBuf,
-    make: &str,
-) -> Result<PathBuf, Box<dyn std::error::Error>> {
-    match contains_yaml_files(path)? {
-        Some(file_list) => {
-            for file in file_list {
-                if file.contains(make) {
-                    return Ok(PathBuf::from(file));
-                }
-            }
-        }
-        None => (),
-    }
-    Err(Box::new(PlatformUnsupported::MakeNotSupported))
-}
-
 fn contains_yaml_files(path: &PathBuf) -> Result<Option<Vec<String>>, Box<dyn std::error::Error>> {
     let entries = match std::fs::read_dir(path) {
         Ok(entries) => entries,
-        Err(e) => return Err(Box::new(e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(Box::new(PlatformUnsupported::PlatformsDirMissing {
+                path: path.display().to_string(),
+            }))
+        }
+        Err(e) => {
+            return Err(Box::new(PlatformUnsupported::PlatformsDirUnreadable {
+                path: path.display().to_string(),
+                error: e,
+            }))
+        }
     };
 
     Ok(Some(
@@ -236,7 +525,14 @@ fn contains_yaml_files(path: &PathBuf) -> Result<Option<Vec<String>>, Box<dyn st
             .filter_map(|entry| {
                 entry
                     .ok()
-                    .and_then(|e| e.path().to_str().map(|s| s.to_owned()))
+                    .map(|e| e.path())
+                    .filter(|path| {
+                        matches!(
+                            path.extension().and_then(|ext| ext.to_str()),
+                            Some("yml" | "yaml")
+                        )
+                    })
+                    .and_then(|path| path.to_str().map(|s| s.to_owned()))
             })
             .collect(),
     ))
@@ -246,6 +542,17 @@ fn contains_yaml_files(path: &PathBuf) -> Result<Option<Vec<String>>, Box<dyn st
 pub enum InterfaceErrors {
     #[error("InvalidPortAssignment: interfaces do not exist on provided platform")]
     InvalidPortAssignment,
+    #[error(
+        "PatternUnanchored: model {model} interface pattern '{pattern}' lacks `^` & `$` anchors"
+    )]
+    PatternUnanchored { model: String, pattern: String },
+    #[error("TooManyInterfaces: {direction} declares {count} interfaces, but model {model} supports at most {max}")]
+    TooManyInterfaces {
+        direction: String,
+        count: usize,
+        model: String,
+        max: usize,
+    },
 }
 
 mod regex_serde {
@@ -278,21 +585,340 @@ mod tests {
     use super::*;
     use regex::Regex;
 
+    #[test]
+    fn scan_skips_files_without_yaml_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("juniper.md"), "# juniper notes").unwrap();
+        std::fs::write(dir.path().join("arista.yml"), "make: arista\nmodels: []\n").unwrap();
+        let catalog = PlatformCatalog::new(dir.path().to_str().unwrap());
+
+        assert_eq!(catalog.makes(), vec![String::from("arista")]);
+        assert!(catalog.load("arista").is_ok());
+    }
+
+    #[test]
+    fn platforms_file_indexes_each_make() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("cisco.yaml"),
+            "platforms:
+  - make: cisco_ios
+    models:
+      - name: c9300
+        interfaces: ['^Gi1/0/\\d+$']
+  - make: cisco_nxos
+    interfaces: ['^mgmt0$']
+    models:
+      - name: n9k
+        interfaces: ['^Ethernet1/\\d+$']
+",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("arista.yml"), "make: arista\nmodels: []\n").unwrap();
+        let catalog = PlatformCatalog::new(dir.path().to_str().unwrap());
+
+        let nxos = catalog.load("cisco_nxos").unwrap();
+        assert_eq!(nxos.lookup_model_regex("n9k").unwrap().len(), 2);
+        assert!(catalog
+            .load("cisco_ios")
+            .unwrap()
+            .lookup_model("n9k")
+            .is_none());
+        assert!(catalog.load("cisco").is_err());
+        assert_eq!(catalog.makes(), vec!["arista", "cisco_ios", "cisco_nxos"]);
+        assert_eq!(catalog.parses.get(), 2);
+
+        let ports = vec![String::from("Ethernet1/1")];
+        let device = Device::build(
+            "rsk101-dc-sw1",
+            "cisco_nxos",
+            "n9k",
+            &ports,
+            &ports,
+            &catalog,
+            LogLevel::None,
+        )
+        .unwrap();
+        assert_eq!(device.make, "cisco_nxos");
+    }
+
+    #[test]
+    fn malformed_platform_file_is_named() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("juniper.yaml");
+        std::fs::write(&file, "make: juniper\nmodels: {").unwrap();
+        let catalog = PlatformCatalog::new(dir.path().to_str().unwrap());
+
+        let e = catalog.load("juniper").unwrap_err();
+        assert!(e.to_string().starts_with(&format!(
+            "PlatformFileMalformed: platform file {}:2:9 is malformed\n",
+            file.display()
+        )));
+    }
+
+    #[test]
+    fn malformed_platform_list_is_located() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("family.yaml");
+        std::fs::write(&file, "platforms:\n  - make: vyos\n").unwrap();
+        let catalog = PlatformCatalog::new(dir.path().to_str().unwrap());
+
+        let e = catalog.load("family").unwrap_err();
+        assert!(e.to_string().starts_with(&format!(
+            "PlatformFileMalformed: platform file {}:2:5 is malformed\n",
+            file.display()
+        )));
+        assert!(e.to_string().contains("missing field `models`"));
+    }
+
+    #[test]
+    fn list_names_makes_and_models() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("vyos.yaml"),
+            "make: vyos\ninterfaces: ['^lo$']\nmodels:\n  - name: vm\n    interfaces: ['^eth\\d+$', '^bond\\d+$']\n  - name: edge\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("broken.yaml"), "make: [").unwrap();
+        let catalog = PlatformCatalog::new(dir.path().to_str().unwrap());
+
+        assert_eq!(
+            catalog.list(LogLevel::None),
+            "vyos\n  vm\t3 interface patterns\n  edge\t1 interface patterns\n"
+        );
+        assert_eq!(PlatformCatalog::new("./missing").list(LogLevel::None), "");
+    }
+
     #[test]
     fn build_device_succeeds() {
         let ports = vec!["xe-0/0/0".to_string(), "xe-0/0/1".to_string()];
         let dbg = crate::LogLevel::Debug;
+        let catalog = PlatformCatalog::new("./platform");
         let _ = Device::build(
             "test-device",
             "juniper",
             "srx1500",
             &ports,
             &ports,
-            "./platforms",
+            &catalog,
             dbg,
         );
     }
 
+    #[test]
+    fn platform_metadata_and_unknown_keys_preserved() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("juniper.yaml"),
+            "make: juniper
+metadata:
+  owner: netops
+  eol: 2030-01-01
+support: tac
+models:
+  - name: srx1500
+    interfaces: ['^ae\\d+$']
+",
+        )
+        .unwrap();
+        let platform = SupportedPlatform::from_file(&dir.path().join("juniper.yaml")).unwrap();
+        let metadata = platform.metadata.as_ref().unwrap();
+
+        assert_eq!(metadata["owner"].as_str(), Some("netops"));
+        assert_eq!(platform.extra["support"].as_str(), Some("tac"));
+
+        let catalog = PlatformCatalog::new(dir.path().to_str().unwrap());
+        let ports = vec![String::from("ae1")];
+        let device = Device::build(
+            "test-device",
+            "juniper",
+            "srx1500",
+            &ports,
+            &ports,
+            &catalog,
+            crate::LogLevel::None,
+        )
+        .unwrap();
+        assert_eq!(device.metadata.as_ref(), Some(metadata));
+    }
+
+    #[test]
+    fn model_interface_limit_rejects_extra_interfaces() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("juniper.yaml"),
+            "make: juniper
+models:
+  - name: srx300
+    max_interfaces: 2
+    interfaces: ['^ae\\d+$']
+",
+        )
+        .unwrap();
+        let catalog = PlatformCatalog::new(dir.path().to_str().unwrap());
+        let build = |ingress: &Vec<String>| {
+            Device::build(
+                "test-device",
+                "juniper",
+                "srx300",
+                ingress,
+                &vec![],
+                &catalog,
+                crate::LogLevel::None,
+            )
+        };
+
+        let two: Vec<String> = vec![String::from("ae1"), String::from("ae2")];
+        assert!(build(&two).is_ok());
+
+        let three: Vec<String> = vec![
+            String::from("ae1"),
+            String::from("ae2"),
+            String::from("ae3"),
+        ];
+        assert_eq!(
+            build(&three).unwrap_err().to_string(),
+            "TooManyInterfaces: ingress declares 3 interfaces, but model srx300 supports at most 2"
+        );
+    }
+
+    #[test]
+    fn build_devices_for_multiple_platforms() {
+        let dbg = crate::LogLevel::Debug;
+        let cfg =
+            crate::config::Configuration::load("site/multi-platform.example.yaml", "./acls", dbg)
+                .unwrap()
+                .unwrap();
+        let catalog = PlatformCatalog::new("./platform");
+
+        let builds: Vec<Result<Device, Box<dyn std::error::Error>>> = cfg
+            .deployment
+            .platforms()
+            .iter()
+            .map(|platform| {
+                Device::build(
+                    "test-device",
+                    &platform.make,
+                    &platform.model,
+                    &cfg.deployment.ingress.interfaces,
+                    &cfg.deployment.egress.interfaces,
+                    &catalog,
+                    dbg,
+                )
+            })
+            .collect();
+
+        assert_eq!(builds.len(), 2);
+        assert_eq!(builds[0].as_ref().unwrap().model, "srx1500");
+        assert_eq!(
+            builds[1].as_ref().unwrap_err().to_string(),
+            PlatformUnsupported::ModelNotSupported.to_string()
+        );
+    }
+
+    #[test]
+    fn missing_platforms_dir_names_env_var() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("platforms");
+        let catalog = PlatformCatalog::new(missing.to_str().unwrap());
+        let ports = vec![String::from("ae0")];
+        let e = Device::build(
+            "test-device",
+            "juniper",
+            "srx1500",
+            &ports,
+            &ports,
+            &catalog,
+            crate::LogLevel::None,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "PlatformsDirMissing: platforms directory {} not found; set AM3K_PLATFORMS_PATH",
+                missing.display()
+            )
+        );
+    }
+
+    #[test]
+    fn unanchored_interface_patterns_are_listed() {
+        let model: Models = serde_yml::from_str(
+            "name: srx300
+interfaces: ['^ge-0/0/\\d+$', 'xe', '^et-', 'lo0$']
+",
+        )
+        .unwrap();
+
+        assert_eq!(model.unanchored_interfaces(), vec!["xe", "^et-", "lo0$"]);
+        let platform = SupportedPlatform::from_file(Path::new("platform/juniper.yaml")).unwrap();
+        for model in &platform.models {
+            assert!(model.unanchored_interfaces().is_empty());
+        }
+    }
+
+    #[test]
+    fn catalog_scans_and_parses_once() {
+        let ports = vec!["ae0".to_string(), "ae1".to_string()];
+        let dbg = crate::LogLevel::Debug;
+        let catalog = PlatformCatalog::new("./platform");
+        for model in ["srx1500", "qfx5200-32c"] {
+            Device::build(
+                "test-device",
+                "juniper",
+                model,
+                &ports,
+                &ports,
+                &catalog,
+                dbg,
+            )
+            .unwrap();
+        }
+
+        assert_eq!(catalog.scans.get(), 1);
+        assert_eq!(catalog.parses.get(), 1);
+    }
+
+    #[test]
+    fn junos_models_extend_base_ifaces() {
+        let platform =
+            SupportedPlatform::from_file(&PathBuf::from("./platform/juniper.yaml")).unwrap();
+        let base: Vec<&str> = platform.interfaces.iter().map(|r| r.as_str()).collect();
+        assert!(!base.is_empty());
+
+        let mut model_specific: bool = false;
+        for model in &platform.models {
+            let patterns: Vec<&str> = platform
+                .lookup_model_regex(&model.name)
+                .unwrap()
+                .iter()
+                .map(|r| r.as_str())
+                .collect();
+            assert!(base.iter().all(|b| patterns.contains(b)), "{}", model.name);
+            model_specific |= patterns.iter().any(|p| !base.contains(p));
+        }
+        assert!(model_specific);
+    }
+
+    #[test]
+    fn arista_eos_ifaces_validate() {
+        let platform =
+            SupportedPlatform::from_file(&PathBuf::from("./platform/arista.yaml")).unwrap();
+        let patterns = platform.lookup_model_regex("7050sx3-48yc8").unwrap();
+
+        for iface in [
+            "Ethernet1",
+            "Ethernet49/1",
+            "Port-Channel10",
+            "Vlan100",
+            "Loopback0",
+        ] {
+            assert!(Paths::is_valid_iface(iface, patterns), "{}", iface);
+        }
+        assert!(!Paths::is_valid_iface("xe-0/0/0", patterns));
+    }
+
     #[test]
     fn build_path_errs_on_invalid_iface() {
         let ports = vec!["et-0/0/0".to_string(), "et-0/0/1".to_string()];
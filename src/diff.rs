@@ -0,0 +1,283 @@
+//! line-based unified diffs of rendered output
+
+/// a line kept from both sides, removed from the old side, or added by the new side
+#[derive(Debug, PartialEq)]
+enum Edit<'a> {
+    Keep(&'a str),
+    Remove(&'a str),
+    Add(&'a str),
+}
+
+/// computes a minimal line edit script in linear space, by Myers' divide & conquer
+/// - removals are listed before additions within a change
+fn edits<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Edit<'a>> {
+    let mut script: Vec<Edit> = Vec::with_capacity(old.len().max(new.len()));
+    conquer(old, new, &mut script);
+
+    let mut ordered: Vec<Edit> = Vec::with_capacity(script.len());
+    let mut added: Vec<Edit> = vec![];
+    for edit in script {
+        match edit {
+            Edit::Add(_) => added.push(edit),
+            Edit::Remove(_) => ordered.push(edit),
+            Edit::Keep(_) => {
+                ordered.append(&mut added);
+                ordered.push(edit);
+            }
+        }
+    }
+    ordered.append(&mut added);
+    ordered
+}
+
+/// appends the edits turning `old` into `new`, splitting both at a middle snake
+/// - common leading & trailing lines are kept without searching them
+fn conquer<'a>(old: &[&'a str], new: &[&'a str], script: &mut Vec<Edit<'a>>) {
+    let prefix: usize = old.iter().zip(new).take_while(|(o, n)| o == n).count();
+    script.extend(old[..prefix].iter().map(|line| Edit::Keep(line)));
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix: usize = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+    let (kept, old, new) = (
+        &old[old.len() - suffix..],
+        &old[..old.len() - suffix],
+        &new[..new.len() - suffix],
+    );
+
+    match (old.is_empty(), new.is_empty()) {
+        (true, _) => script.extend(new.iter().map(|line| Edit::Add(line))),
+        (false, true) => script.extend(old.iter().map(|line| Edit::Remove(line))),
+        (false, false) => {
+            let (x, y) = middle_snake(old, new);
+            conquer(&old[..x], &new[..y], script);
+            conquer(&old[x..], &new[y..], script);
+        }
+    }
+    script.extend(kept.iter().map(|line| Edit::Keep(line)));
+}
+
+/// furthest reaching x per diagonal `k = x - y`, indexed from `-d` to `d`
+struct Diagonals {
+    offset: isize,
+    x: Vec<usize>,
+}
+
+impl std::ops::Index<isize> for Diagonals {
+    type Output = usize;
+
+    fn index(&self, k: isize) -> &usize {
+        &self.x[(k + self.offset) as usize]
+    }
+}
+
+impl std::ops::IndexMut<isize> for Diagonals {
+    fn index_mut(&mut self, k: isize) -> &mut usize {
+        &mut self.x[(k + self.offset) as usize]
+    }
+}
+
+/// finds where a shortest edit path crosses its middle, searching from both ends at once
+/// - expects `old` & `new` to differ in their first and last lines, so the split is strictly inside
+fn middle_snake(old: &[&str], new: &[&str]) -> (usize, usize) {
+    let (n, m) = (old.len(), new.len());
+    let delta: isize = n as isize - m as isize;
+    let odd: bool = delta & 1 == 1;
+    let max: usize = (n + m).div_ceil(2) + 1;
+    let diagonals = || Diagonals {
+        offset: max as isize,
+        x: vec![0; 2 * max + 1],
+    };
+    let (mut forward, mut backward) = (diagonals(), diagonals());
+
+    for d in 0..max as isize {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x: usize = match k == -d || (k != d && forward[k - 1] < forward[k + 1]) {
+                true => forward[k + 1],
+                false => forward[k - 1] + 1,
+            };
+            let (x0, y0) = (x, (x as isize - k) as usize);
+            let mut y: usize = y0;
+            while x < n && y < m && old[x] == new[y] {
+                (x, y) = (x + 1, y + 1);
+            }
+            forward[k] = x;
+            if odd && (k - delta).abs() < d && forward[k] + backward[-(k - delta)] >= n {
+                return (x0, y0);
+            }
+        }
+        // the backward search counts x & y from the ends of `old` & `new`
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x: usize = match k == -d || (k != d && backward[k - 1] < backward[k + 1]) {
+                true => backward[k + 1],
+                false => backward[k - 1] + 1,
+            };
+            let mut y: usize = (x as isize - k) as usize;
+            while x < n && y < m && old[n - x - 1] == new[m - y - 1] {
+                (x, y) = (x + 1, y + 1);
+            }
+            backward[k] = x;
+            if !odd && (k - delta).abs() <= d && backward[k] + forward[-(k - delta)] >= n {
+                return (n - x, m - y);
+            }
+        }
+    }
+    unreachable!("a shortest edit path crosses its middle within (n + m) / 2 edits")
+}
+
+/// formats a unified diff of two texts with `context` unchanged lines around each change
+/// - returns `None` when the texts are identical
+pub fn unified(
+    old_name: &str,
+    new_name: &str,
+    old: &str,
+    new: &str,
+    context: usize,
+) -> Option<String> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let script: Vec<Edit> = edits(&old_lines, &new_lines);
+
+    let changes: Vec<usize> = (0..script.len())
+        .filter(|&i| !matches!(script[i], Edit::Keep(_)))
+        .collect();
+    if changes.is_empty() {
+        return None;
+    }
+
+    // line offsets into each side before every edit
+    let mut offsets: Vec<(usize, usize)> = Vec::with_capacity(script.len() + 1);
+    let (mut o, mut n) = (0, 0);
+    for edit in &script {
+        offsets.push((o, n));
+        match edit {
+            Edit::Keep(_) => (o, n) = (o + 1, n + 1),
+            Edit::Remove(_) => o += 1,
+            Edit::Add(_) => n += 1,
+        }
+    }
+    offsets.push((o, n));
+
+    // changes separated by more than twice the context share no hunk
+    let mut hunks: Vec<(usize, usize)> = vec![];
+    for &i in &changes {
+        match hunks.last_mut() {
+            Some((_, last)) if i - *last <= 2 * context + 1 => *last = i,
+            _ => hunks.push((i, i)),
+        }
+    }
+
+    let mut diff: String = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for (first, last) in hunks {
+        let start: usize = first.saturating_sub(context);
+        let end: usize = (last + context + 1).min(script.len());
+        let (old_start, new_start) = offsets[start];
+        let (old_end, new_end) = offsets[end];
+        // empty ranges are numbered by the line preceding them
+        let line = |offset: usize, count: usize| match count {
+            0 => offset,
+            _ => offset + 1,
+        };
+        diff.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            line(old_start, old_end - old_start),
+            old_end - old_start,
+            line(new_start, new_end - new_start),
+            new_end - new_start
+        ));
+        for edit in &script[start..end] {
+            let (sign, text) = match edit {
+                Edit::Keep(text) => (' ', text),
+                Edit::Remove(text) => ('-', text),
+                Edit::Add(text) => ('+', text),
+            };
+            diff.push_str(&format!("{}{}\n", sign, text));
+        }
+    }
+    Some(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_texts_have_no_diff() {
+        assert_eq!(unified("a", "b", "x\ny\n", "x\ny\n", 3), None);
+    }
+
+    #[test]
+    fn changed_line_is_removed_then_added() {
+        let old: &str = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let new: &str = "1\n2\n3\n4\nfive\n6\n7\n8\n9\n";
+
+        assert_eq!(
+            unified("old", "new", old, new, 2).unwrap(),
+            "--- old\n+++ new\n@@ -3,5 +3,5 @@\n 3\n 4\n-5\n+five\n 6\n 7\n"
+        );
+    }
+
+    #[test]
+    fn distant_changes_split_hunks() {
+        let old: &str = "a\n1\n2\n3\n4\n5\nb\n";
+        let new: &str = "1\n2\n3\n4\n5\nc\n";
+
+        assert_eq!(
+            unified("old", "new", old, new, 1).unwrap(),
+            "--- old\n+++ new\n@@ -1,2 +1,1 @@\n-a\n 1\n@@ -6,2 +5,2 @@\n 5\n-b\n+c\n"
+        );
+    }
+
+    #[test]
+    fn added_to_empty_text_numbers_from_zero() {
+        assert_eq!(
+            unified("old", "new", "", "x\n", 3).unwrap(),
+            "--- old\n+++ new\n@@ -0,0 +1,1 @@\n+x\n"
+        );
+    }
+
+    #[test]
+    fn edits_keep_a_longest_common_subsequence() {
+        // lines drawn from a small alphabet by a fixed linear congruential sequence
+        let mut seed: u32 = 7;
+        let mut lines = |len: usize| -> Vec<&'static str> {
+            (0..len)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                    ["a", "b", "c", "d"][(seed >> 16) as usize % 4]
+                })
+                .collect()
+        };
+        for (n, m) in [(1, 1), (2, 5), (7, 3), (12, 12), (20, 9), (31, 40)] {
+            let (old, new) = (lines(n), lines(m));
+            let mut lcs: Vec<Vec<usize>> = vec![vec![0; m + 1]; n + 1];
+            for i in (0..n).rev() {
+                for j in (0..m).rev() {
+                    lcs[i][j] = match old[i] == new[j] {
+                        true => lcs[i + 1][j + 1] + 1,
+                        false => lcs[i + 1][j].max(lcs[i][j + 1]),
+                    };
+                }
+            }
+
+            let script: Vec<Edit> = edits(&old, &new);
+            let (mut kept, mut from, mut to) = (0, vec![], vec![]);
+            for edit in &script {
+                match *edit {
+                    Edit::Keep(line) => {
+                        kept += 1;
+                        from.push(line);
+                        to.push(line);
+                    }
+                    Edit::Remove(line) => from.push(line),
+                    Edit::Add(line) => to.push(line),
+                }
+            }
+            assert_eq!(kept, lcs[0][0], "{:?} {:?}", old, new);
+            assert_eq!((from, to), (old, new));
+        }
+    }
+}
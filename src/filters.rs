@@ -0,0 +1,712 @@
+use std::{collections::HashMap, net::Ipv4Addr};
+
+use crate::ruleset::{wildcard_cidr, AddrFamily};
+
+use tera::{Tera, Value};
+
+type Filter = fn(&Value, &HashMap<String, Value>) -> tera::Result<Value>;
+
+/// every output filter, by the name templates call it with
+const FILTERS: &[(&str, Filter)] = &[
+    ("ipt_addr", ipt_addr),
+    ("ipt_port", ipt_port),
+    ("nft_addr", nft_addr),
+    ("nft_ports", nft_ports),
+    ("junos_addr", junos_addr),
+    ("junos_ports", junos_ports),
+    ("ios_addr", ios_addr),
+    ("ios_ports", ios_ports),
+    ("addr_family", addr_family),
+    ("netmask", netmask),
+    ("wildcard", wildcard),
+    ("wrap", wrap),
+];
+
+/// names of every output filter, in registration order
+pub const NAMES: &[&str] = &[
+    "ipt_addr",
+    "ipt_port",
+    "nft_addr",
+    "nft_ports",
+    "junos_addr",
+    "junos_ports",
+    "ios_addr",
+    "ios_ports",
+    "addr_family",
+    "netmask",
+    "wildcard",
+    "wrap",
+];
+
+/// registers the output filters available to every template
+pub fn register(tera: &mut Tera) {
+    register_only(tera, NAMES);
+}
+
+/// registers the named output filters, ignoring names of no filter
+pub fn register_only(tera: &mut Tera, names: &[&str]) {
+    for (name, filter) in FILTERS.iter().filter(|(name, _)| names.contains(name)) {
+        tera.register_filter(name, *filter);
+    }
+}
+
+/// formats a prefix as an iptables address match, e.g. `-s 10.0.0.0/8`
+/// - requires `dir` of `src` or `dst`, and accepts `negated` to invert the match
+/// - `any` omits the match, `any4` & `any6` match `0.0.0.0/0` & `::/0`
+/// - named prefixes reference an ipset
+fn ipt_addr(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let prefix: &str = value
+        .as_str()
+        .ok_or_else(|| tera::Error::msg("ipt_addr: expected a prefix string"))?;
+    let dir: &str = match args.get("dir").and_then(Value::as_str) {
+        Some(dir @ ("src" | "dst")) => dir,
+        _ => return Err(tera::Error::msg("ipt_addr: expected `dir` of src or dst")),
+    };
+
+    let not: &str = match is_negated(args) {
+        true => "! ",
+        false => "",
+    };
+
+    let matched: String = match wildcard_cidr(prefix) {
+        "any" => String::new(),
+        p if p.contains(':') || p.starts_with(|c: char| c.is_ascii_digit()) => {
+            format!("{}-{} {}", not, &dir[..1], p)
+        }
+        p => format!("-m set {}--match-set {} {}", not, p, dir),
+    };
+    Ok(Value::String(matched))
+}
+
+/// formats a port value as an iptables port match, e.g. `--dport 22`
+/// - requires `dir` of `sport` or `dport`
+/// - ranges use `first:last`, and port lists fall back to `multiport`
+/// - `any` formats as an empty string so the match is omitted
+fn ipt_port(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let dir: &str = match args.get("dir").and_then(Value::as_str) {
+        Some(dir @ ("sport" | "dport")) => dir,
+        _ => {
+            return Err(tera::Error::msg(
+                "ipt_port: expected `dir` of sport or dport",
+            ))
+        }
+    };
+    let ports: Vec<String> = match value {
+        Value::String(s) if s == "any" => return Ok(Value::String(String::new())),
+        Value::Number(n) => vec![n.to_string()],
+        Value::Array(ranges) => ranges
+            .iter()
+            .map(|range| match range.as_array().map(Vec::as_slice) {
+                Some([start, end]) if start == end => Ok(start.to_string()),
+                Some([start, end]) => Ok(format!("{}:{}", start, end)),
+                _ => Err(tera::Error::msg("ipt_port: expected port range tuples")),
+            })
+            .collect::<tera::Result<_>>()?,
+        _ => {
+            return Err(tera::Error::msg(
+                "ipt_port: expected `any`, a port, or port map",
+            ))
+        }
+    };
+
+    match ports.len() {
+        1 => Ok(Value::String(format!("--{} {}", dir, ports[0]))),
+        _ => Ok(Value::String(format!(
+            "-m multiport --{}s {}",
+            dir,
+            ports.join(",")
+        ))),
+    }
+}
+
+/// formats a prefix as an nftables address match, e.g. `ip saddr 10.0.0.0/8`
+/// - requires `dir` of `saddr` or `daddr`, and accepts `negated` to invert the match
+/// - `any` omits the match, `any4` & `any6` match `0.0.0.0/0` & `::/0`
+/// - named prefixes reference an nft variable
+fn nft_addr(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let prefix: &str = value
+        .as_str()
+        .ok_or_else(|| tera::Error::msg("nft_addr: expected a prefix string"))?;
+    let dir: &str = match args.get("dir").and_then(Value::as_str) {
+        Some(dir @ ("saddr" | "daddr")) => dir,
+        _ => {
+            return Err(tera::Error::msg(
+                "nft_addr: expected `dir` of saddr or daddr",
+            ))
+        }
+    };
+
+    let not: &str = match is_negated(args) {
+        true => "!= ",
+        false => "",
+    };
+
+    let matched: String = match wildcard_cidr(prefix) {
+        "any" => String::new(),
+        p if p.contains(':') => format!("ip6 {} {}{}", dir, not, p),
+        p if p.starts_with(|c: char| c.is_ascii_digit()) => format!("ip {} {}{}", dir, not, p),
+        p => format!("ip {} {}${}", dir, not, p),
+    };
+    Ok(Value::String(matched))
+}
+
+/// formats a prefix as a junos `from` match, e.g. `source-address { 10.0.0.0/8; }`
+/// - requires `dir` of `source` or `destination`, and accepts `negated` to invert the match
+/// - `any` omits the match, `any4` & `any6` match `0.0.0.0/0` & `::/0`
+/// - named prefixes reference a prefix-list
+/// - negated addresses are excepted from the family default route, as junos requires
+fn junos_addr(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let prefix: &str = value
+        .as_str()
+        .ok_or_else(|| tera::Error::msg("junos_addr: expected a prefix string"))?;
+    let dir: &str = match args.get("dir").and_then(Value::as_str) {
+        Some(dir @ ("source" | "destination")) => dir,
+        _ => {
+            return Err(tera::Error::msg(
+                "junos_addr: expected `dir` of source or destination",
+            ))
+        }
+    };
+    let negated: bool = is_negated(args);
+
+    let matched: String = match (wildcard_cidr(prefix), negated) {
+        ("any", _) => String::new(),
+        (p, _) if AddrFamily::of(p).is_none() => match negated {
+            true => format!("{}-prefix-list {{ {} except; }}", dir, p),
+            false => format!("{}-prefix-list {{ {}; }}", dir, p),
+        },
+        (p, true) => format!(
+            "{}-address {{ {}; {} except; }}",
+            dir,
+            wildcard_cidr(if p.contains(':') { "any6" } else { "any4" }),
+            p
+        ),
+        (p, false) => format!("{}-address {{ {}; }}", dir, p),
+    };
+    Ok(Value::String(matched))
+}
+
+/// formats a port value as a junos `from` match, e.g. `destination-port [ 80 443 ];`
+/// - requires `dir` of `source-port` or `destination-port`
+/// - accepts `any`, a single port, or a serialized `PortMap` of range tuples
+/// - `any` formats as an empty string so the match is omitted
+fn junos_ports(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let dir: &str = match args.get("dir").and_then(Value::as_str) {
+        Some(dir @ ("source-port" | "destination-port")) => dir,
+        _ => {
+            return Err(tera::Error::msg(
+                "junos_ports: expected `dir` of source-port or destination-port",
+            ))
+        }
+    };
+    let ports: Vec<String> = match value {
+        Value::String(s) if s == "any" => return Ok(Value::String(String::new())),
+        Value::Number(n) => vec![n.to_string()],
+        Value::Array(ranges) => ranges
+            .iter()
+            .map(|range| match range.as_array().map(Vec::as_slice) {
+                Some([start, end]) if start == end => Ok(start.to_string()),
+                Some([start, end]) => Ok(format!("{}-{}", start, end)),
+                _ => Err(tera::Error::msg("junos_ports: expected port range tuples")),
+            })
+            .collect::<tera::Result<_>>()?,
+        _ => {
+            return Err(tera::Error::msg(
+                "junos_ports: expected `any`, a port, or port map",
+            ))
+        }
+    };
+
+    match ports.len() {
+        1 => Ok(Value::String(format!("{} {};", dir, ports[0]))),
+        _ => Ok(Value::String(format!("{} [ {} ];", dir, ports.join(" ")))),
+    }
+}
+
+/// formats a prefix as a cisco ios extended acl address, e.g. `10.0.0.0 0.255.255.255`
+/// - `any`, `any4`, and default routes are `any`, and /32s & bare addresses are `host`s
+/// - named prefixes reference a network object-group
+/// - ios extended acls are ipv4 only, so ipv6 prefixes err
+fn ios_addr(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let prefix: &str = value
+        .as_str()
+        .ok_or_else(|| tera::Error::msg("ios_addr: expected a prefix string"))?;
+    let matched: String = match wildcard_cidr(prefix) {
+        "any" | "0.0.0.0/0" => String::from("any"),
+        p if p.contains(':') => {
+            return Err(tera::Error::msg(format!(
+                "ios_addr: expected an ipv4 prefix, found {}",
+                p
+            )))
+        }
+        p if AddrFamily::of(p).is_none() => format!("object-group {}", p),
+        p => match p.split_once('/') {
+            None | Some((_, "32")) => format!("host {}", p.trim_end_matches("/32")),
+            Some((addr, _)) => match ipv4_mask("ios_addr", value)? {
+                Some(mask) => format!("{} {}", addr, Ipv4Addr::from(!mask)),
+                None => String::from("any"),
+            },
+        },
+    };
+    Ok(Value::String(matched))
+}
+
+/// formats a port value as a cisco ios or arista eos port match, e.g. `eq 80 443` or `range 8000 8010`
+/// - accepts `any`, a single port, or a serialized `PortMap` of range tuples
+/// - `any` formats as an empty string so the match is omitted
+/// - ios matches a single range, or a list of ports, so lists mixing ranges err
+fn ios_ports(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let ranges: Vec<(u64, u64)> = match value {
+        Value::String(s) if s == "any" => return Ok(Value::String(String::new())),
+        Value::Number(n) => vec![(n.as_u64().unwrap_or(0), n.as_u64().unwrap_or(0))],
+        Value::Array(ranges) => ranges
+            .iter()
+            .map(|range| match range.as_array().map(Vec::as_slice) {
+                Some([start, end]) => match (start.as_u64(), end.as_u64()) {
+                    (Some(start), Some(end)) => Ok((start, end)),
+                    _ => Err(tera::Error::msg("ios_ports: expected port range tuples")),
+                },
+                _ => Err(tera::Error::msg("ios_ports: expected port range tuples")),
+            })
+            .collect::<tera::Result<_>>()?,
+        _ => {
+            return Err(tera::Error::msg(
+                "ios_ports: expected `any`, a port, or port map",
+            ))
+        }
+    };
+
+    match ranges[..] {
+        [(start, end)] if start != end => Ok(Value::String(format!("range {} {}", start, end))),
+        _ if ranges.iter().all(|(start, end)| start == end) => Ok(Value::String(format!(
+            "eq {}",
+            ranges
+                .iter()
+                .map(|(port, _)| port.to_string())
+                .collect::<Vec<String>>()
+                .join(" ")
+        ))),
+        _ => Err(tera::Error::msg(
+            "ios_ports: expected a single range, or a list of ports",
+        )),
+    }
+}
+
+/// names the address family of a rule from whichever prefix names one
+/// - formats as `ipv4`, `ipv6`, or `any` when neither prefix names a family
+fn addr_family(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let prefix = |field: &str| -> tera::Result<Option<AddrFamily>> {
+        value
+            .get(field)
+            .and_then(Value::as_str)
+            .map(AddrFamily::of)
+            .ok_or_else(|| tera::Error::msg("addr_family: expected a rule"))
+    };
+    let family: &str = match prefix("src_prefix")?.or(prefix("dst_prefix")?) {
+        Some(AddrFamily::Ipv4) => "ipv4",
+        Some(AddrFamily::Ipv6) => "ipv6",
+        None => "any",
+    };
+    Ok(Value::String(String::from(family)))
+}
+
+/// reads the optional `negated` filter argument
+fn is_negated(args: &HashMap<String, Value>) -> bool {
+    args.get("negated")
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// formats an ipv4 cidr as its subnet mask, e.g. `10.0.0.0/24` as `255.255.255.0`
+/// - a bare address is a host route, and `any` passes through unchanged
+fn netmask(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    match ipv4_mask("netmask", value)? {
+        Some(mask) => Ok(Value::String(Ipv4Addr::from(mask).to_string())),
+        None => Ok(value.clone()),
+    }
+}
+
+/// formats an ipv4 cidr as its inverse wildcard mask, e.g. `10.0.0.0/24` as `0.0.0.255`
+/// - a bare address is a host route, and `any` passes through unchanged
+fn wildcard(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    match ipv4_mask("wildcard", value)? {
+        Some(mask) => Ok(Value::String(Ipv4Addr::from(!mask).to_string())),
+        None => Ok(value.clone()),
+    }
+}
+
+/// parses the prefix length of an ipv4 cidr into its mask bits, or `None` for `any`
+fn ipv4_mask(filter: &str, value: &Value) -> tera::Result<Option<u32>> {
+    let invalid = || tera::Error::msg(format!("{}: expected an ipv4 cidr or `any`", filter));
+    let prefix: &str = value.as_str().ok_or_else(invalid)?;
+    if prefix == "any" {
+        return Ok(None);
+    }
+
+    let (addr, len) = prefix.split_once('/').unwrap_or((prefix, "32"));
+    addr.parse::<Ipv4Addr>().map_err(|_| invalid())?;
+    match len.parse::<u32>() {
+        Ok(0) => Ok(Some(0)),
+        Ok(len @ 1..=32) => Ok(Some(u32::MAX << (32 - len))),
+        _ => Err(invalid()),
+    }
+}
+
+/// wraps a comma separated list onto lines no longer than `width`, e.g. an nftables port set
+/// - continuation lines are prefixed with `indent`, which counts towards `width`
+/// - a missing or zero `width` leaves the value alone, as does an item longer than `width`
+fn wrap(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let list: &str = value
+        .as_str()
+        .ok_or_else(|| tera::Error::msg("wrap: expected a string"))?;
+    let width: usize = match args.get("width").map(Value::as_u64) {
+        None => 0,
+        Some(Some(width)) => width as usize,
+        Some(None) => return Err(tera::Error::msg("wrap: expected `width` of a number")),
+    };
+    let indent: &str = args.get("indent").and_then(Value::as_str).unwrap_or("");
+    if width == 0 || list.chars().count() <= width {
+        return Ok(Value::String(String::from(list)));
+    }
+
+    let items: Vec<&str> = list.split(", ").collect();
+    let mut lines: Vec<String> = vec![];
+    let mut line: String = String::new();
+    let mut fresh: bool = true;
+    for (i, item) in items.iter().enumerate() {
+        let item: String = match i + 1 == items.len() {
+            true => item.to_string(),
+            false => format!("{},", item),
+        };
+        if !fresh && line.chars().count() + 1 + item.chars().count() > width {
+            lines.push(std::mem::replace(&mut line, String::from(indent)));
+            fresh = true;
+        }
+        if !fresh {
+            line.push(' ');
+        }
+        line.push_str(&item);
+        fresh = false;
+    }
+    lines.push(line);
+    Ok(Value::String(lines.join("\n")))
+}
+
+/// formats a port value as an nftables port or anonymous set, e.g. `{ 80, 443, 8000-8010 }`
+/// - accepts `any`, a single port, or a serialized `PortMap` of range tuples
+/// - `any` formats as an empty string so the match is omitted
+fn nft_ports(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let ports: Vec<String> = match value {
+        Value::String(s) if s == "any" => return Ok(Value::String(String::new())),
+        Value::Number(n) => vec![n.to_string()],
+        Value::Array(ranges) => ranges
+            .iter()
+            .map(|range| match range.as_array().map(Vec::as_slice) {
+                Some([start, end]) if start == end => Ok(start.to_string()),
+                Some([start, end]) => Ok(format!("{}-{}", start, end)),
+                _ => Err(tera::Error::msg("nft_ports: expected port range tuples")),
+            })
+            .collect::<tera::Result<_>>()?,
+        _ => {
+            return Err(tera::Error::msg(
+                "nft_ports: expected `any`, a port, or port map",
+            ))
+        }
+    };
+
+    match ports.len() {
+        1 => Ok(Value::String(ports[0].clone())),
+        _ => Ok(Value::String(format!("{{ {} }}", ports.join(", ")))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{context, device::PlatformCatalog, Configuration, Device, LogLevel, Ruleset};
+
+    /// renders a template for a single ingress-only srx1500 with the provided rules
+    fn render(template: &str, rules: &[&str]) -> String {
+        let dbg = LogLevel::None;
+        let mut cfg = Configuration::load("site/example.yaml", "./acls", dbg)
+            .unwrap()
+            .unwrap();
+        cfg.deployment.egress.deployable = false;
+        cfg.deployment.ingress.established = false;
+        let device = Device::build(
+            "rsk101-ext-fw1",
+            "juniper",
+            "srx1500",
+            &cfg.deployment.ingress.interfaces,
+            &cfg.deployment.egress.interfaces,
+            &PlatformCatalog::new("./platform"),
+            dbg,
+        )
+        .unwrap();
+        let rules: Vec<String> = rules.iter().map(|r| r.to_string()).collect();
+        let ruleset = Ruleset::from_vec(&rules).unwrap();
+
+        let mut tera = Tera::new("tmpl/**/*").unwrap();
+        register(&mut tera);
+        let context = context::pack(&cfg, &[device], &[ruleset]).unwrap();
+        tera.render(template, &context).unwrap()
+    }
+
+    #[test]
+    fn nftables_renders_mixed_rules() {
+        let rendered = render(
+            "nftables.tera",
+            &[
+                "allow tcp 10.0.0.0/8 any 192.168.1.0/24 80,443,8000-8010",
+                "denylog udp any any 2001:db8::/32 53",
+                "allowlog ip outside any inside any",
+                "allow icmp any any any 8",
+            ],
+        );
+
+        assert_eq!(
+            rendered,
+            r#"
+# rsk101-ext-fw1: juniper srx1500
+table inet am3k {
+  chain valid.example-ingress {
+    ip saddr 10.0.0.0/8 ip daddr 192.168.1.0/24 tcp dport { 80, 443, 8000-8010 } accept
+    ip6 daddr 2001:db8::/32 udp dport 53 log drop
+    ip saddr $outside ip daddr $inside log accept
+    ip protocol icmp icmp type 8 accept
+    drop
+  }
+  chain forward {
+    type filter hook forward priority 0; policy accept;
+    iifname { "ae101", "ae102" } jump valid.example-ingress
+  }
+}
+
+"#
+        );
+    }
+
+    #[test]
+    fn junos_filter_renders_terms_per_family() {
+        let rendered = render(
+            "junos_filter.tera",
+            &[
+                "allowlog tcp 10.0.0.0/8 any 192.168.1.0/24 80,443",
+                "deny udp any any 2001:db8::/32 53",
+                "allow !icmp !10.0.0.0/8 any inside any",
+            ],
+        );
+        let (firewall, interfaces) = rendered.split_once("interfaces {").unwrap();
+
+        assert_eq!(
+            firewall,
+            r#"
+/* rsk101-ext-fw1: juniper srx1500 */
+firewall {
+    family inet {
+        filter valid.example-ingress {
+            term 1 {
+                from {
+                    source-address { 10.0.0.0/8; }
+                    destination-address { 192.168.1.0/24; }
+                    protocol tcp;
+                    destination-port [ 80 443 ];
+                }
+                then {
+                    log;
+                    accept;
+                }
+            }
+            term 3 {
+                from {
+                    source-address { 0.0.0.0/0; 10.0.0.0/8 except; }
+                    destination-prefix-list { inside; }
+                    protocol-except icmp;
+                }
+                then accept;
+            }
+            term 4 {
+                then discard;
+            }
+        }
+    }
+    family inet6 {
+        filter valid.example-ingress {
+            term 2 {
+                from {
+                    destination-address { 2001:db8::/32; }
+                    next-header udp;
+                    destination-port 53;
+                }
+                then discard;
+            }
+            term 4 {
+                then discard;
+            }
+        }
+    }
+}
+"#
+        );
+        assert_eq!(
+            interfaces
+                .matches("input-list [ valid.example-ingress ];")
+                .count(),
+            4
+        );
+    }
+
+    #[test]
+    fn iptables_denylog_logs_then_drops() {
+        let rendered = render("iptables.tera", &["denylog tcp any any 10.0.0.0/8 22"]);
+        let lines: Vec<&str> = rendered
+            .lines()
+            .filter(|line| line.starts_with("-A valid.example-ingress"))
+            .collect();
+
+        assert_eq!(
+            lines,
+            [
+                "-A valid.example-ingress -p tcp -d 10.0.0.0/8 --dport 22 -j LOG",
+                "-A valid.example-ingress -p tcp -d 10.0.0.0/8 --dport 22 -j DROP",
+                "-A valid.example-ingress -j DROP",
+            ]
+        );
+    }
+
+    #[test]
+    fn negated_fields_render_inverted_matches() {
+        let rules = ["allow !icmp !10.0.0.0/8 any inside any"];
+        let nft = render("nftables.tera", &rules);
+        let ipt = render("iptables.tera", &rules);
+
+        assert!(nft
+            .contains("    ip saddr != 10.0.0.0/8 ip daddr $inside meta l4proto != icmp accept\n"));
+        assert!(ipt.contains(
+            "-A valid.example-ingress ! -p icmp ! -s 10.0.0.0/8 -m set --match-set inside dst -j ACCEPT\n"
+        ));
+    }
+
+    #[test]
+    fn names_match_registered_filters() {
+        let registered: Vec<&str> = FILTERS.iter().map(|(name, _)| *name).collect();
+        assert_eq!(registered, NAMES);
+    }
+
+    #[test]
+    fn cisco_ios_renders_masks_hosts_and_ranges() {
+        let rendered = render(
+            "cisco_ios.tera",
+            &[
+                "allow tcp 10.0.0.0/8 any 192.168.1.1/32 8000-8010",
+                "denylog udp any any 2001:db8::/32 53",
+                "allow icmp any any 192.168.1.1 8",
+            ],
+        );
+
+        assert_eq!(
+            rendered,
+            r#"
+! rsk101-ext-fw1: juniper srx1500
+ip access-list extended valid.example-ingress
+ 10 permit tcp 10.0.0.0 0.255.255.255 host 192.168.1.1 range 8000 8010
+ 30 permit icmp any host 192.168.1.1 8
+ 40 deny ip any any
+interface ae101
+ ip access-group valid.example-ingress in
+interface ae102
+ ip access-group valid.example-ingress in
+
+"#
+        );
+    }
+
+    #[test]
+    fn ios_ports_lists_single_ports_only() {
+        let args = HashMap::new();
+
+        assert_eq!(
+            ios_ports(&serde_json::json!([[80, 80], [443, 443]]), &args).unwrap(),
+            "eq 80 443"
+        );
+        assert!(ios_ports(&serde_json::json!([[80, 80], [8000, 8010]]), &args).is_err());
+        assert_eq!(ios_ports(&Value::from("any"), &args).unwrap(), "");
+    }
+
+    #[test]
+    fn nft_ports_formats_sets() {
+        let args = HashMap::new();
+        let map = serde_json::json!([[80, 80], [8000, 8010]]);
+
+        assert_eq!(nft_ports(&map, &args).unwrap(), "{ 80, 8000-8010 }");
+        assert_eq!(nft_ports(&Value::from(22), &args).unwrap(), "22");
+        assert_eq!(nft_ports(&Value::from("any"), &args).unwrap(), "");
+    }
+
+    #[test]
+    fn wrap_breaks_port_sets_at_width() {
+        let set = Value::from("{ 80, 443, 8000-8010, 9000 }");
+        let args = HashMap::from([
+            (String::from("width"), Value::from(16)),
+            (String::from("indent"), Value::from("    ")),
+        ]);
+
+        assert_eq!(
+            wrap(&set, &args).unwrap(),
+            "{ 80, 443,\n    8000-8010,\n    9000 }"
+        );
+        assert_eq!(wrap(&set, &HashMap::new()).unwrap(), set);
+        assert_eq!(wrap(&Value::from("22"), &args).unwrap(), Value::from("22"));
+    }
+
+    #[test]
+    fn masks_render_through_template() {
+        let mut tera: Tera = Tera::default();
+        register(&mut tera);
+        tera.add_raw_template(
+            "masks",
+            "{% for p in prefixes %}{{ p | netmask }} {{ p | wildcard }}\n{% endfor %}",
+        )
+        .unwrap();
+        let mut context = tera::Context::new();
+        context.insert(
+            "prefixes",
+            &[
+                "10.0.0.0/24",
+                "192.0.2.1/32",
+                "192.0.2.1",
+                "172.16.0.0/12",
+                "0.0.0.0/0",
+                "any",
+            ],
+        );
+
+        assert_eq!(
+            tera.render("masks", &context).unwrap(),
+            "255.255.255.0 0.0.0.255\n\
+             255.255.255.255 0.0.0.0\n\
+             255.255.255.255 0.0.0.0\n\
+             255.240.0.0 0.15.255.255\n\
+             0.0.0.0 255.255.255.255\n\
+             any any\n"
+        );
+        assert!(wildcard(&Value::from("fd00::/8"), &HashMap::new()).is_err());
+        assert!(netmask(&Value::from("10.0.0.0/33"), &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn family_wildcards_render_default_routes() {
+        let nft = HashMap::from([(String::from("dir"), Value::from("saddr"))]);
+        let ipt = HashMap::from([(String::from("dir"), Value::from("src"))]);
+
+        assert_eq!(
+            nft_addr(&Value::from("any4"), &nft).unwrap(),
+            "ip saddr 0.0.0.0/0"
+        );
+        assert_eq!(
+            nft_addr(&Value::from("any6"), &nft).unwrap(),
+            "ip6 saddr ::/0"
+        );
+        assert_eq!(ipt_addr(&Value::from("any6"), &ipt).unwrap(), "-s ::/0");
+    }
+}
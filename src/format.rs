@@ -0,0 +1,154 @@
+//! output formats, naming the template each renders and the tera filters it needs
+
+use crate::filters;
+use tera::Tera;
+
+/// a rendering of compiled rulesets, selected by `--format` or by a device's make
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputFormat<'a> {
+    pub id: &'a str,
+    /// makes rendering this format when none is selected
+    pub makes: &'a [&'a str],
+    pub template: &'a str,
+    pub filters: &'a [&'a str],
+    /// whether the template renders `rule.negated`, else negated rules are refused
+    pub negates: bool,
+}
+
+/// every built-in format, where the first renders makes no other format claims
+pub const FORMATS: &[OutputFormat<'static>] = &[
+    OutputFormat {
+        id: "yaml",
+        makes: &[],
+        template: "ruleset.tera",
+        filters: &[],
+        negates: true,
+    },
+    OutputFormat {
+        id: "arista",
+        makes: &["arista"],
+        template: "arista_eos.tera",
+        filters: &["ios_ports", "addr_family"],
+        negates: false,
+    },
+    OutputFormat {
+        id: "cisco",
+        makes: &["cisco"],
+        template: "cisco_ios.tera",
+        filters: &["ios_addr", "ios_ports", "addr_family"],
+        negates: false,
+    },
+    OutputFormat {
+        id: "junos",
+        makes: &[],
+        template: "junos_filter.tera",
+        filters: &["junos_addr", "junos_ports", "addr_family"],
+        negates: true,
+    },
+    OutputFormat {
+        id: "nftables",
+        makes: &[],
+        template: "nftables.tera",
+        filters: &["nft_addr", "nft_ports", "wrap"],
+        negates: true,
+    },
+    OutputFormat {
+        id: "iptables",
+        makes: &[],
+        template: "iptables.tera",
+        filters: &["ipt_addr", "ipt_port", "addr_family"],
+        negates: true,
+    },
+];
+
+impl<'a> OutputFormat<'a> {
+    /// finds a built-in format by id
+    pub fn lookup(id: &str) -> Option<OutputFormat<'static>> {
+        FORMATS.iter().find(|format| format.id == id).copied()
+    }
+
+    /// the built-in format rendered for a make, falling back to `yaml`
+    pub fn for_make(make: &str) -> OutputFormat<'static> {
+        FORMATS
+            .iter()
+            .find(|format| format.makes.contains(&make))
+            .copied()
+            .unwrap_or(FORMATS[0])
+    }
+
+    /// a format rendering a template named by `--template`, with every filter available
+    pub fn custom(template: &'a str) -> Self {
+        OutputFormat {
+            id: "custom",
+            makes: &[],
+            template,
+            filters: filters::NAMES,
+            negates: true,
+        }
+    }
+
+    /// selects the format for a make, where a `--template` overrides a `--format`
+    pub fn select(template: Option<&'a str>, format: Option<Self>, make: &str) -> Self {
+        match (template, format) {
+            (Some(template), _) => Self::custom(template),
+            (None, Some(format)) => format,
+            (None, None) => Self::for_make(make),
+        }
+    }
+
+    /// registers the filters this format renders with
+    pub fn register(&self, tera: &mut Tera) {
+        filters::register_only(tera, self.filters);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_format_names_known_filters() {
+        for format in FORMATS {
+            assert!(
+                format.filters.iter().all(|f| filters::NAMES.contains(f)),
+                "{}",
+                format.id
+            );
+        }
+    }
+
+    #[test]
+    fn cisco_and_junos_select_their_templates_and_filters() {
+        let cisco = OutputFormat::select(None, OutputFormat::lookup("cisco"), "juniper");
+        let junos = OutputFormat::select(None, OutputFormat::lookup("junos"), "cisco");
+
+        assert_eq!(cisco.template, "cisco_ios.tera");
+        assert_eq!(junos.template, "junos_filter.tera");
+
+        let render = |format: &OutputFormat, filter: &str| {
+            let mut tera = Tera::default();
+            format.register(&mut tera);
+            let template = format!("{{{{ '10.0.0.0/24' | {} }}}}", filter);
+            tera.render_str(&template, &tera::Context::new())
+        };
+        assert_eq!(render(&cisco, "ios_addr").unwrap(), "10.0.0.0 0.0.0.255");
+        assert!(render(&cisco, "junos_addr(dir='source')").is_err());
+        assert_eq!(
+            render(&junos, "junos_addr(dir='source')").unwrap(),
+            "source-address { 10.0.0.0/24; }"
+        );
+        assert!(render(&junos, "ios_addr").is_err());
+    }
+
+    #[test]
+    fn make_selects_format_unless_overridden() {
+        assert_eq!(OutputFormat::for_make("arista").id, "arista");
+        assert_eq!(OutputFormat::for_make("juniper").id, "yaml");
+        assert_eq!(
+            OutputFormat::select(Some("mine.tera"), OutputFormat::lookup("cisco"), "arista")
+                .template,
+            "mine.tera"
+        );
+        assert_eq!(OutputFormat::lookup("ios"), None);
+    }
+}
@@ -0,0 +1,1014 @@
+//! (am3k) Access Control List Manager 3000
+//!
+//! library entrypoint for loading, validating, and expanding site configurations and rulesets
+
+pub mod cli;
+pub mod config;
+pub mod context;
+pub mod device;
+pub mod diff;
+pub mod filters;
+pub mod format;
+pub mod log;
+pub mod ruleset;
+#[cfg(feature = "watch")]
+pub mod watch;
+
+pub use config::Configuration;
+pub use context::Layout;
+pub use device::Device;
+pub use format::OutputFormat;
+pub use log::LogLevel;
+pub use ruleset::{Rule, RuleBuilder, Ruleset};
+
+use cli::{Args, EnvVars};
+use device::PlatformCatalog;
+use ruleset::{Family, RuleErrors};
+use std::io::BufRead;
+use tera::Tera;
+use thiserror::Error;
+
+/// validated inputs ready for packing into a tera context
+#[derive(Debug)]
+pub struct CompileOutput {
+    pub config: Configuration,
+    pub devices: Vec<Device>,
+    pub rulesets: Vec<Ruleset>,
+    /// findings of the analysis passes, logged as warnings and fatal under `--strict`
+    pub warnings: Vec<String>,
+}
+
+/// failures surfaced by `run`, each mapping to a process exit code
+#[derive(Debug, Error)]
+pub enum AppError {
+    /// exit code 1: configuration or stdin could not be read
+    #[error("{0}")]
+    ConfigLoad(Box<dyn std::error::Error>),
+    /// exit code 2: configuration loaded but failed post-checks
+    #[error("{}", config::ConfigInvalid::FailedPostChecks)]
+    ConfigInvalid,
+    /// exit code 3: devices or rulesets failed validation, carrying any rule errors found
+    #[error("Unable to generate output with provided configuration and rulesets.")]
+    Unbuildable(ruleset::RuleErrors),
+    /// exit code 3: rules provided on stdin failed to parse
+    #[error("* Ruleset issues found while parsing:\n{0}")]
+    RulesInvalid(ruleset::RuleErrors),
+    /// exit code 4: templates could not be loaded
+    #[error("{0}")]
+    TemplateLoad(tera::Error),
+    /// exit code 5: context could not be packed, or template failed to render
+    #[error("{0}")]
+    Render(tera::Error),
+    /// exit code 6: selected template was not discovered in the templates path
+    #[error("template '{name}' not found in {dir}; available: {}", available.join(", "))]
+    TemplateNotFound {
+        name: String,
+        dir: String,
+        available: Vec<String>,
+    },
+    /// exit code of the failure: one side of a diff could not be rendered
+    #[error("{side} config {path} could not be rendered: {error}")]
+    DiffSideFailed {
+        side: &'static str,
+        path: String,
+        error: Box<AppError>,
+    },
+    /// exit code 7: configs compared by diff rendered different output
+    #[error("rendered output of {old} and {new} differs")]
+    Differs { old: String, new: String },
+    /// exit code 8: warnings were found while running with `--strict`
+    #[error("--strict: {count} warning(s) treated as errors")]
+    WarningsFatal { count: usize },
+    /// exit code 9: configs checked by `--config-dir` failed validation
+    #[error("--config-dir: {failed} of {total} config(s) failed validation")]
+    ConfigsFailed { failed: usize, total: usize },
+}
+
+impl AppError {
+    /// process exit code for the error
+    pub fn code(&self) -> i32 {
+        match self {
+            AppError::ConfigLoad(_) => 1,
+            AppError::ConfigInvalid => 2,
+            AppError::Unbuildable(_) | AppError::RulesInvalid(_) => 3,
+            AppError::TemplateLoad(_) => 4,
+            AppError::Render(_) => 5,
+            AppError::TemplateNotFound { .. } => 6,
+            AppError::DiffSideFailed { error, .. } => error.code(),
+            AppError::Differs { .. } => 7,
+            AppError::WarningsFatal { .. } => 8,
+            AppError::ConfigsFailed { .. } => 9,
+        }
+    }
+
+    /// describes the class of failure, shared by every error of the variant
+    pub fn class(&self) -> &'static str {
+        match self {
+            AppError::ConfigLoad(_) => "configuration or stdin could not be read",
+            AppError::ConfigInvalid => "configuration loaded but failed post-checks",
+            AppError::Unbuildable(_) => "devices or rulesets failed validation",
+            AppError::RulesInvalid(_) => "rules provided on stdin failed to parse",
+            AppError::TemplateLoad(_) => "templates could not be loaded",
+            AppError::Render(_) => "template failed to render",
+            AppError::TemplateNotFound { .. } => {
+                "selected template was not discovered in the templates path"
+            }
+            AppError::DiffSideFailed { .. } => "one side of a diff could not be rendered",
+            AppError::Differs { .. } => "configs compared by diff rendered different output",
+            AppError::WarningsFatal { .. } => "warnings were found while running with --strict",
+            AppError::ConfigsFailed { .. } => "configs checked by --config-dir failed validation",
+        }
+    }
+
+    /// one error of every class with an exit code of its own, in exit code order
+    /// - `DiffSideFailed` is omitted, as it exits with the code of the failed side
+    fn classes() -> Vec<AppError> {
+        vec![
+            AppError::ConfigLoad(Box::from("")),
+            AppError::ConfigInvalid,
+            AppError::Unbuildable(RuleErrors::new()),
+            AppError::RulesInvalid(RuleErrors::new()),
+            AppError::TemplateLoad(tera::Error::msg("")),
+            AppError::Render(tera::Error::msg("")),
+            AppError::TemplateNotFound {
+                name: String::new(),
+                dir: String::new(),
+                available: vec![],
+            },
+            AppError::Differs {
+                old: String::new(),
+                new: String::new(),
+            },
+            AppError::WarningsFatal { count: 0 },
+            AppError::ConfigsFailed {
+                failed: 0,
+                total: 0,
+            },
+        ]
+    }
+}
+
+/// lists each exit code with the class of failure it represents, one `code\tclass` per line
+/// - lists only the classes exiting with `code` when provided
+pub fn explain(code: Option<i32>) -> String {
+    let table: String = AppError::classes()
+        .iter()
+        .filter(|e| code.is_none_or(|code| e.code() == code))
+        .map(|e| format!("{}\t{}\n", e.code(), e.class()))
+        .collect();
+    match (table.is_empty(), code) {
+        (true, Some(code)) => format!("exit code {} is not used by am3k\n", code),
+        _ => table,
+    }
+}
+
+/// prints a plain English description of each rule on the line, as parsed by `--stdin`
+fn explain_rule(rule: &str) -> Result<(), AppError> {
+    match Ruleset::from_vec(&[String::from(rule)]) {
+        Ok(ruleset) => {
+            for rule in ruleset {
+                println!("{}", rule.describe());
+            }
+            Ok(())
+        }
+        Err(mut e) => {
+            e.update_paths("--explain-rule");
+            Err(AppError::RulesInvalid(e))
+        }
+    }
+}
+
+/// runs am3k for parsed arguments, printing rendered output
+/// - completions, schemas, diff, and stdin linting short-circuit the configuration pipeline
+pub fn run(args: &Args) -> Result<(), AppError> {
+    let dbg: LogLevel = args.loglevel;
+    log::set_format(args.log_format);
+
+    if let Some(shell) = &args.completions {
+        print!("{}", cli::completions(shell));
+        return Ok(());
+    }
+
+    if args.list_platforms {
+        let catalog: PlatformCatalog = PlatformCatalog::new(&args.env.platforms);
+        match catalog.list(dbg) {
+            listing if listing.is_empty() => warn!(
+                dbg,
+                "No supported platforms found in [{}]",
+                catalog.dir().display()
+            ),
+            listing => print!("{}", listing),
+        }
+        return Ok(());
+    }
+
+    if args.json_schema {
+        println!("{:#}", config::json_schema());
+        return Ok(());
+    }
+
+    if let Some(topic) = &args.explain {
+        print!("{}", explain(topic.parse().ok()));
+        return Ok(());
+    }
+
+    if let Some(rule) = &args.explain_rule {
+        return explain_rule(rule);
+    }
+
+    if let Some((old, new)) = &args.diff {
+        return diff_configs(old, new, args);
+    }
+
+    if args.stdin {
+        return lint_stdin(dbg).inspect_err(|e| {
+            if args.diagnostics {
+                print_diagnostics(e)
+            }
+        });
+    }
+
+    if let Some(dir) = &args.config_dir {
+        return check_config_dir(dir, args);
+    }
+
+    // configuration is mandatory outside of stdin & config dir modes
+    let config_path: &str = args.config.as_deref().ok_or_else(|| {
+        AppError::ConfigLoad(
+            "no configuration given, expected --config, --stdin, or --config-dir".into(),
+        )
+    })?;
+    if args.count {
+        print!("{}", count_rules(config_path, args)?);
+        return Ok(());
+    }
+    #[cfg(feature = "watch")]
+    if args.watch {
+        watch_config(config_path, args);
+    }
+    let output: Vec<String> = build(config_path, args)?;
+    for rendered in &output {
+        // rendered output is the artifact, so it prints regardless of loglevel
+        println!("\n{}", rendered);
+    }
+    if args.report_size {
+        eprint!("{}", size_report(&output));
+    }
+    Ok(())
+}
+
+/// lists the size of each rendered output, in render order, then their totals
+/// - one `output N\tL lines\tB bytes` line per output, closed by a `total` line
+/// - each output covers every device sharing its format, as templates loop over devices
+pub fn size_report(rendered: &[String]) -> String {
+    let mut report: String = String::new();
+    let (mut lines, mut bytes) = (0, 0);
+    for (i, output) in rendered.iter().enumerate() {
+        let (l, b) = (output.lines().count(), output.len());
+        report.push_str(&format!("output {}\t{} lines\t{} bytes\n", i + 1, l, b));
+        (lines, bytes) = (lines + l, bytes + b);
+    }
+    report.push_str(&format!("total\t{} lines\t{} bytes\n", lines, bytes));
+    report
+}
+
+/// renders a site configuration, then re-renders it each time its inputs change
+/// - the config, and every file under the rulesets & templates directories, are watched
+/// - errors are printed in place of output, and never end the watch
+#[cfg(feature = "watch")]
+fn watch_config(config_path: &str, args: &Args) -> ! {
+    let dbg: LogLevel = args.loglevel;
+    let inputs: Vec<std::path::PathBuf> = [config_path, &args.env.rulesets, &args.env.templates]
+        .iter()
+        .map(std::path::PathBuf::from)
+        .collect();
+    let mut seen: watch::Snapshot = watch::snapshot(&inputs);
+    loop {
+        // clears the terminal, so each render replaces the last
+        print!("\x1b[2J\x1b[H");
+        match build(config_path, args) {
+            Ok(output) => output
+                .iter()
+                .for_each(|rendered| println!("\n{}", rendered)),
+            Err(e) => crit!(dbg, "{}", e),
+        }
+        info!(dbg, "\nWatching {} for changes...", config_path);
+        seen = watch::wait_for_change(&inputs, &seen);
+    }
+}
+
+/// compiles and renders a site configuration using the options in parsed arguments
+/// - differing ingress & egress defaults warn, unless `--allow-asymmetric-default`
+/// - under `--strict`, any warning from compilation fails the build
+/// - holds no state between calls, so rendering the same inputs again yields the same output
+pub fn build(config_path: &str, args: &Args) -> Result<Vec<String>, AppError> {
+    let dbg: LogLevel = args.loglevel;
+    let mut output: CompileOutput = compile_checked(config_path, args)?;
+    if args.sort {
+        verb!(dbg, "\nSorting rulesets...");
+        output.rulesets = output.rulesets.into_iter().map(Ruleset::sort).collect();
+    }
+    if args.auto_seq {
+        verb!(dbg, "\nNumbering rulesets...");
+        output.rulesets = output.rulesets.into_iter().map(Ruleset::sequence).collect();
+    }
+    let layout: Layout = Layout {
+        indent: args.indent,
+        line_width: args.line_width,
+    };
+    render(
+        output,
+        args.template.as_deref(),
+        args.format.as_deref().and_then(OutputFormat::lookup),
+        &args.env.templates,
+        &layout,
+        dbg,
+    )
+}
+
+/// compiles as `compile_args`, then checks the configuration for warnings
+/// - differing ingress & egress defaults warn, unless `--allow-asymmetric-default`
+/// - under `--strict`, any warning from compilation fails
+fn compile_checked(config_path: &str, args: &Args) -> Result<CompileOutput, AppError> {
+    let mut output: CompileOutput = compile_args(config_path, args)?;
+    if let Some(warning) = output.config.asymmetric_default() {
+        if !args.allow_asymmetric_default {
+            warn!(args.loglevel, "* {}", warning);
+            output.warnings.push(warning);
+        }
+    }
+    if args.strict && !output.warnings.is_empty() {
+        return Err(AppError::WarningsFatal {
+            count: output.warnings.len(),
+        });
+    }
+    Ok(output)
+}
+
+/// compiles a site configuration, or bundle, with the options in parsed arguments
+/// - rule errors are printed as diagnostics when requested
+/// - `--no-expand` keeps port lists & ranges as one rule each
+/// - `--seed-rules` prepends the rules of its file to every ruleset
+/// - `--include-disabled` keeps disabled rules, which are otherwise dropped once validated
+fn compile_args(config_path: &str, args: &Args) -> Result<CompileOutput, AppError> {
+    let opts: CompileOptions = CompileOptions {
+        max_rules: args.max_rules,
+        device_name: args.device_name.as_deref(),
+        bundle: args.bundle,
+        expand: !args.no_expand,
+        prologue: args.seed_rules.as_deref(),
+        strict_anchors: args.strict_anchors,
+        include_disabled: args.include_disabled,
+    };
+    let compiled = compile_from(config_path, &args.env, &opts, args.loglevel);
+    compiled.inspect_err(|e| {
+        if args.diagnostics {
+            print_diagnostics(e)
+        }
+    })
+}
+
+/// compiles every `.yml` & `.yaml` config in a directory, without rendering any
+/// - prints one `path\tpass` or `path\tfail\terror` line per config, closed by a `total` line
+/// - errs with `ConfigsFailed` after the summary when any config fails
+fn check_config_dir(dir: &str, args: &Args) -> Result<(), AppError> {
+    let mut configs: Vec<std::path::PathBuf> = std::fs::read_dir(dir)
+        .map_err(|e| AppError::ConfigLoad(format!("{}: {}", dir, e).into()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("yml" | "yaml")
+            )
+        })
+        .collect();
+    configs.sort();
+
+    let mut summary: String = String::new();
+    let mut failed: usize = 0;
+    for config in &configs {
+        let config_path: String = config.to_string_lossy().into_owned();
+        info!(args.loglevel, "\nChecking {}...", config_path);
+        match compile_checked(&config_path, args) {
+            Ok(_) => summary.push_str(&format!("{}\tpass\n", config_path)),
+            Err(e) => {
+                // only the first line, as malformed yaml errors continue with serde's detail
+                let error: String = e.to_string().lines().next().unwrap_or_default().to_string();
+                summary.push_str(&format!("{}\tfail\t{}\n", config_path, error));
+                failed += 1;
+            }
+        }
+    }
+    summary.push_str(&format!(
+        "total\t{} passed\t{} failed\n",
+        configs.len() - failed,
+        failed
+    ));
+    // the summary is the artifact, so it prints regardless of loglevel
+    print!("{}", summary);
+    match failed {
+        0 => Ok(()),
+        _ => Err(AppError::ConfigsFailed {
+            failed,
+            total: configs.len(),
+        }),
+    }
+}
+
+/// lists the rules of each ruleset as parsed & expanded, then their totals
+/// - one `name\tparsed\texpanded` line per ruleset, closed by a `total` line
+fn count_rules(config_path: &str, args: &Args) -> Result<String, AppError> {
+    let output: CompileOutput = compile_args(config_path, args)?;
+    let mut counts: String = String::new();
+    let (mut parsed, mut expanded) = (0, 0);
+    for (name, ruleset) in output
+        .config
+        .deployment
+        .rulesets
+        .iter()
+        .zip(&output.rulesets)
+    {
+        let (p, e) = (ruleset.parsed_len(), ruleset.stats().total);
+        counts.push_str(&format!("{}\t{}\t{}\n", name, p, e));
+        (parsed, expanded) = (parsed + p, expanded + e);
+    }
+    counts.push_str(&format!("total\t{}\t{}\n", parsed, expanded));
+    Ok(counts)
+}
+
+/// renders two site configurations and prints a unified diff of their output
+/// - differing output is an error, so scripts can gate on the exit code
+fn diff_configs(old: &str, new: &str, args: &Args) -> Result<(), AppError> {
+    let side = |side: &'static str, path: &str| {
+        build(path, args)
+            .map(|rendered| rendered.join("\n"))
+            .map_err(|e| AppError::DiffSideFailed {
+                side,
+                path: path.to_string(),
+                error: Box::new(e),
+            })
+    };
+    let old_rendered: String = side("old", old)?;
+    let new_rendered: String = side("new", new)?;
+
+    match diff::unified(old, new, &old_rendered, &new_rendered, 3) {
+        Some(unified) => {
+            print!("{}", unified);
+            Err(AppError::Differs {
+                old: old.to_string(),
+                new: new.to_string(),
+            })
+        }
+        None => Ok(()),
+    }
+}
+
+/// renders compiled output, returning the text of each template in platform order
+/// - `format` overrides the format selected per make for every device
+/// - `template` overrides both, rendering with every filter available
+/// - each format renders with only the filters it names
+pub fn render(
+    output: CompileOutput,
+    template: Option<&str>,
+    format: Option<OutputFormat>,
+    templates_path: &str,
+    layout: &Layout,
+    dbg: LogLevel,
+) -> Result<Vec<String>, AppError> {
+    // group devices sharing a format, preserving platform order
+    let mut groups: Vec<(OutputFormat, Vec<Device>)> = vec![];
+    for device in output.devices {
+        let format: OutputFormat = OutputFormat::select(template, format, &device.make);
+        match groups.iter_mut().find(|(f, _)| *f == format) {
+            Some((_, devices)) => devices.push(device),
+            None => groups.push((format, vec![device])),
+        }
+    }
+
+    // formats that cannot express negation would render a negated rule as its inverse
+    for (format, _) in groups.iter().filter(|(format, _)| !format.negates) {
+        let negated = output
+            .rulesets
+            .iter()
+            .flatten()
+            .find(|rule| rule.is_enabled() && rule.is_negated());
+        if let Some(rule) = negated {
+            return Err(AppError::Render(tera::Error::msg(format!(
+                "{} format cannot express negated rules, found: {}",
+                format.id, rule
+            ))));
+        }
+    }
+
+    verb!(dbg, "\nPacking Tera context...");
+    let mut contexts: Vec<(OutputFormat, tera::Context)> = vec![];
+    for (format, devices) in &groups {
+        let mut context = context::pack(&output.config, devices, &output.rulesets)
+            .map_err(|e| AppError::Render(tera::Error::json(e)))?;
+        layout.insert(&mut context);
+        if dbg.value() <= LogLevel::Debug.value() {
+            dbg!(&context);
+        }
+        contexts.push((*format, context));
+    }
+    verb!(dbg, "Packing succeeded.");
+
+    verb!(dbg, "\nLoading templates from {}...", templates_path);
+    let tera: Tera =
+        Tera::new(&format!("{}/**/*", templates_path)).map_err(AppError::TemplateLoad)?;
+    let mut available: Vec<String> = tera.get_template_names().map(String::from).collect();
+    available.sort();
+    if let Some((format, _)) = contexts
+        .iter()
+        .find(|(format, _)| !available.iter().any(|name| name == format.template))
+    {
+        return Err(AppError::TemplateNotFound {
+            name: format.template.to_string(),
+            dir: templates_path.to_string(),
+            available,
+        });
+    }
+
+    // render tera using the format selected for each make, with only its filters
+    contexts
+        .into_iter()
+        .map(|(format, context)| {
+            let mut tera: Tera = tera.clone();
+            format.register(&mut tera);
+            tera.render(format.template, &context)
+                .map_err(AppError::Render)
+        })
+        .collect()
+}
+
+/// prints the rule errors carried by an error as a json array of diagnostics
+/// - errors carrying no rule errors print an empty array
+fn print_diagnostics(e: &AppError) {
+    let diagnostics: Vec<ruleset::Diagnostic> = match e {
+        AppError::Unbuildable(errors) | AppError::RulesInvalid(errors) => errors.diagnostics(),
+        _ => vec![],
+    };
+    println!("{}", serde_json::to_string(&diagnostics).unwrap());
+}
+
+/// lists zone members a direction's policy would apply to, but the device paths lack
+fn unapplied_zone_interfaces<'a>(
+    direction: &'a config::Direction,
+    paths: &[String],
+) -> Vec<(&'a str, &'a str)> {
+    direction
+        .zones
+        .iter()
+        .flat_map(|(zone, members)| members.iter().map(move |iface| (zone, iface)))
+        .filter(|(_, iface)| !paths.contains(iface))
+        .map(|(zone, iface)| (zone.as_str(), iface.as_str()))
+        .collect()
+}
+
+/// parses newline-delimited rules from stdin, printing the expanded ruleset
+fn lint_stdin(dbg: LogLevel) -> Result<(), AppError> {
+    let lines: Vec<String> = std::io::stdin()
+        .lock()
+        .lines()
+        .collect::<Result<_, _>>()
+        .map_err(|e| AppError::ConfigLoad(e.into()))?;
+
+    if lines.iter().all(|line| line.trim().is_empty()) {
+        warn!(dbg, "No rules provided on stdin.");
+        return Ok(());
+    }
+
+    match Ruleset::from_vec(&lines).and_then(|ruleset| ruleset.check_expansion().map(|_| ruleset)) {
+        Ok(ruleset) => {
+            for rule in ruleset.expand() {
+                println!("{}", rule);
+            }
+            Ok(())
+        }
+        Err(mut e) => {
+            e.update_paths("stdin");
+            Err(AppError::RulesInvalid(e))
+        }
+    }
+}
+
+/// loads a site configuration, then builds its devices and expanded rulesets
+/// - device and ruleset issues are logged as found and aggregated into `Unbuildable`
+/// - rulesets expanding to more than `max_rules` rules are invalid
+/// - analysis findings, such as unreachable rules or zone interfaces missing from device
+///   paths, are logged and returned as `warnings`
+/// - a device is built per platform for each `devicelist` name, or only for `device_name`
+pub fn compile(
+    config_path: &str,
+    env: &EnvVars,
+    max_rules: usize,
+    device_name: Option<&str>,
+    dbg: LogLevel,
+) -> Result<CompileOutput, AppError> {
+    let opts: CompileOptions = CompileOptions {
+        max_rules,
+        device_name,
+        bundle: false,
+        expand: true,
+        prologue: None,
+        strict_anchors: false,
+        include_disabled: false,
+    };
+    compile_from(config_path, env, &opts, dbg)
+}
+
+/// compiles as `compile`, reading rulesets inline from the configuration's `rulesets`
+pub fn compile_bundle(
+    bundle_path: &str,
+    env: &EnvVars,
+    max_rules: usize,
+    device_name: Option<&str>,
+    dbg: LogLevel,
+) -> Result<CompileOutput, AppError> {
+    let opts: CompileOptions = CompileOptions {
+        max_rules,
+        device_name,
+        bundle: true,
+        expand: true,
+        prologue: None,
+        strict_anchors: false,
+        include_disabled: false,
+    };
+    compile_from(bundle_path, env, &opts, dbg)
+}
+
+/// how `compile_from` finds, seeds & expands rulesets
+#[derive(Clone, Copy)]
+struct CompileOptions<'a> {
+    max_rules: usize,
+    device_name: Option<&'a str>,
+    bundle: bool,
+    /// expands port lists & ranges into a rule per port, unset by `--no-expand`
+    expand: bool,
+    /// file of rules prepended to every ruleset, from `--seed-rules`
+    prologue: Option<&'a str>,
+    /// fails platforms with unanchored interface patterns, which otherwise warn
+    strict_anchors: bool,
+    /// keeps disabled rules in rulesets, for templates that render them
+    include_disabled: bool,
+}
+
+fn compile_from(
+    config_path: &str,
+    env: &EnvVars,
+    opts: &CompileOptions,
+    dbg: LogLevel,
+) -> Result<CompileOutput, AppError> {
+    let CompileOptions {
+        max_rules,
+        device_name,
+        bundle,
+        expand,
+        prologue,
+        strict_anchors,
+        include_disabled,
+    } = *opts;
+    info!(dbg, "\nLoading configuration file {}...", config_path);
+    let loaded = match bundle {
+        true => Configuration::load_bundle(config_path, dbg),
+        false => Configuration::load(config_path, &env.rulesets, dbg),
+    };
+    let cfg: Configuration = match loaded {
+        Ok(Some(config)) => config,
+        Ok(None) => return Err(AppError::ConfigInvalid),
+        Err(e) => return Err(AppError::ConfigLoad(e)),
+    };
+    info!(dbg, "Configuration file loaded successfully from yaml.");
+
+    let names: Vec<String> = match device_name {
+        Some(name) => {
+            if cfg.check_device_name(name).is_err() {
+                crit!(
+                    dbg,
+                    "* {}: {}",
+                    config::ConfigInvalid::DeviceNamesInvalid,
+                    name
+                );
+                return Err(AppError::ConfigInvalid);
+            }
+            vec![String::from(name)]
+        }
+        None => cfg.deployment.devicelist.clone(),
+    };
+
+    verb!(dbg, "  Loading path to supported platforms...");
+    let catalog: PlatformCatalog = PlatformCatalog::new(&env.platforms);
+    verb!(dbg, "  Found path: {}", catalog.dir().display());
+
+    verb!(dbg, "\n  Checking platform models belong to their make...");
+    let mismatches: Vec<config::ConfigInvalid> = cfg.check_platforms(&catalog);
+    if !mismatches.is_empty() {
+        for e in mismatches {
+            crit!(dbg, "* {}", e);
+        }
+        return Err(AppError::ConfigInvalid);
+    }
+
+    let mut buildable: bool = true;
+
+    // build a device per platform, aggregating errors
+    info!(dbg, "\nChecking platforms are supported...");
+    let mut platform_devices: Vec<Device> = vec![];
+    for platform in cfg.deployment.platforms() {
+        match Device::build(
+            names.first().map_or("", String::as_str),
+            &platform.make,
+            &platform.model,
+            &cfg.deployment.ingress.interfaces,
+            &cfg.deployment.egress.interfaces,
+            &catalog,
+            dbg,
+        ) {
+            Ok(device) => platform_devices.push(device),
+            Err(e) => {
+                crit!(dbg, "{}", e);
+                buildable = false;
+            }
+        }
+    }
+    let mut warnings: Vec<String> = vec![];
+    verb!(dbg, "\nChecking interface patterns are anchored...");
+    for device in &platform_devices {
+        let Ok(platform) = catalog.load(&device.make) else {
+            continue;
+        };
+        let Some(model) = platform.lookup_model(&device.model) else {
+            continue;
+        };
+        for pattern in model.unanchored_interfaces() {
+            match strict_anchors {
+                true => {
+                    let e = device::InterfaceErrors::PatternUnanchored {
+                        model: device.model.clone(),
+                        pattern: pattern.to_string(),
+                    };
+                    crit!(dbg, "* {}: {}", device.make, e);
+                    buildable = false;
+                }
+                false => {
+                    let warning: String = format!(
+                        "{} {}: interface pattern '{}' is unanchored, so it matches any interface containing it",
+                        device.make, device.model, pattern
+                    );
+                    warn!(dbg, "* {}", warning);
+                    warnings.push(warning);
+                }
+            }
+        }
+    }
+    match buildable {
+        true => info!(dbg, "Platforms are supported."),
+        false => info!(dbg, "Platforms are not supported."),
+    }
+
+    verb!(dbg, "\nChecking deployable directions have interfaces...");
+    for device in &platform_devices {
+        for (name, direction, paths) in [
+            ("ingress", &cfg.deployment.ingress, &device.paths.ingress),
+            ("egress", &cfg.deployment.egress, &device.paths.egress),
+        ] {
+            if direction.deployable && paths.is_empty() {
+                let warning: String = format!(
+                    "{} {}: {} has no interfaces, so its rulesets apply nowhere",
+                    device.make, device.model, name
+                );
+                warn!(dbg, "* {}", warning);
+                warnings.push(warning);
+            }
+        }
+    }
+
+    verb!(dbg, "\nChecking zones apply to device interfaces...");
+    for device in &platform_devices {
+        for (name, direction, paths) in [
+            ("ingress", &cfg.deployment.ingress, &device.paths.ingress),
+            ("egress", &cfg.deployment.egress, &device.paths.egress),
+        ] {
+            if !direction.deployable {
+                continue;
+            }
+            for (zone, iface) in unapplied_zone_interfaces(direction, paths) {
+                let warning: String = format!(
+                    "{} {}: {} zone {} interface {} is not among the device {} paths",
+                    device.make, device.model, name, zone, iface, name
+                );
+                warn!(dbg, "* {}", warning);
+                warnings.push(warning);
+            }
+        }
+    }
+
+    // name a copy of each platform device after every device in the deployment
+    let devices: Vec<Device> = platform_devices
+        .iter()
+        .flat_map(|device| {
+            names.iter().map(|name| Device {
+                name: name.clone(),
+                ..device.clone()
+            })
+        })
+        .collect();
+
+    // build a vec of validated rulesets
+    info!(dbg, "\nLoading rulesets...");
+    dbug!(dbg, "{:#?}", &cfg.deployment.rulesets);
+    let acls_paths: Vec<String> = cfg
+        .deployment
+        .rulesets
+        .iter()
+        .map(|ruleset| match bundle {
+            true => format!("{}#{}", config_path, ruleset),
+            false => format!("{}/{}.acl", &env.rulesets, ruleset),
+        })
+        .collect();
+    let loaded: Vec<ruleset::LoadResult> = match bundle {
+        true => acls_paths
+            .iter()
+            .zip(&cfg.deployment.rulesets)
+            .map(|(source, name)| {
+                Ruleset::load_inline(source, &cfg.rulesets[name], &cfg.defaults.syntax, expand)
+            })
+            .collect(),
+        false => ruleset::load_all(&acls_paths, &cfg.defaults.syntax, expand, dbg),
+    };
+    let mut rulesets: Vec<Ruleset> = vec![];
+    let mut rulesets_valid: bool = true;
+    let mut rule_errors: RuleErrors = RuleErrors::new();
+    let seed: Ruleset = match prologue {
+        Some(path) => {
+            verb!(dbg, "  Loading prologue rules: {}", path);
+            let lines: Vec<String> = std::fs::read_to_string(path)
+                .map_err(|e| AppError::ConfigLoad(format!("{}: {}", path, e).into()))?
+                .lines()
+                .map(String::from)
+                .collect();
+            let source: String = format!("{} (prologue)", path);
+            match Ruleset::load_inline(&source, &lines, &cfg.defaults.syntax, expand) {
+                Ok(seed) => seed,
+                Err(e) => {
+                    crit!(dbg, "* Prologue issues found while parsing:\n{}", e);
+                    if let Some(e) = e.downcast_ref::<RuleErrors>() {
+                        rule_errors.extend(e.clone());
+                    }
+                    rulesets_valid = false;
+                    Ruleset::default()
+                }
+            }
+        }
+        None => Ruleset::default(),
+    };
+    for ((acls_path, name), loaded) in acls_paths.iter().zip(&cfg.deployment.rulesets).zip(loaded) {
+        match loaded {
+            Ok(ruleset) => {
+                // prologue rules come first, so they match ahead of the ruleset's own
+                // - they keep their prologue source, so their errors are located there
+                let mut seeded: Ruleset = seed.clone();
+                seeded.merge(ruleset);
+                let ruleset: Ruleset = seeded;
+                let ruleset: Ruleset = match ruleset.resolve_objects(&cfg.objects) {
+                    Ok(ruleset) => ruleset,
+                    Err(mut e) => {
+                        e.update_paths(acls_path);
+                        crit!(dbg, "* Ruleset address objects undefined:\n{}", e);
+                        rule_errors.extend(e);
+                        rulesets_valid = false;
+                        continue;
+                    }
+                };
+                let family: Family = cfg
+                    .deployment
+                    .families
+                    .get(name)
+                    .copied()
+                    .unwrap_or_default();
+                if let Err(mut e) = ruleset.check_family(family) {
+                    e.update_paths(acls_path);
+                    crit!(dbg, "* Ruleset rules outside its {} family:\n{}", family, e);
+                    rule_errors.extend(e);
+                    rulesets_valid = false;
+                }
+                let ruleset: Ruleset = match include_disabled {
+                    true => ruleset,
+                    false => ruleset.without_disabled(),
+                };
+                verb!(dbg, "{}", &ruleset.to_string());
+                if let Err(e) = ruleset.check_size(max_rules) {
+                    crit!(dbg, "* {}: {}", acls_path, e);
+                    rulesets_valid = false;
+                }
+                // acl entries are counted as deployed, with return rules and the default
+                for device in &platform_devices {
+                    let Some(max) = device.max_acl_entries else {
+                        continue;
+                    };
+                    for (direction_name, direction) in [
+                        ("ingress", &cfg.deployment.ingress),
+                        ("egress", &cfg.deployment.egress),
+                    ] {
+                        if !direction.deployable {
+                            continue;
+                        }
+                        let closed: Ruleset = context::close_ruleset(direction, &ruleset);
+                        if let Err(e) = closed.check_size(max) {
+                            crit!(
+                                dbg,
+                                "* {}: {} {} {} acl entries: {}",
+                                acls_path,
+                                device.make,
+                                device.model,
+                                direction_name,
+                                e
+                            );
+                            rulesets_valid = false;
+                        }
+                    }
+                }
+                for i in ruleset.find_unreachable() {
+                    if let Some(rule) = ruleset.get(i) {
+                        let warning: String = format!(
+                            "{}: rule unreachable after a preceding rule shadowing it: {}",
+                            acls_path, rule
+                        );
+                        warn!(dbg, "* {}", warning);
+                        warnings.push(warning);
+                    }
+                }
+                for (i, j) in ruleset.find_conflicts() {
+                    if let (Some(first), Some(second)) = (ruleset.get(i), ruleset.get(j)) {
+                        let warning: String = format!(
+                            "{}: rules conflict on the same match: {} and {}",
+                            acls_path, first, second
+                        );
+                        warn!(dbg, "* {}", warning);
+                        warnings.push(warning);
+                    }
+                }
+                for i in ruleset.find_unexpected_ports(&cfg.defaults.unexpected_ports) {
+                    if let Some(rule) = ruleset.get(i) {
+                        let warning: String = format!(
+                            "{}: rule names an unexpected service port: {}",
+                            acls_path, rule
+                        );
+                        warn!(dbg, "* {}", warning);
+                        warnings.push(warning);
+                    }
+                }
+                for (name, direction) in [
+                    ("ingress", &cfg.deployment.ingress),
+                    ("egress", &cfg.deployment.egress),
+                ] {
+                    if !direction.deployable {
+                        continue;
+                    }
+                    // rulesets are closed by their own terminal deny or a denying default
+                    let default_denies: bool =
+                        direction.default_action().is_some_and(|a| a.is_deny());
+                    if !default_denies && !ruleset.is_closed() {
+                        let warning: String = format!(
+                            "{}: ruleset is open, with no terminal deny and an {} default of {}",
+                            acls_path, name, direction.default
+                        );
+                        warn!(dbg, "* {}", warning);
+                        warnings.push(warning);
+                    }
+                    if let Err(mut e) =
+                        ruleset.check_filters(&direction.filters.src, &direction.filters.dst)
+                    {
+                        e.update_paths(acls_path);
+                        crit!(dbg, "* Ruleset prefixes outside {} filters:\n{}", name, e);
+                        rule_errors.extend(e);
+                        rulesets_valid = false;
+                    }
+                }
+                rulesets.push(ruleset)
+            }
+            Err(e) => {
+                crit!(dbg, "* Ruleset issues found while parsing:\n{}", e);
+                if let Some(e) = e.downcast_ref::<RuleErrors>() {
+                    rule_errors.extend(e.clone());
+                }
+                rulesets_valid = false;
+            }
+        }
+    }
+    match rulesets_valid {
+        true => info!(dbg, "Valid rules provided in rulesets."),
+        false => info!(dbg, "Invalid rules provided in rulesets."),
+    }
+
+    if !(buildable && rulesets_valid) {
+        return Err(AppError::Unbuildable(rule_errors));
+    }
+
+    Ok(CompileOutput {
+        config: cfg,
+        devices,
+        rulesets,
+        warnings,
+    })
+}
@@ -1,4 +1,23 @@
 #![allow(dead_code)]
+use std::sync::OnceLock;
+
+/// output format for log lines, set once at startup
+static FORMAT: OnceLock<LogFormat> = OnceLock::new();
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+/// selects the log output format, only the first call takes effect
+pub fn set_format(format: LogFormat) {
+    let _ = FORMAT.set(format);
+}
+
+fn format() -> LogFormat {
+    *FORMAT.get().unwrap_or(&LogFormat::Text)
+}
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum LogLevel {
@@ -14,26 +33,98 @@ impl LogLevel {
     pub fn value(&self) -> u8 {
         match self {
             LogLevel::Debug => u8::MIN,
-            LogLevel::Verbose => u8::from(30),
-            LogLevel::Info => u8::from(60),
-            LogLevel::Warning => u8::from(90),
-            LogLevel::Critical => u8::from(120),
+            LogLevel::Verbose => 30,
+            LogLevel::Info => 60,
+            LogLevel::Warning => 90,
+            LogLevel::Critical => 120,
             LogLevel::None => u8::MAX,
         }
     }
 }
 
+impl LogLevel {
+    /// level name used in timestamped log prefixes
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Verbose => "VERBOSE",
+            LogLevel::Info => "INFO",
+            LogLevel::Warning => "WARN",
+            LogLevel::Critical => "CRIT",
+            LogLevel::None => "NONE",
+        }
+    }
+}
+
 impl std::fmt::Display for LogLevel {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{:?}", self)
     }
 }
 
+/// writes a log line emitted by one of the log macros
+/// - `warn!` and `crit!` write to stderr, everything else to stdout
+/// - when `AM3K_LOG_TIMESTAMPS=1`, each line is prefixed with a UTC timestamp and level name
+/// - under `LogFormat::Json`, each message is a single json object instead
+pub fn emit(level: LogLevel, msg: &str) {
+    let line: String = match format() {
+        LogFormat::Text => format_line(level, msg, timestamps_enabled()),
+        LogFormat::Json => format_json(level, msg, timestamps_enabled()),
+    };
+    match level {
+        LogLevel::Warning | LogLevel::Critical => eprintln!("{}", line),
+        _ => println!("{}", line),
+    }
+}
+
+fn timestamps_enabled() -> bool {
+    matches!(std::env::var("AM3K_LOG_TIMESTAMPS").as_deref(), Ok("1"))
+}
+
+fn timestamp() -> String {
+    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+/// formats `{"level":"info","msg":"..."}`, trimming the blank lines used to space text output
+fn format_json(level: LogLevel, msg: &str, timestamps: bool) -> String {
+    let mut line = serde_json::Map::new();
+    if timestamps {
+        line.insert(String::from("ts"), serde_json::Value::from(timestamp()));
+    }
+    line.insert(
+        String::from("level"),
+        serde_json::Value::from(level.name().to_lowercase()),
+    );
+    line.insert(
+        String::from("msg"),
+        serde_json::Value::from(msg.trim_matches('\n')),
+    );
+    serde_json::Value::Object(line).to_string()
+}
+
+fn format_line(level: LogLevel, msg: &str, timestamps: bool) -> String {
+    if !timestamps {
+        return match level {
+            LogLevel::Debug => format!("[Debug]{}", msg),
+            _ => msg.to_string(),
+        };
+    }
+
+    let prefix: String = format!("{} [{}]", timestamp(), level.name());
+    msg.split('\n')
+        .map(|line| match line.is_empty() {
+            true => String::new(),
+            false => format!("{} {}", prefix, line),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
 #[macro_export]
 macro_rules! info {
     ($current_level:expr, $($msg:expr),*) => {
         if LogLevel::Info.value() >= $current_level.value() {
-            println!($($msg),*);
+            $crate::log::emit(LogLevel::Info, &format!($($msg),*));
         }
     };
 }
@@ -42,7 +133,7 @@ macro_rules! info {
 macro_rules! verb {
     ($current_level:expr, $($msg:expr),*) => {
         if LogLevel::Verbose.value() >= $current_level.value() {
-            println!($($msg),*);
+            $crate::log::emit(LogLevel::Verbose, &format!($($msg),*));
         }
     };
 }
@@ -51,8 +142,7 @@ macro_rules! verb {
 macro_rules! dbug {
     ($current_level:expr, $($msg:expr),*) => {
         if LogLevel::Debug.value() >= $current_level.value() {
-            print!("[Debug]");
-            println!($($msg),*);
+            $crate::log::emit(LogLevel::Debug, &format!($($msg),*));
         }
     };
 }
@@ -61,7 +151,7 @@ macro_rules! dbug {
 macro_rules! warn {
     ($current_level:expr, $($msg:expr),*) => {
         if LogLevel::Warning.value() >= $current_level.value() {
-            eprintln!($($msg),*);
+            $crate::log::emit(LogLevel::Warning, &format!($($msg),*));
         }
     };
 }
@@ -70,7 +160,37 @@ macro_rules! warn {
 macro_rules! crit {
     ($current_level:expr, $($msg:expr),*) => {
         if LogLevel::Critical.value() >= $current_level.value() {
-            eprintln!($($msg),*);
+            $crate::log::emit(LogLevel::Critical, &format!($($msg),*));
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    #[test]
+    fn timestamp_prefix_uses_emitting_level() {
+        let line: String = format_line(LogLevel::Warning, "\nsomething odd", true);
+        let pattern =
+            Regex::new(r"^\n\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}Z \[WARN\] something odd$").unwrap();
+        assert!(pattern.is_match(&line), "{}", line);
+    }
+
+    #[test]
+    fn json_line_parses_with_fields() {
+        let line: String = format_json(LogLevel::Info, "\nLoading rulesets...", false);
+        let parsed: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(
+            parsed,
+            serde_json::json!({"level": "info", "msg": "Loading rulesets..."})
+        );
+    }
+
+    #[test]
+    fn plain_lines_without_timestamps() {
+        assert_eq!(format_line(LogLevel::Info, "hello", false), "hello");
+        assert_eq!(format_line(LogLevel::Debug, "hello", false), "[Debug]hello");
+    }
+}
@@ -1,61 +1,844 @@
 #![allow(dead_code)]
-use crate::{verb, LogLevel};
+use crate::{config::is_name_safe, verb, LogLevel};
 
-use serde::Serialize;
-use std::{error::Error, fmt, path::PathBuf, str::FromStr, vec::IntoIter};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    vec::IntoIter,
+};
 use thiserror::Error;
 
-#[derive(Debug, PartialEq, Clone, Serialize)]
+/// from→to prefix rewrites applied by `Rule::transform`
+pub type PrefixMap = BTreeMap<String, String>;
+
+/// named address objects, each listing member prefixes referenced as `@name`
+pub type ObjectMap = BTreeMap<String, Vec<String>>;
+
+/// gap between sequence numbers assigned by `Ruleset::sequence`
+pub const SEQ_STEP: u32 = 10;
+
+/// next multiple of `SEQ_STEP` after a sequence number
+fn next_seq(seq: u32) -> u32 {
+    (seq / SEQ_STEP).saturating_add(1).saturating_mul(SEQ_STEP)
+}
+
+/// result of loading a single ruleset file
+pub type LoadResult = Result<Ruleset, Box<dyn Error + Send + Sync>>;
+
+/// how ruleset text is read, set from the site configuration `defaults`
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct Syntax {
+    /// marks the rest of a line as a comment, e.g. `#`, `;` or `//`
+    #[serde(default = "Syntax::default_comment_prefix")]
+    pub comment_prefix: String,
+    /// reads keywords, such as actions, protocols, and `any`, only when written in lowercase
+    /// - keywords are read regardless of case by default, and named prefixes always keep their case
+    #[serde(default)]
+    pub case_sensitive: bool,
+}
+
+impl Syntax {
+    fn default_comment_prefix() -> String {
+        String::from("#")
+    }
+
+    /// strips any comment from a line, returning `None` when no rule remains
+    /// - keywords are lowercased in place unless `case_sensitive`, keeping columns intact
+    fn read<'a>(&self, line: &'a str) -> Option<std::borrow::Cow<'a, str>> {
+        let rule: &str = match self.comment_prefix.is_empty() {
+            true => line,
+            false => line
+                .split_once(self.comment_prefix.as_str())
+                .map_or(line, |(rule, _)| rule),
+        };
+        match (rule.trim().is_empty(), self.case_sensitive) {
+            (true, _) => None,
+            (false, false) => Some(std::borrow::Cow::Owned(fold_keywords(rule))),
+            (false, true) => Some(std::borrow::Cow::Borrowed(rule)),
+        }
+    }
+
+    /// reads an `include <name>` directive as the included name and its 1-based column
+    fn include(&self, line: &str) -> Option<(String, usize)> {
+        let rule = self.read(line)?;
+        match rule.split_whitespace().collect::<Vec<&str>>()[..] {
+            ["include", _] => Some((
+                String::from(line.split_whitespace().nth(1)?),
+                field_columns(line)[1],
+            )),
+            _ => None,
+        }
+    }
+
+    /// captures a comment of the form `desc: ...` as the rule description, whitespace and all
+    /// - e.g. `allow tcp inside any outside 22 # desc: mgmt ssh` is described as `mgmt ssh`
+    fn description(&self, line: &str) -> Option<String> {
+        if self.comment_prefix.is_empty() {
+            return None;
+        }
+        let (_, comment) = line.split_once(self.comment_prefix.as_str())?;
+        let desc: &str = comment.trim().strip_prefix("desc:")?.trim();
+        match desc.is_empty() {
+            true => None,
+            false => Some(String::from(desc)),
+        }
+    }
+}
+
+impl Default for Syntax {
+    fn default() -> Self {
+        Syntax {
+            comment_prefix: Self::default_comment_prefix(),
+            case_sensitive: false,
+        }
+    }
+}
+
+/// lowercases every field of a rule but its prefixes, which fold only as `any` wildcards
+/// - a leading `disabled` or `seq=N` token is lowercased, but not counted as a field
+/// - whitespace is kept, so error columns still point into the original line
+fn fold_keywords(rule: &str) -> String {
+    let markers: usize = leading_markers(rule);
+    let mut folded: String = String::with_capacity(rule.len());
+    let mut token: String = String::new();
+    let mut i: usize = 0;
+    for c in rule.chars() {
+        match c.is_whitespace() {
+            true => {
+                if !token.is_empty() {
+                    folded.push_str(&fold_keyword(i.checked_sub(markers), &token));
+                    token.clear();
+                    i += 1;
+                }
+                folded.push(c);
+            }
+            false => token.push(c),
+        }
+    }
+    folded.push_str(&fold_keyword(i.checked_sub(markers), &token));
+    folded
+}
+
+/// lowercases a token of the numbered rule field, or of a leading marker when `None`
+fn fold_keyword(field: Option<usize>, token: &str) -> String {
+    let lower: String = token.to_lowercase();
+    match (field, lower.trim_start_matches('!')) {
+        (Some(2 | 4), "any" | "any4" | "any6") => lower,
+        (Some(2 | 4), _) => token.to_string(),
+        _ => lower,
+    }
+}
+
+/// counts the leading `disabled` and `seq=N` tokens preceding the fields of a rule
+fn leading_markers(rule: &str) -> usize {
+    let tokens: Vec<String> = rule
+        .split_whitespace()
+        .take(2)
+        .map(str::to_lowercase)
+        .collect();
+    let disabled: bool = matches!(tokens.first().map(String::as_str), Some("disabled" | "!"));
+    let seq: bool = tokens
+        .get(disabled as usize)
+        .is_some_and(|token| token.starts_with("seq="));
+    disabled as usize + seq as usize
+}
+
+/// locates keywords not written in lowercase, as the field error each reads as under `case_sensitive`
+/// - prefixes are names, and keep their case, so only actions, protocols & ports are checked
+fn miscased_keywords(rule: &str) -> Vec<(FieldError, usize)> {
+    let markers: usize = leading_markers(rule);
+    rule.split_whitespace()
+        .zip(field_columns(rule))
+        .enumerate()
+        .filter_map(|(i, (token, column))| {
+            let field: Option<usize> = i.checked_sub(markers);
+            let error: FieldError = match field {
+                Some(0) => FieldError::ActionInvalid,
+                Some(1) => FieldError::ProtocolUnsupported,
+                Some(3 | 5) => FieldError::PortInvalid,
+                _ => return None,
+            };
+            (fold_keyword(field, token) != token).then_some((error, column))
+        })
+        .collect()
+}
+
+/// loads each ruleset file, preserving input order, expanded as by `Ruleset::load`
+/// - parses files concurrently when built with the `parallel` feature
+pub fn load_all(
+    acls_paths: &[String],
+    syntax: &Syntax,
+    expand: bool,
+    dbg: LogLevel,
+) -> Vec<LoadResult> {
+    #[cfg(feature = "parallel")]
+    return load_parallel(acls_paths, syntax, expand, dbg);
+    #[cfg(not(feature = "parallel"))]
+    return load_sequential(acls_paths, syntax, expand, dbg);
+}
+
+fn load_sequential(
+    acls_paths: &[String],
+    syntax: &Syntax,
+    expand: bool,
+    dbg: LogLevel,
+) -> Vec<LoadResult> {
+    acls_paths
+        .iter()
+        .map(|acls_path| Ruleset::load(acls_path, syntax, expand, dbg))
+        .collect()
+}
+
+/// spreads files across one worker per available core, then restores input order
+#[cfg(feature = "parallel")]
+fn load_parallel(
+    acls_paths: &[String],
+    syntax: &Syntax,
+    expand: bool,
+    dbg: LogLevel,
+) -> Vec<LoadResult> {
+    let workers: usize = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(acls_paths.len().max(1));
+
+    let mut indexed: Vec<(usize, LoadResult)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|worker| {
+                scope.spawn(move || {
+                    acls_paths
+                        .iter()
+                        .enumerate()
+                        .skip(worker)
+                        .step_by(workers)
+                        .map(|(i, acls_path)| (i, Ruleset::load(acls_path, syntax, expand, dbg)))
+                        .collect::<Vec<(usize, LoadResult)>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("ruleset worker panicked"))
+            .collect()
+    });
+
+    indexed.sort_by_key(|(i, _)| *i);
+    indexed.into_iter().map(|(_, result)| result).collect()
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Serialize)]
 pub struct Ruleset(Vec<Rule>);
 
+/// rule counts for a ruleset after expansion, keyed by lowercase action and protocol
+#[derive(Debug, Default, PartialEq, Clone, Serialize)]
+pub struct RulesetStats {
+    pub total: usize,
+    pub actions: BTreeMap<String, usize>,
+    pub protocols: BTreeMap<String, usize>,
+}
+
 impl Ruleset {
-    pub fn load(acls_path: &str, dbg: LogLevel) -> Result<Self, Box<dyn std::error::Error>> {
+    /// loads & parses a ruleset file, expanding its port lists & ranges unless `expand` is unset
+    /// - every rule error is reported, located at `acls_path` and its 1-based file line & column
+    /// - read failures are prefixed with `acls_path`
+    /// - an `include <name>` line splices in the rules of `<name>.acl` from the same directory,
+    ///   whose errors, including those found after loading, are located in the included file
+    pub fn load(acls_path: &str, syntax: &Syntax, expand: bool, dbg: LogLevel) -> LoadResult {
         verb!(dbg, "  Loading ruleset file: {}", acls_path);
-        let rs_file: &Vec<String> = &std::fs::read_to_string(PathBuf::from(acls_path))?
-            .lines()
-            .map(String::from)
-            .collect();
+        let rs: Ruleset = Self::load_including(acls_path, syntax, expand, &mut vec![], dbg)?;
+        verb!(dbg, "  Ruleset file loaded successfully from yaml.");
+
+        match expand {
+            true => Ok(rs.expand()),
+            false => Ok(rs),
+        }
+    }
+
+    /// parses a ruleset file and, recursively, the files it includes
+    /// - `including` holds the files still being read, so an include of any of them is a cycle
+    fn load_including(
+        acls_path: &str,
+        syntax: &Syntax,
+        expand: bool,
+        including: &mut Vec<PathBuf>,
+        dbg: LogLevel,
+    ) -> LoadResult {
+        let file: File =
+            File::open(PathBuf::from(acls_path)).map_err(|e| format!("{}: {}", acls_path, e))?;
+        let dir: PathBuf = Path::new(acls_path)
+            .parent()
+            .map_or_else(PathBuf::new, Path::to_path_buf);
+        let canonical = |path: &Path| std::fs::canonicalize(path).unwrap_or(path.to_path_buf());
+
+        // (line, name, column) of each include directive
+        let mut directives: Vec<(usize, String, usize)> = vec![];
+        let own = Self::read(BufReader::new(file), syntax, Some(&mut directives));
+
+        let mut errors: RuleErrors = RuleErrors::new();
+        let mut included: Vec<(usize, Ruleset)> = vec![];
+        including.push(canonical(Path::new(acls_path)));
+        for (line, name, column) in directives {
+            let path: PathBuf = dir.join(format!("{}.acl", name));
+            let location = || Location::new(String::from(acls_path), line, column);
+            if !is_name_safe(&name) {
+                errors.push(FieldError::IncludeNameInvalid, location());
+                continue;
+            }
+            if !path.is_file() {
+                errors.push(FieldError::IncludeUndefined, location());
+                continue;
+            }
+            if including.contains(&canonical(&path)) {
+                errors.push(FieldError::IncludeCycle, location());
+                continue;
+            }
+            verb!(dbg, "  Including ruleset file: {}", path.display());
+            match Self::load_including(&path.to_string_lossy(), syntax, expand, including, dbg) {
+                Ok(ruleset) => included.push((line, ruleset)),
+                Err(e) => errors.extend(*e.downcast::<RuleErrors>()?),
+            }
+        }
+        including.pop();
 
-        let rs: Ruleset = match Self::from_vec(rs_file) {
-            Ok(ruleset) => ruleset,
+        let own: Ruleset = match own.and_then(|ruleset| match expand {
+            true => ruleset.check_expansion().map(|_| ruleset),
+            false => Ok(ruleset),
+        }) {
+            Ok(ruleset) => ruleset.read_from(acls_path),
             Err(mut e) => {
                 e.update_paths(acls_path);
-                return Err(Box::new(e));
+                errors.extend(e);
+                Ruleset(vec![])
             }
         };
-        verb!(dbg, "  Ruleset file loaded successfully from yaml.");
+        if !errors.is_empty() {
+            return Err(Box::new(errors));
+        }
+
+        // splices each included ruleset ahead of the rules following its directive
+        let mut spliced: Vec<Rule> = vec![];
+        let mut included = included.into_iter().peekable();
+        for rule in own {
+            while let Some((_, ruleset)) =
+                included.next_if(|(line, _)| Some(*line) < rule.source_line)
+            {
+                spliced.extend(ruleset);
+            }
+            spliced.push(rule);
+        }
+        spliced.extend(included.flat_map(|(_, ruleset)| ruleset));
+        Ok(Ruleset(spliced))
+    }
+
+    /// parses rules provided in place of a ruleset file, expanded as by `load`
+    /// - errors are located at `source`, e.g. `site.yaml#web`
+    pub fn load_inline(
+        source: &str,
+        rules: &[String],
+        syntax: &Syntax,
+        expand: bool,
+    ) -> LoadResult {
+        let parsed = Self::from_vec_with(rules, syntax).and_then(|ruleset| match expand {
+            true => ruleset.check_expansion().map(|_| ruleset.expand()),
+            false => Ok(ruleset),
+        });
+        match parsed {
+            Ok(ruleset) => Ok(ruleset.read_from(source)),
+            Err(mut e) => {
+                e.update_paths(source);
+                Err(Box::new(e))
+            }
+        }
+    }
 
-        Ok(rs.expand())
+    /// records `path` as where each rule was read from, locating later errors there
+    fn read_from(mut self, path: &str) -> Self {
+        let path: Arc<str> = Arc::from(path);
+        for rule in self.0.iter_mut() {
+            rule.source.path = Some(Arc::clone(&path));
+        }
+        self
     }
 
     pub fn push(&mut self, rule: Rule) {
         self.0.push(rule);
     }
 
+    pub fn get(&self, index: usize) -> Option<&Rule> {
+        self.0.get(index)
+    }
+
+    /// serializes rules as canonical `.acl` text, one rule per line with single spaces
+    /// - the inverse of `from_vec`, so re-parsing the text yields the same rules
+    /// - implicit rules are omitted, as am3k appends them rather than reading them
+    /// - descriptions are written back as a trailing `# desc: ...` comment
+    pub fn to_acl(&self) -> String {
+        self.0
+            .iter()
+            .filter(|rule| !rule.implicit)
+            .map(|rule| match &rule.description {
+                Some(desc) => format!("{} # desc: {}\n", rule, desc),
+                None => format!("{}\n", rule),
+            })
+            .collect()
+    }
+
+    /// appends the rules of another ruleset, such that `self` is the base and `other` overrides
+    /// - rules keep their source line, so errors still point into their original file
+    pub fn merge(&mut self, other: Ruleset) {
+        self.0.extend(other.0);
+    }
+
     /// parses rules from vec of strings to validated rules that may require expansion
-    fn from_vec(raw_rules: &Vec<String>) -> Result<Self, RuleErrors> {
+    /// - reads the default `Syntax`, so `#` starts a comment and keywords are lowercase
+    pub fn from_vec(raw_rules: &[String]) -> Result<Self, RuleErrors> {
+        Self::from_vec_with(raw_rules, &Syntax::default())
+    }
+
+    /// parses rules as `from_vec`, reading comments and keywords with the provided syntax
+    /// - blank and comment-only lines are skipped, keeping line numbers of the rest
+    /// - a `desc: ...` comment is kept as the description of the rule it follows
+    /// - a trailing `\r` is dropped, so CRLF lines parse as their LF equivalents
+    pub fn from_vec_with(raw_rules: &[String], syntax: &Syntax) -> Result<Self, RuleErrors> {
+        let mut ruleset: Ruleset = Ruleset(Vec::new());
+        let mut errors: RuleErrors = RuleErrors::new();
+
+        for (i, line) in raw_rules.iter().enumerate() {
+            ruleset.parse_line(i + 1, line, syntax, &mut errors);
+        }
+
+        match errors.is_empty() {
+            true => Ok(ruleset),
+            false => Err(errors),
+        }
+    }
+
+    /// parses rules as `from_vec`, a line at a time, without holding every line in memory
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, RuleErrors> {
+        Self::from_reader_with(reader, &Syntax::default())
+    }
+
+    /// parses rules as `from_reader`, reading comments and keywords with the provided syntax
+    /// - a line failing to read, e.g. as invalid utf-8, errs with `LineUnreadable` and ends parsing
+    pub fn from_reader_with<R: BufRead>(reader: R, syntax: &Syntax) -> Result<Self, RuleErrors> {
+        Self::read(reader, syntax, None)
+    }
+
+    /// parses rules a line at a time, collecting include directives in place of parsing them
+    /// - directives are only read when `directives` is provided, as `(line, name, column)`
+    fn read<R: BufRead>(
+        reader: R,
+        syntax: &Syntax,
+        mut directives: Option<&mut Vec<(usize, String, usize)>>,
+    ) -> Result<Self, RuleErrors> {
         let mut ruleset: Ruleset = Ruleset(Vec::new());
         let mut errors: RuleErrors = RuleErrors::new();
 
-        for (i, rule) in raw_rules.iter().enumerate() {
-            match Rule::from_str(rule) {
-                Ok(r) => ruleset.push(r),
-                Err((e, mut loc)) => {
-                    loc.line = i + 1;
+        for (i, line) in reader.lines().enumerate() {
+            let Ok(line) = line else {
+                errors.push(
+                    FieldError::LineUnreadable,
+                    Location::new(String::new(), i + 1, 1),
+                );
+                break;
+            };
+            if let Some(directives) = directives.as_mut() {
+                if let Some((name, column)) = syntax.include(&line) {
+                    directives.push((i + 1, name, column));
+                    continue;
+                }
+            }
+            ruleset.parse_line(i + 1, &line, syntax, &mut errors);
+        }
+
+        match errors.is_empty() {
+            true => Ok(ruleset),
+            false => Err(errors),
+        }
+    }
+
+    /// parses a single numbered line, pushing its rule or each of its errors
+    fn parse_line(&mut self, line_no: usize, line: &str, syntax: &Syntax, errors: &mut RuleErrors) {
+        let line: &str = line.strip_suffix('\r').unwrap_or(line);
+        let Some(rule) = syntax.read(line) else {
+            return;
+        };
+        if syntax.case_sensitive {
+            let miscased: Vec<(FieldError, usize)> = miscased_keywords(&rule);
+            if !miscased.is_empty() {
+                for (e, column) in miscased {
+                    errors.push(e, Location::new(String::new(), line_no, column));
+                }
+                return;
+            }
+        }
+        match Rule::from_str(&rule) {
+            Ok(mut r) => {
+                r.source_line = Some(line_no);
+                r.description = syntax.description(line);
+                self.push(r)
+            }
+            Err(errs) => {
+                for (e, mut loc) in errs {
+                    loc.line = line_no;
                     errors.push(e, loc)
                 }
+            }
+        };
+    }
+
+    /// flags rules whose src & dst ports are both port lists, as only one of them may expand
+    /// - errs with `RuleExpansionUnsupported` at the line of each, leaving unexpanded rules valid
+    pub fn check_expansion(&self) -> Result<(), RuleErrors> {
+        let mut errors: RuleErrors = RuleErrors::new();
+        for rule in self.0.iter() {
+            if rule.src_port.is_list() && rule.dst_port.is_list() {
+                errors.push(
+                    FieldError::RuleExpansionUnsupported,
+                    Location {
+                        column: 1,
+                        ..rule.locate(0)
+                    },
+                );
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+
+    /// drops disabled rules, leaving only those that render
+    pub fn without_disabled(self) -> Self {
+        Ruleset(self.0.into_iter().filter(|rule| rule.enabled).collect())
+    }
+
+    pub fn expand(self) -> Self {
+        Ruleset(self.into_iter().flat_map(|rule| rule.expand()).collect())
+    }
+
+    /// counts rules as parsed, before expansion, from runs of rules sharing a source line
+    /// - rules spliced in from an included file differ by path, though not always by line
+    pub fn parsed_len(&self) -> usize {
+        self.0
+            .iter()
+            .enumerate()
+            .filter(|(i, rule)| {
+                *i == 0 || {
+                    let before: &Rule = &self.0[i - 1];
+                    before.source_line != rule.source_line || before.source.path != rule.source.path
+                }
+            })
+            .count()
+    }
+
+    /// counts expanded rules in total, per action, and per protocol
+    pub fn stats(&self) -> RulesetStats {
+        let mut stats: RulesetStats = RulesetStats::default();
+        for rule in self.0.iter().flat_map(|rule| rule.clone().expand()) {
+            stats.total += 1;
+            *stats.actions.entry(rule.action.to_string()).or_default() += 1;
+            *stats
+                .protocols
+                .entry(rule.protocol.to_string())
+                .or_default() += 1;
+        }
+        stats
+    }
+
+    /// checks an expanded ruleset holds at most `max` rules
+    /// - errs naming the source line that expanded into the most rules
+    pub fn check_size(&self, max: usize) -> Result<(), ExpansionLimitExceeded> {
+        if self.0.len() <= max {
+            return Ok(());
+        }
+        let mut per_line: BTreeMap<usize, usize> = BTreeMap::new();
+        for rule in &self.0 {
+            *per_line.entry(rule.line()).or_default() += 1;
+        }
+        let (line, expanded): (usize, usize) = per_line
+            .into_iter()
+            .max_by_key(|&(line, expanded)| (expanded, std::cmp::Reverse(line)))
+            .unwrap_or_default();
+        Err(ExpansionLimitExceeded {
+            count: self.0.len(),
+            max,
+            line,
+            expanded,
+        })
+    }
+
+    /// whether the last enabled rule denies all traffic, e.g. `deny ip any any any any`
+    /// - `denylog` closes the ruleset too, as does an implicit deny from `with_default`
+    /// - an open ruleset passes whatever its rules leave unmatched
+    pub fn is_closed(&self) -> bool {
+        self.0
+            .iter()
+            .rfind(|rule| rule.enabled)
+            .is_some_and(|rule| {
+                rule.action.is_deny()
+                    && rule.protocol == Protocol::IP
+                    && rule.src_prefix == "any"
+                    && rule.src_port == PortType::Any
+                    && rule.dst_prefix == "any"
+                    && rule.dst_port == PortType::Any
+                    && !rule.negated.any()
+                    && !rule.established
+            })
+    }
+
+    /// flags indices of rules that can never match because an earlier rule shadows them
+    /// - `ip` contains every protocol, so e.g. `allow ip inside any outside any` shadows
+    ///   a following `deny tcp inside any outside 22`
+    /// - implicit rules are never flagged, as they close every ruleset
+    /// - disabled rules neither shadow nor are flagged, as they match nothing
+    pub fn find_unreachable(&self) -> Vec<usize> {
+        self.0
+            .iter()
+            .enumerate()
+            .filter(|(i, rule)| {
+                !rule.implicit
+                    && rule.enabled
+                    && self.0[..*i]
+                        .iter()
+                        .any(|earlier| earlier.enabled && earlier.shadows(rule))
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// flags index pairs of rules matching identical traffic, where one allows and one denies
+    /// - the match is the protocol, prefixes, ports, negations, and `established`
+    /// - rules differing only in logging agree, and implicit & disabled rules are never flagged
+    pub fn find_conflicts(&self) -> Vec<(usize, usize)> {
+        let rules: Vec<(usize, &Rule)> = self
+            .0
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| !r.implicit && r.enabled)
+            .collect();
+        rules
+            .iter()
+            .enumerate()
+            .flat_map(|(n, (i, rule))| {
+                rules[n + 1..]
+                    .iter()
+                    .filter(move |(_, other)| {
+                        rule.action.allows() != other.action.allows() && rule.matches_same(other)
+                    })
+                    .map(move |(j, _)| (*i, *j))
+            })
+            .collect()
+    }
+
+    /// flags indexes of tcp & udp rules whose dst port is `0` or overlaps an `unexpected` port
+    /// - `any` dst ports are never flagged, nor are implicit or disabled rules
+    pub fn find_unexpected_ports(&self, unexpected: &[PortType]) -> Vec<usize> {
+        self.0
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| !r.implicit && r.enabled && r.dst_port != PortType::Any)
+            .filter(|(_, r)| {
+                matches!(r.protocol, Protocol::TCP | Protocol::UDP) && !r.negated.protocol
+            })
+            .filter(|(_, r)| {
+                r.dst_port.overlaps(&PortType::Port(0))
+                    || unexpected.iter().any(|ports| r.dst_port.overlaps(ports))
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// expands rules referencing `@name` address objects into a rule per member prefix
+    /// - rules referencing objects in both src & dst expand to every src & dst pair
+    /// - errs with `ObjectUndefined` for each reference missing from `objects`
+    pub fn resolve_objects(self, objects: &ObjectMap) -> Result<Self, RuleErrors> {
+        let mut resolved: Ruleset = Ruleset(Vec::new());
+        let mut errors: RuleErrors = RuleErrors::new();
+
+        for rule in self {
+            let members = |prefix: &str| -> Option<Vec<String>> {
+                match prefix.strip_prefix('@') {
+                    Some(name) => objects.get(name).cloned(),
+                    None => Some(vec![prefix.to_string()]),
+                }
+            };
+            let (Some(srcs), Some(dsts)) = (members(&rule.src_prefix), members(&rule.dst_prefix))
+            else {
+                // located at the src prefix, unless only the dst references an undefined object
+                let field: usize = match members(&rule.src_prefix) {
+                    Some(_) => 4,
+                    None => 2,
+                };
+                errors.push(FieldError::ObjectUndefined, rule.locate(field));
+                continue;
             };
+            for src in &srcs {
+                for dst in &dsts {
+                    let mut member: Rule = rule.clone();
+                    member.src_prefix = Prefix::from(src.as_str());
+                    member.dst_prefix = Prefix::from(dst.as_str());
+                    resolved.push(member);
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(resolved)
+    }
+
+    /// checks every rule prefix is covered by the provided src & dst filters
+    /// - filters are CIDRs or named keywords, where `any` covers all prefixes
+    /// - a negated prefix matches everything outside it, so only an `any` filter covers it
+    pub fn check_filters(&self, src: &[String], dst: &[String]) -> Result<(), RuleErrors> {
+        let mut errors: RuleErrors = RuleErrors::new();
+
+        for rule in &self.0 {
+            if rule.implicit {
+                continue;
+            }
+            for (prefix, negated, filters, field) in [
+                (&rule.src_prefix, rule.negated.src_prefix, src, 2),
+                (&rule.dst_prefix, rule.negated.dst_prefix, dst, 4),
+            ] {
+                let covered: bool = match negated {
+                    true => filters.iter().any(|filter| filter == "any"),
+                    false => is_prefix_covered(prefix, filters),
+                };
+                if covered {
+                    continue;
+                }
+                let loc = rule.locate(field);
+                if !errors
+                    .0
+                    .contains(&(FieldError::PrefixOutOfScope, loc.clone()))
+                {
+                    errors.push(FieldError::PrefixOutOfScope, loc);
+                }
+            }
         }
 
-        if errors.len() > 0 {
+        if !errors.is_empty() {
             return Err(errors);
         }
 
-        Ok(ruleset)
+        Ok(())
     }
 
-    fn expand(self) -> Self {
-        Ruleset(self.into_iter().flat_map(|rule| rule.expand()).collect())
+    /// checks every rule is of the declared address family
+    /// - rules of `any` and named prefixes belong to no family, so always pass
+    pub fn check_family(&self, family: Family) -> Result<(), RuleErrors> {
+        let mut errors: RuleErrors = RuleErrors::new();
+        for rule in &self.0 {
+            if !rule.implicit && !family.admits(rule.family()) {
+                // located at the prefix naming the family, src first
+                let field: usize = match AddrFamily::of(&rule.src_prefix) {
+                    Some(_) => 2,
+                    None => 4,
+                };
+                errors.push(FieldError::FamilyUndeclared, rule.locate(field));
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+
+    /// rewrites rule prefixes using the provided src & dst mappings
+    pub fn transform(self, src: Option<&PrefixMap>, dst: Option<&PrefixMap>) -> Self {
+        Ruleset(
+            self.into_iter()
+                .map(|rule| rule.transform(src, dst))
+                .collect(),
+        )
+    }
+
+    /// orders rules by action, protocol, src prefix & port, then dst prefix & port
+    /// - ports order by their ranges, with `any` first, so the ordering is total
+    pub fn sort(mut self) -> Self {
+        self.0.sort_by(|a, b| {
+            (&a.action, &a.protocol, &a.src_prefix)
+                .cmp(&(&b.action, &b.protocol, &b.src_prefix))
+                .then_with(|| a.src_port.sort_key().cmp(&b.src_port.sort_key()))
+                .then_with(|| a.dst_prefix.cmp(&b.dst_prefix))
+                .then_with(|| a.dst_port.sort_key().cmp(&b.dst_port.sort_key()))
+        });
+        self
+    }
+
+    /// merges adjacent rules that differ only by dst prefix, then only by src prefix,
+    /// into one rule per cidr of the minimal set covering their prefixes
+    /// - e.g. `10.0.0.0/25` and `10.0.0.128/25` merge into `10.0.0.0/24`
+    /// - rules with named, wildcard, or negated prefixes are never merged
+    pub fn aggregate_prefixes(self) -> Self {
+        let rules: Vec<Rule> = aggregate_prefixes(self.0, |rule| &mut rule.dst_prefix);
+        Ruleset(aggregate_prefixes(rules, |rule| &mut rule.src_prefix))
+    }
+
+    /// merges adjacent rules that differ only by dst port, then only by src port,
+    /// into a single rule with coalesced port ranges
+    /// - rules matching `any` port are never merged
+    pub fn compact(self) -> Self {
+        let rules: Vec<Rule> = compact_ports(self.0, |rule| &mut rule.dst_port);
+        Ruleset(compact_ports(rules, |rule| &mut rule.src_port))
+    }
+
+    /// appends an implicit terminal `ip any any any any` rule using the provided action
+    /// - numbered after the last rule when it is numbered
+    pub fn with_default(mut self, action: Action) -> Self {
+        self.push(Rule {
+            seq: self.0.last().and_then(|rule| rule.seq).map(next_seq),
+            log: action.is_logged(),
+            description: None,
+            action,
+            protocol: Protocol::IP,
+            src_prefix: Prefix::from("any"),
+            src_port: PortType::Any,
+            dst_prefix: Prefix::from("any"),
+            dst_port: PortType::Any,
+            negated: Negated::default(),
+            implicit: true,
+            enabled: true,
+            established: false,
+            source_line: None,
+            source: Source::default(),
+        });
+        self
+    }
+
+    /// numbers each unnumbered rule with the next multiple of `SEQ_STEP` after the rule before it
+    /// - explicit sequence numbers are kept, so numbering resumes after them
+    /// - the number after each rule is left for its established return rule
+    pub fn sequence(mut self) -> Self {
+        let mut last: u32 = 0;
+        for rule in self.0.iter_mut() {
+            last = *rule.seq.get_or_insert(next_seq(last.saturating_add(1)));
+        }
+        self
+    }
+
+    /// inserts an established return rule after each tcp allow rule
+    pub fn with_established(self) -> Self {
+        Ruleset(
+            self.into_iter()
+                .flat_map(|rule| {
+                    let reciprocal: Option<Rule> = rule.reciprocal();
+                    std::iter::once(rule).chain(reciprocal)
+                })
+                .collect(),
+        )
     }
 }
 
@@ -68,6 +851,15 @@ impl IntoIterator for Ruleset {
     }
 }
 
+impl<'a> IntoIterator for &'a Ruleset {
+    type Item = &'a Rule;
+    type IntoIter = std::slice::Iter<'a, Rule>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
 impl fmt::Display for Ruleset {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "Ruleset(")?;
@@ -80,116 +872,1037 @@ impl fmt::Display for Ruleset {
 
 #[derive(Debug, PartialEq, Clone, Serialize)]
 pub struct Rule {
+    /// sequence number from a leading `seq=N`, or assigned by `Ruleset::sequence`
+    /// - omitted from templates when unset, so they may number rules themselves
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seq: Option<u32>,
     action: Action,
     protocol: Protocol,
-    src_prefix: String,
+    src_prefix: Prefix,
     src_port: PortType,
-    dst_prefix: String,
+    dst_prefix: Prefix,
     dst_port: PortType,
+    /// set when matches are logged, by a fused `allowlog`/`denylog` or a trailing `log`
+    log: bool,
+    /// free text from a trailing `desc: ...` comment, for templates to render as remarks
+    description: Option<String>,
+    /// fields prefixed with `!`, left for templates to render
+    negated: Negated,
+    /// set on rules appended by am3k rather than parsed from a ruleset file
+    implicit: bool,
+    /// unset on rules disabled by a leading `disabled` token or `!`, which are validated
+    /// but left out of rendering unless `--include-disabled`
+    enabled: bool,
+    /// set on return rules that only match established sessions
+    established: bool,
+    /// 1-based line of the ruleset file the rule was parsed from, kept by its expansions
+    /// - omitted from templates for rules not parsed from a file, such as implicit rules
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_line: Option<usize>,
+    /// file & field columns of the source line, locating errors found after parsing
+    #[serde(skip)]
+    source: Source,
 }
 
-impl Rule {
-    pub fn expand(&self) -> Vec<Rule> {
-        let mut expanded_rules: Vec<Rule> = vec![];
+/// where a rule was written, if anywhere
+/// - `path` names the file or inline source the rule was read from, kept through includes
+/// - `columns` are the 1-based columns of the six fields on the text it was parsed from
+/// - compares equal to any other, as where a rule was written never distinguishes it,
+///   e.g. once reformatted by `to_acl`
+#[derive(Debug, Default, Clone)]
+struct Source {
+    path: Option<Arc<str>>,
+    columns: Option<[usize; 6]>,
+}
 
-        if let Some(port_expansion) = self.src_port.get_expansion() {
-            let mut rule_clone: Rule = self.clone();
-            for port in port_expansion {
-                rule_clone.src_port = PortType::Port(port);
-                expanded_rules.push(rule_clone.clone());
-            }
-        } else if let Some(port_expansion) = self.dst_port.get_expansion() {
-            let mut rule_clone: Rule = self.clone();
-            for port in port_expansion {
-                rule_clone.dst_port = PortType::Port(port);
-                expanded_rules.push(rule_clone.clone());
-            }
-        } else {
-            expanded_rules.push(self.clone());
+impl PartialEq for Source {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+/// address family of a prefix
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddrFamily {
+    Ipv4,
+    Ipv6,
+}
+
+impl AddrFamily {
+    /// reads the family of a cidr, address, or `any4`/`any6` wildcard
+    /// - `any` and named prefixes belong to no family
+    pub fn of(prefix: &str) -> Option<Self> {
+        match parse_cidr(wildcard_cidr(prefix)) {
+            Some((_, _, 32)) => Some(AddrFamily::Ipv4),
+            Some(_) => Some(AddrFamily::Ipv6),
+            None => None,
         }
+    }
+}
 
-        expanded_rules
+/// address families a ruleset is declared to hold, under `deployment.families`
+/// - `both` admits every rule, and is assumed for rulesets left undeclared
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Family {
+    Inet,
+    Inet6,
+    #[default]
+    Both,
+}
+
+impl Family {
+    /// whether a rule of the address family belongs, where rules of no family always do
+    fn admits(&self, family: Option<AddrFamily>) -> bool {
+        matches!(
+            (self, family),
+            (Family::Both, _)
+                | (_, None)
+                | (Family::Inet, Some(AddrFamily::Ipv4))
+                | (Family::Inet6, Some(AddrFamily::Ipv6))
+        )
     }
 }
 
-impl FromStr for Rule {
-    type Err = (FieldError, Location);
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let family = match self {
+            Family::Inet => "inet",
+            Family::Inet6 => "inet6",
+            Family::Both => "both",
+        };
+        write!(f, "{}", family)
+    }
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parts: Vec<&str> = s.split_whitespace().collect();
+/// a rule prefix, e.g. `10.0.0.0/8`, `any`, or a named zone like `inside`
+/// - addresses are normalized on parse, e.g. `010.000.000.001` reads as `10.0.0.1`
+///   and `2001:db8:0:0:0:0:0:1/128` as `2001:db8::1/128`, keeping the prefix length
+/// - compares, orders & serializes as its normalized text
+/// - reads as an address range for containment, where `any` contains every prefix
+///   and a named prefix contains only itself
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize)]
+#[serde(transparent)]
+pub struct Prefix(String);
 
-        if parts.len() != 6 {
-            return Err((
-                FieldError::RuleLengthErr,
-                Location::new(String::new(), 0, s.len() + 1),
-            ));
-        }
+/// the addresses a prefix names
+enum Space<'a> {
+    Any,
+    Network(u128, u8, u8),
+    Named(&'a str),
+}
 
-        if parts[3].contains(',') && parts[5].contains(',') {
-            return Err((
-                FieldError::RuleExpansionUnsupported,
-                Location::new(String::new(), 0, s.len() + 1),
-            ));
+impl Prefix {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    fn space(&self) -> Space<'_> {
+        match wildcard_cidr(&self.0) {
+            "any" => Space::Any,
+            p => match parse_cidr(p) {
+                Some((addr, len, bits)) => Space::Network(addr & mask(len, bits), len, bits),
+                None => Space::Named(p),
+            },
         }
+    }
 
-        let mut columns: Vec<usize> = vec![];
-        for (i, c) in s.trim().char_indices() {
-            if c.is_whitespace() {
-                columns.push(i + 2);
+    /// whether every address of the other prefix falls within this one
+    pub fn contains(&self, other: &Prefix) -> bool {
+        match (self.space(), other.space()) {
+            (Space::Any, _) => true,
+            (Space::Network(addr, len, bits), Space::Network(o_addr, o_len, o_bits)) => {
+                bits == o_bits && len <= o_len && o_addr & mask(len, bits) == addr
             }
+            (Space::Named(name), Space::Named(o_name)) => name == o_name,
+            _ => false,
         }
+    }
 
-        let action: Action = match Action::from_str(parts[0]) {
-            Ok(action) => action,
-            Err(e) => return Err((e, Location::new(String::new(), 0, 0))),
-        };
-
-        let protocol: Protocol = match Protocol::from_str(parts[1]) {
-            Ok(protocol) => protocol,
-            Err(e) => return Err((e, Location::new(String::new(), 0, columns[0]))),
-        };
-
-        // placeholder for src_prefix
+    /// whether the prefixes share any address, as networks either nest or are disjoint
+    pub fn overlaps(&self, other: &Prefix) -> bool {
+        match (self.space(), other.space()) {
+            (Space::Any, _) | (_, Space::Any) => true,
+            (Space::Network(..), Space::Network(..)) => {
+                self.contains(other) || other.contains(self)
+            }
+            (Space::Named(name), Space::Named(o_name)) => name == o_name,
+            _ => false,
+        }
+    }
+}
 
-        let src_port: PortType = match PortType::from_str(parts[3]) {
-            Ok(protocol) => protocol,
-            Err(e) => return Err((e, Location::new(String::new(), 0, columns[2]))),
-        };
+impl std::ops::Deref for Prefix {
+    type Target = str;
 
-        // placeholder for dst_prefix
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
 
-        let dst_port: PortType = match PortType::from_str(parts[5]) {
-            Ok(protocol) => protocol,
-            Err(e) => return Err((e, Location::new(String::new(), 0, columns[4]))),
-        };
+impl From<&str> for Prefix {
+    fn from(s: &str) -> Self {
+        Prefix(canonical(s).unwrap_or_else(|| String::from(s)))
+    }
+}
 
-        Ok(Rule {
-            action,
-            protocol,
-            src_prefix: String::from(parts[2]),
-            src_port,
-            dst_prefix: String::from(parts[4]),
-            dst_port,
-        })
+impl From<String> for Prefix {
+    fn from(s: String) -> Self {
+        match canonical(&s) {
+            Some(normal) => Prefix(normal),
+            None => Prefix(s),
+        }
+    }
+}
+
+/// the canonical text of an address or cidr, or `None` for names & wildcards
+/// - ipv6 is compressed and lowercased, the prefix length is kept as written
+fn canonical(s: &str) -> Option<String> {
+    let (addr, len) = match s.split_once('/') {
+        Some((addr, len)) => (addr, Some(len.parse::<u8>().ok()?)),
+        None => (s, None),
+    };
+    let addr: IpAddr = parse_addr(addr)?;
+    let bits: u8 = if addr.is_ipv4() { 32 } else { 128 };
+    match len {
+        Some(len) if len > bits => None,
+        Some(len) => Some(format!("{}/{}", addr, len)),
+        None => Some(addr.to_string()),
+    }
+}
+
+/// whether a prefix is a name, or parses as the address or cidr it is written as
+/// - prefixes holding `/` or `:`, or only digits and dots, are written as addresses,
+///   so `10.0.0.0/33` and `300.1.1.1` are invalid rather than kept as names
+/// - a prefix is one token of a rule line, so is never empty nor holds whitespace
+fn is_prefix_valid(s: &str) -> bool {
+    if s.is_empty() || s.contains(char::is_whitespace) {
+        return false;
+    }
+    let address_like: bool = s.contains(['/', ':'])
+        || (s.contains('.') && s.bytes().all(|b| b.is_ascii_digit() || b == b'.'));
+    !address_like || canonical(s).is_some()
+}
+
+/// parses an address, reading zero-padded ipv4 octets like `010` as decimal
+/// - octets are never read as octal, so `010.0.0.1` is `10.0.0.1`, not `8.0.0.1`
+fn parse_addr(s: &str) -> Option<IpAddr> {
+    if let Ok(addr) = s.parse::<IpAddr>() {
+        return Some(addr);
+    }
+    let octets: Vec<&str> = s.split('.').collect();
+    if octets.len() != 4 {
+        return None;
+    }
+    let mut parsed: [u8; 4] = [0; 4];
+    for (octet, part) in parsed.iter_mut().zip(octets) {
+        if part.is_empty() || part.len() > 3 || !part.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        *octet = part.parse::<u8>().ok()?;
+    }
+    Some(IpAddr::from(parsed))
+}
+
+impl PartialEq<&str> for Prefix {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// rewrites the family wildcards `any4` & `any6` as `0.0.0.0/0` & `::/0`
+/// - other prefixes are returned unchanged
+pub fn wildcard_cidr(prefix: &str) -> &str {
+    match prefix {
+        "any4" => "0.0.0.0/0",
+        "any6" => "::/0",
+        prefix => prefix,
+    }
+}
+
+/// rule fields prefixed with `!`, matching everything except the field value
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize)]
+pub struct Negated {
+    pub protocol: bool,
+    pub src_prefix: bool,
+    pub dst_prefix: bool,
+}
+
+impl Negated {
+    fn any(&self) -> bool {
+        self.protocol || self.src_prefix || self.dst_prefix
+    }
+}
+
+/// strips a leading `!`, returning whether the field is negated
+fn strip_negation(s: &str) -> (bool, &str) {
+    match s.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    }
+}
+
+/// reads a standalone `*` field as the `any` keyword, as written by other ACL tools
+/// - `*` is not a member of port lists, so `22,*,443` stays invalid
+fn any_alias(s: &str) -> &str {
+    match s {
+        "*" => "any",
+        s => s,
+    }
+}
+
+/// describes a prefix for `Rule::describe`, e.g. `any4` as "any IPv4 address"
+fn describe_prefix(prefix: &Prefix, negated: bool) -> String {
+    let described: &str = match prefix.as_str() {
+        "any" => "any address",
+        "any4" => "any IPv4 address",
+        "any6" => "any IPv6 address",
+        prefix => prefix,
+    };
+    match negated {
+        true => format!("anything except {}", described),
+        false => described.to_string(),
+    }
+}
+
+/// describes a port field for `Rule::describe`, with a leading space
+/// - `any` reads as e.g. " (any source port)", and ranges as "8000 through 8010"
+/// - a single port is named by `one`, and lists or ranges by `many`
+fn describe_ports(port: &PortType, any: &str, one: &str, many: &str) -> String {
+    let ranges: Vec<(u16, u16)> = match port {
+        PortType::Any => return format!(" (any {})", any),
+        PortType::Port(num) => vec![(*num, *num)],
+        PortType::Map(map) => map.0.clone(),
+    };
+    let mut items: Vec<String> = ranges
+        .iter()
+        .map(|&(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{} through {}", start, end),
+        })
+        .collect();
+    let noun: &str = match ranges.as_slice() {
+        [(start, end)] if start == end => one,
+        _ => many,
+    };
+    let listed: String = match items.pop() {
+        Some(last) if !items.is_empty() => format!("{} and {}", items.join(", "), last),
+        Some(last) => last,
+        None => String::new(),
+    };
+    format!(" {} {}", noun, listed)
+}
+
+/// prefixes a field with `!` when negated
+fn negate(negated: bool, s: impl fmt::Display) -> String {
+    match negated {
+        true => format!("!{}", s),
+        false => s.to_string(),
+    }
+}
+
+impl Rule {
+    /// unset on rules parsed after a `disabled` token or `!`
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// whether any field is prefixed with `!`
+    pub fn is_negated(&self) -> bool {
+        self.negated.any()
+    }
+
+    /// 1-based line of the ruleset file the rule was parsed from, if any
+    pub fn source_line(&self) -> Option<usize> {
+        self.source_line
+    }
+
+    /// source line, or `0` for rules not parsed from a file
+    fn line(&self) -> usize {
+        self.source_line.unwrap_or_default()
+    }
+
+    /// source column of the numbered field, from `0` for the action to `5` for the dst port
+    /// - `0` for rules not parsed from text, such as built or implicit rules
+    fn column(&self, field: usize) -> usize {
+        self.source.columns.map_or(0, |columns| columns[field])
+    }
+
+    /// locates the numbered field in the file the rule was read from
+    /// - the path is left empty for rules not read from a file, for callers to fill
+    fn locate(&self, field: usize) -> Location {
+        let path: &str = self.source.path.as_deref().unwrap_or_default();
+        Location::new(path.to_string(), self.line(), self.column(field))
+    }
+
+    /// describes what the rule matches in plain English, e.g. `allow tcp 10.0.0.0/24 any
+    /// 10.1.0.0/24 443` as "Permit TCP from 10.0.0.0/24 (any source port) to 10.1.0.0/24 port 443"
+    /// - icmp rules describe their dst port field as the icmp type
+    pub fn describe(&self) -> String {
+        let mut description: String = String::from(match self.action {
+            Action::Allow | Action::AllowLog => "Permit",
+            Action::Deny | Action::DenyLog => "Deny",
+        });
+        if self.log {
+            description.push_str(" and log");
+        }
+        let protocol: String = self.protocol.to_string().to_uppercase();
+        match self.negated.protocol {
+            true => description.push_str(&format!(" all but {}", protocol)),
+            false => description.push_str(&format!(" {}", protocol)),
+        }
+
+        let (src_ports, dst_ports) = self.protocol.ports(self.negated.protocol);
+        description.push_str(" from ");
+        description.push_str(&describe_prefix(&self.src_prefix, self.negated.src_prefix));
+        if src_ports {
+            description.push_str(&describe_ports(
+                &self.src_port,
+                "source port",
+                "port",
+                "ports",
+            ));
+        }
+        description.push_str(" to ");
+        description.push_str(&describe_prefix(&self.dst_prefix, self.negated.dst_prefix));
+        match (&self.protocol, dst_ports) {
+            (Protocol::ICMP, true) => description.push_str(&describe_ports(
+                &self.dst_port,
+                "ICMP type",
+                "ICMP type",
+                "ICMP types",
+            )),
+            (_, true) => description.push_str(&describe_ports(
+                &self.dst_port,
+                "destination port",
+                "port",
+                "ports",
+            )),
+            (_, false) => (),
+        }
+
+        if self.established {
+            description.push_str(", on established sessions only");
+        }
+        if !self.enabled {
+            description.push_str(" (disabled)");
+        }
+        description
+    }
+
+    /// address family of the rule, from whichever prefix names one
+    pub fn family(&self) -> Option<AddrFamily> {
+        AddrFamily::of(&self.src_prefix).or(AddrFamily::of(&self.dst_prefix))
+    }
+
+    /// expands a rule into one rule per port of its src port map, or else its dst port map
+    /// - rules are yielded lazily, so wide ranges are never materialized up front
+    /// - a numbered rule sub-numbers its expansions, as `seq`, `seq + 2`, and so on,
+    ///   leaving the number after each for its established return rule
+    pub fn expand(self) -> Box<dyn Iterator<Item = Rule>> {
+        let seq = move |seq: Option<u32>, i: usize| {
+            seq.map(|seq| seq.saturating_add((i as u32).saturating_mul(2)))
+        };
+        if let Some(ports) = self.src_port.clone().into_expansion() {
+            return Box::new(ports.enumerate().map(move |(i, port)| Rule {
+                src_port: PortType::Port(port),
+                seq: seq(self.seq, i),
+                ..self.clone()
+            }));
+        }
+        if let Some(ports) = self.dst_port.clone().into_expansion() {
+            return Box::new(ports.enumerate().map(move |(i, port)| Rule {
+                dst_port: PortType::Port(port),
+                seq: seq(self.seq, i),
+                ..self.clone()
+            }));
+        }
+        Box::new(std::iter::once(self))
+    }
+
+    /// rewrites prefixes matching a mapping, leaving unmapped prefixes alone
+    pub fn transform(mut self, src: Option<&PrefixMap>, dst: Option<&PrefixMap>) -> Rule {
+        if let Some(to) = src.and_then(|map| map.get(self.src_prefix.as_str())) {
+            self.src_prefix = Prefix::from(to.as_str());
+        }
+        if let Some(to) = dst.and_then(|map| map.get(self.dst_prefix.as_str())) {
+            self.dst_prefix = Prefix::from(to.as_str());
+        }
+        self
+    }
+
+    /// true when both rules match identical traffic, whatever their actions
+    fn matches_same(&self, other: &Rule) -> bool {
+        self.protocol == other.protocol
+            && self.negated == other.negated
+            && self.src_prefix == other.src_prefix
+            && self.src_port == other.src_port
+            && self.dst_prefix == other.dst_prefix
+            && self.dst_port == other.dst_port
+            && self.established == other.established
+    }
+
+    /// true when this rule matches every packet the other rule matches
+    /// - negated fields are only shadowed by `ip` and `any`, as they match too much to compare
+    fn shadows(&self, other: &Rule) -> bool {
+        let prefix_covers = |outer: &Prefix, inner: &Prefix, negated: bool| {
+            *outer == "any" || (!negated && outer.contains(inner))
+        };
+        !self.negated.any()
+            && match other.negated.protocol {
+                true => self.protocol == Protocol::IP,
+                false => self.protocol.contains(&other.protocol),
+            }
+            && self.src_port.contains(&other.src_port)
+            && self.dst_port.contains(&other.dst_port)
+            && (!self.established || other.established)
+            && prefix_covers(
+                &self.src_prefix,
+                &other.src_prefix,
+                other.negated.src_prefix,
+            )
+            && prefix_covers(
+                &self.dst_prefix,
+                &other.dst_prefix,
+                other.negated.dst_prefix,
+            )
+    }
+
+    /// builds the established return rule for a tcp allow rule
+    /// - swaps src & dst prefix and port, keeping action and protocol
+    /// - a numbered rule numbers its return rule one after itself
+    fn reciprocal(&self) -> Option<Rule> {
+        match (&self.action, &self.protocol, self.negated.protocol) {
+            (Action::Allow | Action::AllowLog, Protocol::TCP, false) => Some(Rule {
+                src_prefix: self.dst_prefix.clone(),
+                src_port: self.dst_port.clone(),
+                dst_prefix: self.src_prefix.clone(),
+                dst_port: self.src_port.clone(),
+                negated: Negated {
+                    protocol: false,
+                    src_prefix: self.negated.dst_prefix,
+                    dst_prefix: self.negated.src_prefix,
+                },
+                established: true,
+                seq: self.seq.map(|seq| seq.saturating_add(1)),
+                ..self.clone()
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = Vec<(FieldError, Location)>;
+
+    /// parses a single rule, collecting every field error found on the line
+    /// - structural errors, such as the field count, are reported alone
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = s.split_whitespace().collect();
+        let mut columns: Vec<usize> = field_columns(s);
+
+        // a leading `disabled` token, or a `!` on the first token, disables the rule
+        let enabled: bool = match parts.first() {
+            Some(&"disabled" | &"!") => {
+                parts.remove(0);
+                columns.remove(0);
+                false
+            }
+            Some(part) if part.starts_with('!') => {
+                parts[0] = &part[1..];
+                columns[0] += 1;
+                false
+            }
+            _ => true,
+        };
+
+        // a leading `seq=N` numbers the rule, and is not one of its fields
+        let seq_field: Option<(&str, usize)> = match parts.first() {
+            Some(part) if part.starts_with("seq=") => Some((parts.remove(0), columns.remove(0))),
+            _ => None,
+        };
+        let log: bool = parts.len() == 7 && parts[6] == "log";
+
+        if parts.len() != 6 && !log {
+            return Err(vec![(
+                FieldError::RuleLengthErr,
+                Location::new(String::new(), 0, s.chars().count() + 1),
+            )]);
+        }
+
+        let mut errors: Vec<(FieldError, Location)> = vec![];
+
+        let seq: Option<u32> = seq_field.and_then(|(token, column)| {
+            token["seq=".len()..]
+                .parse::<u32>()
+                .map_err(|_| {
+                    errors.push((
+                        FieldError::SeqInvalid,
+                        Location::new(String::new(), 0, column),
+                    ))
+                })
+                .ok()
+        });
+
+        match Rule::from_fields(&parts[..6], &columns[..6], log) {
+            Ok(rule) if errors.is_empty() => Ok(Rule {
+                seq,
+                enabled,
+                source: Source {
+                    path: None,
+                    columns: Some(std::array::from_fn(|field| columns[field])),
+                },
+                ..rule
+            }),
+            Ok(_) => Err(errors),
+            Err(e) => {
+                errors.extend(e);
+                Err(errors)
+            }
+        }
+    }
+}
+
+impl Rule {
+    /// validates the six fields of a rule, locating errors at the provided field columns
+    fn from_fields(
+        parts: &[&str],
+        columns: &[usize],
+        log: bool,
+    ) -> Result<Self, Vec<(FieldError, Location)>> {
+        let mut errors: Vec<(FieldError, Location)> = vec![];
+        let location = |field: usize| Location::new(String::new(), 0, columns[field]);
+
+        let action: Option<Action> = Action::from_str(parts[0])
+            .map(|action| match log {
+                true => action.logged(),
+                false => action,
+            })
+            .map_err(|e| errors.push((e, location(0))))
+            .ok();
+
+        let (protocol_negated, protocol) = strip_negation(parts[1]);
+        let protocol: Option<Protocol> = Protocol::from_str(protocol)
+            .map_err(|e| errors.push((e, location(1))))
+            .ok();
+
+        let (src_negated, src_prefix) = strip_negation(parts[2]);
+        let src_prefix: Prefix = Prefix::from(any_alias(src_prefix));
+
+        let src_port: Option<PortType> = PortType::from_str(parts[3])
+            .map_err(|e| errors.push((e, location(3))))
+            .ok();
+
+        let (dst_negated, dst_prefix) = strip_negation(parts[4]);
+        let dst_prefix: Prefix = Prefix::from(any_alias(dst_prefix));
+
+        let dst_port: Option<PortType> = PortType::from_str(parts[5])
+            .map_err(|e| errors.push((e, location(5))))
+            .ok();
+
+        let negated = Negated {
+            protocol: protocol_negated,
+            src_prefix: src_negated,
+            dst_prefix: dst_negated,
+        };
+        let fields: [Option<&PortType>; 2] = [src_port.as_ref(), dst_port.as_ref()];
+        for (e, field) in check_fields(
+            protocol.as_ref(),
+            &negated,
+            [&src_prefix, &dst_prefix],
+            fields,
+        ) {
+            errors.push((e, location(field)));
+        }
+
+        match (action, protocol, src_port, dst_port) {
+            _ if !errors.is_empty() => Err(errors),
+            (Some(action), Some(protocol), Some(src_port), Some(dst_port)) => Ok(Rule {
+                seq: None,
+                log: action.is_logged(),
+                description: None,
+                action,
+                protocol,
+                src_prefix,
+                src_port,
+                dst_prefix,
+                dst_port,
+                negated,
+                implicit: false,
+                enabled: true,
+                established: false,
+                source_line: None,
+                source: Source::default(),
+            }),
+            _ => Err(errors),
+        }
+    }
+}
+
+/// checks rule fields against one another, where fields that failed to parse are `None`
+/// - shared by `Rule::from_str` & `RuleBuilder`, so both accept exactly the same rules
+/// - errs with the index of the offending rule field, from `0` for the action to `5`
+///   for the dst port, in field order
+fn check_fields(
+    protocol: Option<&Protocol>,
+    negated: &Negated,
+    [src_prefix, dst_prefix]: [&Prefix; 2],
+    [src_port, dst_port]: [Option<&PortType>; 2],
+) -> Vec<(FieldError, usize)> {
+    let mut errors: Vec<(FieldError, usize)> = vec![];
+    let (src_ported, dst_ported) = match protocol {
+        Some(protocol) => protocol.ports(negated.protocol),
+        None => (true, true),
+    };
+    let portless = |port: Option<&PortType>| matches!(port, None | Some(PortType::Any));
+
+    if !is_prefix_valid(src_prefix) {
+        errors.push((FieldError::PrefixInvalid, 2));
+    }
+    if !src_ported && !portless(src_port) {
+        errors.push((FieldError::PortProtocolMismatch, 3));
+    }
+    if !is_prefix_valid(dst_prefix) {
+        errors.push((FieldError::PrefixInvalid, 4));
+    }
+    if let (Some(src), Some(dst)) = (AddrFamily::of(src_prefix), AddrFamily::of(dst_prefix)) {
+        if src != dst {
+            errors.push((FieldError::AddressFamilyMismatch, 4));
+        }
+    }
+    if !dst_ported && !portless(dst_port) {
+        errors.push((FieldError::PortProtocolMismatch, 5));
+    }
+    errors
+}
+
+/// builds a rule from typed fields, validated exactly as a parsed rule line
+/// - prefixes & ports default to `any`, while the action & protocol must be set
+/// - fields are matched as given, unless negated with `negated`
+#[derive(Debug, Default, Clone)]
+pub struct RuleBuilder {
+    action: Option<Action>,
+    protocol: Option<Protocol>,
+    src_prefix: Option<Prefix>,
+    src_port: Option<PortType>,
+    dst_prefix: Option<Prefix>,
+    dst_port: Option<PortType>,
+    negated: Negated,
+}
+
+impl RuleBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn action(mut self, action: Action) -> Self {
+        self.action = Some(action);
+        self
+    }
+
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    pub fn src_prefix(mut self, prefix: Prefix) -> Self {
+        self.src_prefix = Some(prefix);
+        self
+    }
+
+    pub fn src_port(mut self, port: PortType) -> Self {
+        self.src_port = Some(port);
+        self
+    }
+
+    pub fn dst_prefix(mut self, prefix: Prefix) -> Self {
+        self.dst_prefix = Some(prefix);
+        self
+    }
+
+    pub fn dst_port(mut self, port: PortType) -> Self {
+        self.dst_port = Some(port);
+        self
+    }
+
+    /// negates the protocol or prefixes, as a leading `!` does on a rule line
+    pub fn negated(mut self, negated: Negated) -> Self {
+        self.negated = negated;
+        self
+    }
+
+    /// validates the fields into a rule, erring with the first field error found
+    /// - an unset action errs as `ActionInvalid`, and an unset protocol as `ProtocolUnsupported`
+    pub fn build(self) -> Result<Rule, FieldError> {
+        let action: Action = self.action.ok_or(FieldError::ActionInvalid)?;
+        let protocol: Protocol = self.protocol.ok_or(FieldError::ProtocolUnsupported)?;
+        let src_prefix: Prefix = self.src_prefix.unwrap_or_else(|| Prefix::from("any"));
+        let src_port: PortType = self.src_port.unwrap_or(PortType::Any);
+        let dst_prefix: Prefix = self.dst_prefix.unwrap_or_else(|| Prefix::from("any"));
+        let dst_port: PortType = self.dst_port.unwrap_or(PortType::Any);
+
+        let prefixes: [&Prefix; 2] = [&src_prefix, &dst_prefix];
+        let ports: [Option<&PortType>; 2] = [Some(&src_port), Some(&dst_port)];
+        if let Some((e, _)) = check_fields(Some(&protocol), &self.negated, prefixes, ports)
+            .into_iter()
+            .next()
+        {
+            return Err(e);
+        }
+        Ok(Rule {
+            seq: None,
+            log: action.is_logged(),
+            description: None,
+            action,
+            protocol,
+            src_prefix,
+            src_port,
+            dst_prefix,
+            dst_port,
+            negated: self.negated,
+            implicit: false,
+            enabled: true,
+            established: false,
+            source_line: None,
+            source: Source::default(),
+        })
     }
 }
 
 impl fmt::Display for Rule {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.enabled {
+            write!(f, "disabled ")?;
+        }
+        if let Some(seq) = self.seq {
+            write!(f, "seq={} ", seq)?;
+        }
         write!(
             f,
             "{} {} {} {} {} {}",
             self.action,
-            self.protocol,
-            self.src_prefix,
+            negate(self.negated.protocol, &self.protocol),
+            negate(self.negated.src_prefix, &self.src_prefix),
             self.src_port,
-            self.dst_prefix,
+            negate(self.negated.dst_prefix, &self.dst_prefix),
             self.dst_port
         )
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize)]
+/// lists the column of the first character of each field
+/// - columns are 1-based character counts, so multi-byte characters count as one column
+/// - any whitespace character counts as one column
+fn field_columns(s: &str) -> Vec<usize> {
+    let mut columns: Vec<usize> = vec![];
+    let mut in_field: bool = false;
+    for (i, c) in s.chars().enumerate() {
+        if !c.is_whitespace() && !in_field {
+            columns.push(i + 1);
+        }
+        in_field = !c.is_whitespace();
+    }
+    columns
+}
+
+/// merges each run of adjacent rules equal in everything but the selected port
+fn compact_ports(rules: Vec<Rule>, port: fn(&mut Rule) -> &mut PortType) -> Vec<Rule> {
+    // compares rules with the selected port and source line masked
+    let masked = |rule: &Rule| -> Rule {
+        let mut rule: Rule = rule.clone();
+        *port(&mut rule) = PortType::Any;
+        rule.source_line = None;
+        rule
+    };
+
+    let mut compacted: Vec<Rule> = vec![];
+    for mut rule in rules {
+        if let Some(last) = compacted.last_mut() {
+            let ranges: Vec<(u16, u16)> = port(&mut rule).sort_key();
+            let last_ranges: Vec<(u16, u16)> = port(last).sort_key();
+            if !ranges.is_empty() && !last_ranges.is_empty() && masked(last) == masked(&rule) {
+                *port(last) = PortType::Map(coalesce(last_ranges.into_iter().chain(ranges)));
+                continue;
+            }
+        }
+        compacted.push(rule);
+    }
+    compacted
+}
+
+/// replaces each run of adjacent rules equal in everything but the selected prefix
+/// with a rule per cidr covering the run
+/// - rules whose prefix survives aggregation are kept as they were, source line included
+fn aggregate_prefixes(rules: Vec<Rule>, prefix: fn(&mut Rule) -> &mut Prefix) -> Vec<Rule> {
+    // compares rules with the selected prefix and source line masked
+    let masked = |rule: &Rule| -> Rule {
+        let mut rule: Rule = rule.clone();
+        *prefix(&mut rule) = Prefix::from("");
+        rule.source_line = None;
+        rule
+    };
+    // only plain cidrs aggregate, as a negated prefix inverts what the rule matches
+    let cidr = |rule: &mut Rule| -> Option<(u128, u8, u8)> {
+        match (rule.negated.src_prefix, rule.negated.dst_prefix) {
+            (false, false) => parse_cidr(prefix(rule)),
+            _ => None,
+        }
+    };
+
+    let mut runs: Vec<Vec<Rule>> = vec![];
+    for mut rule in rules {
+        let family: Option<u8> = cidr(&mut rule).map(|(_, _, bits)| bits);
+        if let Some(run) = runs.last_mut() {
+            let last: &mut Rule = run.last_mut().unwrap();
+            if family.is_some()
+                && cidr(last).map(|(_, _, bits)| bits) == family
+                && masked(last) == masked(&rule)
+            {
+                run.push(rule);
+                continue;
+            }
+        }
+        runs.push(vec![rule]);
+    }
+
+    let mut aggregated: Vec<Rule> = vec![];
+    for mut run in runs {
+        if run.len() == 1 {
+            aggregated.append(&mut run);
+            continue;
+        }
+        let cidrs: Vec<(u128, u8, u8)> = run.iter_mut().filter_map(cidr).collect();
+        let bits: u8 = cidrs[0].2;
+        for (addr, len) in aggregate(&cidrs) {
+            let kept: Option<&Rule> = run.iter().zip(&cidrs).find_map(|(rule, &(a, l, _))| {
+                (l == len && a & mask(len, bits) == addr).then_some(rule)
+            });
+            match kept {
+                Some(rule) => aggregated.push(rule.clone()),
+                None => {
+                    let mut rule: Rule = run[0].clone();
+                    *prefix(&mut rule) = Prefix::from(format_cidr(addr, len, bits));
+                    aggregated.push(rule);
+                }
+            }
+        }
+    }
+    aggregated
+}
+
+/// network mask of a prefix length within an address of `bits` bits
+fn mask(len: u8, bits: u8) -> u128 {
+    match bits - len {
+        128 => 0,
+        shift => (u128::MAX << shift) & (u128::MAX >> (128 - u32::from(bits))),
+    }
+}
+
+/// reduces cidrs of a single family to the minimal set of networks covering them
+/// - contained networks are absorbed, then sibling networks merge into their parent
+fn aggregate(cidrs: &[(u128, u8, u8)]) -> Vec<(u128, u8)> {
+    let mut networks: Vec<(u128, u8)> = cidrs
+        .iter()
+        .map(|&(addr, len, bits)| (addr & mask(len, bits), len))
+        .collect();
+    networks.sort();
+
+    let bits: u8 = cidrs.first().map_or(32, |&(_, _, bits)| bits);
+    let mut merged: Vec<(u128, u8)> = vec![];
+    for (addr, len) in networks {
+        if let Some(&(last, last_len)) = merged.last() {
+            if last_len <= len && addr & mask(last_len, bits) == last {
+                continue;
+            }
+        }
+        merged.push((addr, len));
+        // siblings differ only by the last bit of their prefix
+        while let [.., (a, a_len), (b, b_len)] = merged[..] {
+            let parent: u128 = mask(a_len.saturating_sub(1), bits);
+            if a_len != b_len || a_len == 0 || a & parent != b & parent {
+                break;
+            }
+            merged.truncate(merged.len() - 2);
+            merged.push((a & parent, a_len - 1));
+        }
+    }
+    merged
+}
+
+/// formats a network from its address bits, as parsed by `parse_cidr`
+fn format_cidr(addr: u128, len: u8, bits: u8) -> String {
+    match bits {
+        32 => format!("{}/{}", std::net::Ipv4Addr::from(addr as u32), len),
+        _ => format!("{}/{}", std::net::Ipv6Addr::from(addr), len),
+    }
+}
+
+/// sorts port ranges, joining any that overlap or touch
+fn coalesce(ranges: impl Iterator<Item = (u16, u16)>) -> PortMap {
+    let mut ranges: Vec<(u16, u16)> = ranges.collect();
+    ranges.sort();
+    let mut merged: Vec<(u16, u16)> = vec![];
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if u32::from(start) <= u32::from(last.1) + 1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    PortMap(merged)
+}
+
+/// shorthand check single prefix against provided filters
+fn is_prefix_covered(prefix: &str, filters: &[String]) -> bool {
+    filters.iter().any(|filter| {
+        filter == "any"
+            || filter == prefix
+            || cidr_contains(wildcard_cidr(filter), wildcard_cidr(prefix))
+    })
+}
+
+/// true when the inner cidr falls within the outer cidr of the same address family
+fn cidr_contains(outer: &str, inner: &str) -> bool {
+    match (parse_cidr(outer), parse_cidr(inner)) {
+        (Some((o_addr, o_len, o_bits)), Some((i_addr, i_len, i_bits))) => {
+            if o_bits != i_bits || o_len > i_len {
+                return false;
+            }
+            let mask: u128 = match o_bits - o_len {
+                128 => 0,
+                shift => u128::MAX << shift,
+            };
+            o_addr & mask == i_addr & mask
+        }
+        _ => false,
+    }
+}
+
+/// parses a cidr into (address, prefix length, address bits)
+/// - a bare address is treated as a host prefix
+fn parse_cidr(s: &str) -> Option<(u128, u8, u8)> {
+    let (addr, len) = match s.split_once('/') {
+        Some((addr, len)) => (addr, Some(len.parse::<u8>().ok()?)),
+        None => (s, None),
+    };
+    let (addr, bits): (u128, u8) = match parse_addr(addr)? {
+        IpAddr::V4(v4) => (u32::from(v4) as u128, 32),
+        IpAddr::V6(v6) => (u128::from(v6), 128),
+    };
+    let len: u8 = len.unwrap_or(bits);
+    match len > bits {
+        true => None,
+        false => Some((addr, len, bits)),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize)]
 pub enum Action {
     Allow,
     Deny,
@@ -197,11 +1910,35 @@ pub enum Action {
     DenyLog,
 }
 
+impl Action {
+    /// fuses logging into the action, e.g. `allow` becomes `allowlog`
+    pub fn logged(self) -> Self {
+        match self {
+            Action::Allow => Action::AllowLog,
+            Action::Deny => Action::DenyLog,
+            action => action,
+        }
+    }
+
+    pub fn is_logged(&self) -> bool {
+        matches!(self, Action::AllowLog | Action::DenyLog)
+    }
+
+    pub fn is_deny(&self) -> bool {
+        matches!(self, Action::Deny | Action::DenyLog)
+    }
+
+    /// whether the action permits matching packets, logged or not
+    pub fn allows(&self) -> bool {
+        matches!(self, Action::Allow | Action::AllowLog)
+    }
+}
+
 impl FromStr for Action {
     type Err = FieldError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
+        match s.to_lowercase().as_str() {
             "allow" => Ok(Action::Allow),
             "deny" => Ok(Action::Deny),
             "allowlog" => Ok(Action::AllowLog),
@@ -223,7 +1960,8 @@ impl fmt::Display for Action {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize)]
 pub enum Protocol {
     TCP,
     UDP,
@@ -231,6 +1969,24 @@ pub enum Protocol {
     IP,
 }
 
+impl Protocol {
+    /// whether the src & dst port fields may name ports under this protocol
+    /// - `icmp` reads its dst port field as the icmp type, and has no src port
+    /// - a negated protocol matches many protocols, so neither field may name ports
+    fn ports(&self, negated: bool) -> (bool, bool) {
+        match (self, negated) {
+            (_, true) | (Protocol::IP, _) => (false, false),
+            (Protocol::ICMP, _) => (false, true),
+            (Protocol::TCP | Protocol::UDP, _) => (true, true),
+        }
+    }
+
+    /// whether this protocol matches every packet of the other, where `ip` contains all
+    fn contains(&self, other: &Protocol) -> bool {
+        *self == Protocol::IP || self == other
+    }
+}
+
 impl FromStr for Protocol {
     type Err = FieldError;
 
@@ -271,15 +2027,15 @@ impl PortMap {
     /// parses a variable port string into range tuples
     /// - supports u16, range(u16), and list of u16/range(u16)
     fn from_str(s: &str) -> Result<Self, FieldError> {
-        match s.parse::<u16>() {
+        match parse_port(s) {
             Ok(n) => Ok(PortMap::from_num(n)),
             Err(_) => {
-                if s.contains(",") {
-                    return PortMap::from_list(s);
-                } else if s.contains('-') {
-                    return Ok(PortMap(vec![Self::parse_range(s)?]));
+                if s.contains(',') {
+                    PortMap::from_list(s)
+                } else if s.contains('-') || s.contains("..<") {
+                    Ok(PortMap(vec![Self::parse_range(s)?]))
                 } else {
-                    return Err(FieldError::PortInvalid);
+                    Err(FieldError::PortInvalid)
                 }
             }
         }
@@ -296,20 +2052,31 @@ impl PortMap {
     fn from_list(s: &str) -> Result<Self, FieldError> {
         let mut port_map: Vec<(u16, u16)> = vec![];
         for part in s.split(',').collect::<Vec<&str>>() {
-            if part.contains('-') {
+            if part.contains('-') || part.contains("..<") {
                 port_map.push(Self::parse_range(part)?);
             } else {
-                let n = match part.parse::<u16>() {
-                    Ok(num) => num,
-                    Err(_) => return Err(FieldError::PortInvalid),
-                };
+                let n: u16 = parse_port(part)?;
                 port_map.push((n, n));
             }
         }
         Ok(PortMap(port_map))
     }
 
+    /// parses an inclusive `start-end` range, or a half-open `start..<end` range
+    /// - half-open ranges resolve to the inclusive `(start, end - 1)`
+    /// - half-open ranges must cover more than a single port
     fn parse_range(s: &str) -> Result<(u16, u16), FieldError> {
+        if let Some((start, end)) = s.split_once("..<") {
+            let (start, end): (u16, u16) = match (start.parse::<u16>(), end.parse::<u16>()) {
+                (Ok(start), Ok(end)) => (start, end),
+                _ => return Err(FieldError::PortInvalid),
+            };
+            return match end.checked_sub(1) {
+                Some(last) if last > start => Ok((start, last)),
+                _ if end < start => Err(FieldError::PortOrderInvalid),
+                _ => Err(FieldError::PortRangeEmpty),
+            };
+        }
         let parts: Vec<u16> = match s
             .split('-')
             .map(|s| s.parse::<u16>())
@@ -339,7 +2106,7 @@ impl PortMap {
                 return true;
             }
         }
-        return false;
+        false
     }
 }
 
@@ -370,9 +2137,9 @@ impl fmt::Display for PortMap {
                 .iter()
                 .map(|&(start, end)| {
                     if start == end {
-                        format!("({})", start)
+                        start.to_string()
                     } else {
-                        format!("({},{})", start, end)
+                        format!("{}-{}", start, end)
                     }
                 })
                 .collect::<Vec<String>>()
@@ -381,6 +2148,37 @@ impl fmt::Display for PortMap {
     }
 }
 
+/// parses a port number or a well-known service name
+fn parse_port(s: &str) -> Result<u16, FieldError> {
+    s.parse::<u16>()
+        .ok()
+        .or_else(|| port_alias(s))
+        .ok_or(FieldError::PortInvalid)
+}
+
+/// well-known service names accepted in place of port numbers
+/// - protocol-agnostic, so `dns` is 53 for both tcp & udp
+fn port_alias(name: &str) -> Option<u16> {
+    match name {
+        "ftp" => Some(21),
+        "ssh" => Some(22),
+        "telnet" => Some(23),
+        "smtp" => Some(25),
+        "dns" => Some(53),
+        "http" => Some(80),
+        "ntp" => Some(123),
+        "snmp" => Some(161),
+        "snmptrap" => Some(162),
+        "bgp" => Some(179),
+        "ldap" => Some(389),
+        "https" => Some(443),
+        "syslog" => Some(514),
+        "ldaps" => Some(636),
+        "rdp" => Some(3389),
+        _ => None,
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum PortType {
     Any,
@@ -393,25 +2191,62 @@ impl PortType {
         PortType::Map(PortMap(vec![(0, 0)]))
     }
 
-    fn is_expandable(&self) -> bool {
-        if let &PortType::Map(ref map) = &self {
-            return map.is_expandable();
+    /// ranges covered by the port, where `any` is empty and sorts first
+    fn sort_key(&self) -> Vec<(u16, u16)> {
+        match self {
+            PortType::Any => vec![],
+            PortType::Map(map) => map.0.clone(),
+            PortType::Port(num) => vec![(*num, *num)],
         }
-        false
     }
 
-    fn get_expansion(&self) -> Option<Vec<u16>> {
-        if let PortType::Map(map) = self {
-            let mut expanded_map: Vec<u16> = vec![];
-            for port_map in map {
-                expanded_map.push(port_map.0);
-                if port_map.0 != port_map.1 {
-                    expanded_map.push(port_map.1);
-                }
-            }
-            return Some(expanded_map);
+    /// inclusive ranges covered by the port, coalesced, where `any` is every port
+    pub fn ranges(&self) -> Vec<(u16, u16)> {
+        match self {
+            PortType::Any => vec![(0, u16::MAX)],
+            PortType::Map(map) => coalesce(map.0.iter().copied()).0,
+            PortType::Port(num) => vec![(*num, *num)],
+        }
+    }
+
+    /// whether this port covers every port of the other, where `any` covers all
+    pub fn contains(&self, other: &PortType) -> bool {
+        let outer: Vec<(u16, u16)> = self.ranges();
+        other.ranges().iter().all(|(start, end)| {
+            outer
+                .iter()
+                .any(|(outer_start, outer_end)| outer_start <= start && end <= outer_end)
+        })
+    }
+
+    /// whether this port shares any port with the other, where `any` overlaps all
+    pub fn overlaps(&self, other: &PortType) -> bool {
+        let outer: Vec<(u16, u16)> = self.ranges();
+        other.ranges().iter().any(|(start, end)| {
+            outer
+                .iter()
+                .any(|(outer_start, outer_end)| outer_start <= end && start <= outer_end)
+        })
+    }
+
+    /// whether the port is a list of more than one port or range, e.g. `22,443`
+    fn is_list(&self) -> bool {
+        matches!(self, PortType::Map(map) if map.0.len() > 1)
+    }
+
+    fn is_expandable(&self) -> bool {
+        if let PortType::Map(map) = self {
+            return map.is_expandable();
+        }
+        false
+    }
+
+    /// yields every port of each inclusive range in a port map
+    fn into_expansion(self) -> Option<impl Iterator<Item = u16>> {
+        match self {
+            PortType::Map(map) => Some(map.into_iter().flat_map(|(start, end)| start..=end)),
+            _ => None,
         }
-        None
     }
 }
 
@@ -419,7 +2254,7 @@ impl FromStr for PortType {
     type Err = FieldError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s == "any" {
+        if any_alias(s) == "any" {
             Ok(PortType::Any)
         } else {
             Ok(PortType::Map(PortMap::from_str(s)?))
@@ -450,7 +2285,7 @@ impl fmt::Display for PortType {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Default, PartialEq, Clone)]
 pub struct RuleErrors(Vec<(FieldError, Location)>);
 
 impl RuleErrors {
@@ -462,15 +2297,70 @@ impl RuleErrors {
         self.0.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     pub fn push(&mut self, error: FieldError, loc: Location) {
         self.0.push((error, loc));
     }
 
+    /// locates errors found outside any file at `path`
+    /// - errors already located in a file, such as an included one, keep their path
     pub fn update_paths(&mut self, path: &str) {
-        for (_, loc) in self.0.iter_mut() {
+        for (_, loc) in self.0.iter_mut().filter(|(_, loc)| loc.path.is_empty()) {
             loc.path = path.to_string();
         }
     }
+
+    pub fn extend(&mut self, other: RuleErrors) {
+        self.0.extend(other.0);
+    }
+
+    /// lists errors by line, fatal errors first, then by column and category
+    /// - files keep the order their first error was found in, as do otherwise equal errors
+    pub fn sorted(&self) -> Vec<&(FieldError, Location)> {
+        let mut paths: Vec<&str> = vec![];
+        for (_, loc) in &self.0 {
+            if !paths.contains(&loc.path.as_str()) {
+                paths.push(&loc.path);
+            }
+        }
+        let mut errors: Vec<&(FieldError, Location)> = self.0.iter().collect();
+        errors.sort_by_key(|(e, loc)| {
+            let path: usize = paths
+                .iter()
+                .position(|path| *path == loc.path)
+                .unwrap_or_default();
+            (path, loc.line, e.severity(), loc.column, e.category())
+        });
+        errors
+    }
+
+    /// flattens each error & location into a diagnostic for editor integration
+    /// - diagnostics are listed in `sorted` order
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.sorted()
+            .into_iter()
+            .map(|(error, loc)| Diagnostic {
+                file: loc.path.clone(),
+                line: loc.line,
+                column: loc.column,
+                code: error.code(),
+                message: error.to_string(),
+            })
+            .collect()
+    }
+}
+
+/// a single rule error, serialized for `--diagnostics json`
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub code: &'static str,
+    pub message: String,
 }
 
 impl IntoIterator for RuleErrors {
@@ -503,7 +2393,7 @@ impl<'a> IntoIterator for &'a mut RuleErrors {
 impl fmt::Display for RuleErrors {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut errors = String::new();
-        for (e, loc) in &self.0 {
+        for (e, loc) in self.sorted() {
             errors.push_str(&format!(
                 "{}:{}:{}\t{}\n",
                 loc.path, loc.line, loc.column, e,
@@ -519,26 +2409,140 @@ impl Error for RuleErrors {
     }
 }
 
+#[derive(Debug, Error, PartialEq, Clone)]
+#[error("ExpansionLimitExceeded: expanded to {count} rules, over the limit of {max}\n  line {line} contributed the most, expanding to {expanded} rules")]
+pub struct ExpansionLimitExceeded {
+    pub count: usize,
+    pub max: usize,
+    pub line: usize,
+    pub expanded: usize,
+}
+
 #[derive(Debug, Error, PartialEq, Clone)]
 pub enum FieldError {
     #[error("ActionInvalid: expected 'allow', 'deny', 'allowlog', or 'denylog'")]
     ActionInvalid,
     #[error("ProtocolUnsupported: expected 'ip', 'tcp', 'udp', or 'icmp'")]
     ProtocolUnsupported,
-    #[error("PortInvalid: expected a port (0-65535), range of ports, comma-separated list of ports, or 'any'")]
+    #[error("PortInvalid: expected a port (0-65535) or service name, range of ports, comma-separated list of ports, or 'any'")]
     PortInvalid,
     #[error("PortOrderInvalid: port range start must be less than port range end")]
     PortOrderInvalid,
-    #[error("RuleLengthErr: expected 6 fields")]
+    #[error("PortRangeEmpty: half-open port range `start..<end` must cover more than one port")]
+    PortRangeEmpty,
+    #[error("RuleLengthErr: expected 6 fields, or 7 ending in `log`")]
     RuleLengthErr,
     #[error("RuleExpansionUnsupported: both src & dst ports cannot be port lists")]
     RuleExpansionUnsupported,
+    #[error("PrefixOutOfScope: prefix is not covered by the direction filters")]
+    PrefixOutOfScope,
+    #[error("ObjectUndefined: expected `@name` to reference a configured address object")]
+    ObjectUndefined,
+    #[error("AddressFamilyMismatch: expected src & dst prefixes of the same address family")]
+    AddressFamilyMismatch,
+    #[error("FamilyUndeclared: expected rules of the address family declared for the ruleset under `deployment.families`")]
+    FamilyUndeclared,
+    #[error("IncludeUndefined: expected `include <name>` to name a ruleset file beside this one")]
+    IncludeUndefined,
+    #[error("IncludeNameInvalid: expected `include <name>` to name a file beside this one, without path separators or `..`")]
+    IncludeNameInvalid,
+    #[error(
+        "IncludeCycle: ruleset file includes itself, directly or through the files it includes"
+    )]
+    IncludeCycle,
+    #[error("SeqInvalid: expected `seq=N` to number the rule, where N is 0-4294967295")]
+    SeqInvalid,
+    #[error("PrefixInvalid: expected an ipv4 or ipv6 address or cidr, `any`, or a name")]
+    PrefixInvalid,
+    #[error("PortProtocolMismatch: expected 'any', as only 'tcp' & 'udp' name ports, and 'icmp' names a dst type")]
+    PortProtocolMismatch,
+    #[error("LineUnreadable: expected a line of utf-8 text, so parsing stopped here")]
+    LineUnreadable,
+}
+
+/// kinds of rule error, in the order diagnostics list them at the same column
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum ErrorCategory {
+    /// the line or file could not be read as rules at all
+    Structure,
+    /// a single field holds an invalid value
+    Field,
+    /// valid fields that contradict one another
+    Consistency,
+    /// a valid rule that falls outside what the configuration allows
+    Scope,
+}
+
+/// how much of a rule an error leaves unchecked, where fatal errors sort first
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Severity {
+    /// stops the rest of the line, or file, from being checked
+    Fatal,
+    /// reported alongside any other errors of the rule
+    Error,
+}
+
+impl FieldError {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            FieldError::RuleLengthErr
+            | FieldError::LineUnreadable
+            | FieldError::IncludeUndefined
+            | FieldError::IncludeNameInvalid
+            | FieldError::IncludeCycle => ErrorCategory::Structure,
+            FieldError::ActionInvalid
+            | FieldError::ProtocolUnsupported
+            | FieldError::PortInvalid
+            | FieldError::PortOrderInvalid
+            | FieldError::PortRangeEmpty
+            | FieldError::PrefixInvalid
+            | FieldError::SeqInvalid => ErrorCategory::Field,
+            FieldError::AddressFamilyMismatch
+            | FieldError::PortProtocolMismatch
+            | FieldError::RuleExpansionUnsupported => ErrorCategory::Consistency,
+            FieldError::PrefixOutOfScope
+            | FieldError::ObjectUndefined
+            | FieldError::FamilyUndeclared => ErrorCategory::Scope,
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self.category() {
+            ErrorCategory::Structure => Severity::Fatal,
+            _ => Severity::Error,
+        }
+    }
+
+    /// stable identifier for the error, matching the prefix of its message
+    pub fn code(&self) -> &'static str {
+        match self {
+            FieldError::ActionInvalid => "ActionInvalid",
+            FieldError::ProtocolUnsupported => "ProtocolUnsupported",
+            FieldError::PortInvalid => "PortInvalid",
+            FieldError::PortOrderInvalid => "PortOrderInvalid",
+            FieldError::PortRangeEmpty => "PortRangeEmpty",
+            FieldError::RuleLengthErr => "RuleLengthErr",
+            FieldError::RuleExpansionUnsupported => "RuleExpansionUnsupported",
+            FieldError::PrefixOutOfScope => "PrefixOutOfScope",
+            FieldError::ObjectUndefined => "ObjectUndefined",
+            FieldError::AddressFamilyMismatch => "AddressFamilyMismatch",
+            FieldError::FamilyUndeclared => "FamilyUndeclared",
+            FieldError::PortProtocolMismatch => "PortProtocolMismatch",
+            FieldError::PrefixInvalid => "PrefixInvalid",
+            FieldError::SeqInvalid => "SeqInvalid",
+            FieldError::IncludeUndefined => "IncludeUndefined",
+            FieldError::IncludeNameInvalid => "IncludeNameInvalid",
+            FieldError::IncludeCycle => "IncludeCycle",
+            FieldError::LineUnreadable => "LineUnreadable",
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Location {
     pub path: String,
     pub line: usize,
+    /// 1-based character column, matching editor expectations over byte offsets
     pub column: usize,
 }
 
@@ -597,6 +2601,28 @@ mod tests {
         dbg!(PortMap::from_str("9000-9010,65535").unwrap());
     }
 
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_load_matches_sequential() {
+        let dbg = LogLevel::None;
+        let acls_paths: Vec<String> = ["valid.example", "invalid.example", "valid.example"]
+            .iter()
+            .map(|name| format!("./acls/{}.acl", name))
+            .collect();
+
+        let flatten = |results: Vec<LoadResult>| -> Vec<Result<Ruleset, String>> {
+            results
+                .into_iter()
+                .map(|r| r.map_err(|e| e.to_string()))
+                .collect()
+        };
+
+        assert_eq!(
+            flatten(load_parallel(&acls_paths, &Syntax::default(), true, dbg)),
+            flatten(load_sequential(&acls_paths, &Syntax::default(), true, dbg))
+        );
+    }
+
     #[test]
     fn portlist_expansion_valid() {
         let rs: Vec<String> = vec![
@@ -606,6 +2632,41 @@ mod tests {
         dbg!(Ruleset::from_vec(&rs).unwrap());
     }
 
+    #[test]
+    fn port_range_expands_every_port() {
+        let rules: Vec<String> =
+            Ruleset::from_vec(&[String::from("allow tcp outside any inside 8000-8002")])
+                .unwrap()
+                .expand()
+                .into_iter()
+                .map(|r| r.to_string())
+                .collect();
+
+        assert_eq!(
+            rules,
+            [
+                "allow tcp outside any inside 8000",
+                "allow tcp outside any inside 8001",
+                "allow tcp outside any inside 8002",
+            ]
+        );
+    }
+
+    #[test]
+    fn port_range_expansion_covers_full_range() {
+        let rule = Rule::from_str("allow tcp outside 0-65535 inside any").unwrap();
+        let mut expanded = rule.expand();
+
+        assert_eq!(
+            expanded.next().unwrap().to_string(),
+            "allow tcp outside 0 inside any"
+        );
+        assert_eq!(
+            expanded.last().unwrap().to_string(),
+            "allow tcp outside 65535 inside any"
+        );
+    }
+
     #[test]
     fn portlist_expansion_invalid() {
         let rs: Vec<String> = vec![
@@ -615,32 +2676,1557 @@ mod tests {
         dbg!(Ruleset::from_vec(&rs).unwrap_err());
     }
 
+    #[test]
+    fn any_contains_ranges() {
+        let range = PortType::from_str("8000-8010").unwrap();
+
+        assert!(PortType::Any.contains(&range));
+        assert!(!range.contains(&PortType::Any));
+        assert!(PortType::Any.contains(&PortType::Any));
+    }
+
+    #[test]
+    fn range_contains_ports() {
+        let range = PortType::from_str("8000-8010").unwrap();
+
+        assert!(range.contains(&PortType::Port(8005)));
+        assert!(range.contains(&PortType::from_str("8000,8010").unwrap()));
+        assert!(!range.contains(&PortType::Port(8011)));
+        // adjacent ranges coalesce, so together they contain a range spanning both
+        let adjacent = PortType::from_str("80,81-90").unwrap();
+        assert!(adjacent.contains(&PortType::from_str("80-85").unwrap()));
+    }
+
+    #[test]
+    fn disjoint_ports_do_not_overlap() {
+        let low = PortType::from_str("1-1023").unwrap();
+        let high = PortType::from_str("1024..<2048").unwrap();
+
+        assert!(!low.overlaps(&high));
+        assert!(!high.overlaps(&PortType::Port(22)));
+    }
+
+    #[test]
+    fn partial_ranges_overlap() {
+        let web = PortType::from_str("80,8000-8010").unwrap();
+        let alt = PortType::from_str("8005-9000").unwrap();
+
+        assert!(web.overlaps(&alt) && alt.overlaps(&web));
+        assert!(!web.contains(&alt) && !alt.contains(&web));
+        assert!(PortType::Any.overlaps(&alt));
+    }
+
+    #[test]
+    fn star_is_any_as_a_standalone_field() {
+        let star: Rule = Rule::from_str("allow tcp * * 10.0.0.0/8 22").unwrap();
+        let any: Rule = Rule::from_str("allow tcp any any 10.0.0.0/8 22").unwrap();
+
+        assert_eq!(star, any);
+        assert_eq!(star.to_string(), "allow tcp any any 10.0.0.0/8 22");
+        assert_eq!(PortType::from_str("*"), Ok(PortType::Any));
+    }
+
+    #[test]
+    fn star_is_rejected_in_port_lists() {
+        let e = Rule::from_str("allow tcp inside 22,*,443 outside any").unwrap_err();
+
+        assert_eq!(e[0].0, FieldError::PortInvalid);
+        assert!(PortType::from_str("22,*").is_err());
+    }
+
     #[test]
     fn rule_contains_multiple_lists() {
         let rs: Vec<String> = vec!["allow tcp inside 20,21 outside 9000,9010".to_string()];
-        dbg!(Ruleset::from_vec(&rs).unwrap_err());
+        let e = Ruleset::from_vec(&rs)
+            .unwrap()
+            .check_expansion()
+            .unwrap_err();
+
+        assert_eq!(e.0[0].0, FieldError::RuleExpansionUnsupported);
+        assert_eq!((e.0[0].1.line, e.0[0].1.column), (1, 1));
     }
 
     #[test]
-    fn rule_lengths_invalid() {
-        let ss: &str = "short rule.";
-        assert_eq!(Rule::from_str(ss).unwrap_err().0, FieldError::RuleLengthErr);
+    fn multiple_lists_load_unexpanded() {
+        let rs: Vec<String> = vec!["allow tcp inside 20,21 outside 9000-9010".to_string()];
+        let syntax: Syntax = Syntax::default();
 
-        let ls: &str = "this is an extra long rule, ok.";
-        assert_eq!(Rule::from_str(ls).unwrap_err().0, FieldError::RuleLengthErr);
+        assert_eq!(
+            Ruleset::load_inline("site.yaml#web", &rs, &syntax, false)
+                .unwrap()
+                .0
+                .len(),
+            1
+        );
+        assert_eq!(
+            Ruleset::load_inline("site.yaml#web", &rs, &syntax, true)
+                .unwrap()
+                .0
+                .len(),
+            2
+        );
+
+        let rs: Vec<String> = vec!["allow tcp inside 20,21 outside 9000,9010".to_string()];
+        assert!(Ruleset::load_inline("site.yaml#web", &rs, &syntax, false).is_ok());
+        let e = Ruleset::load_inline("site.yaml#web", &rs, &syntax, true).unwrap_err();
+        assert!(e.to_string().contains("site.yaml#web:1:1"));
     }
 
     #[test]
-    fn action_parse_err() {
-        let s: &str = "[failhere] ip inside any outside any";
-        assert_eq!(Rule::from_str(s).unwrap_err().0, FieldError::ActionInvalid);
+    fn inet_ruleset_rejects_ipv6_rules() {
+        let rs: Vec<String> = vec![
+            "allow tcp 10.0.0.0/8 any any 22".to_string(),
+            "allow tcp 2001:db8::/32 any any 22".to_string(),
+            "allow udp any any inside 53".to_string(),
+        ];
+        let ruleset: Ruleset = Ruleset::from_vec(&rs).unwrap();
+        let errors: Vec<(FieldError, Location)> = ruleset
+            .check_family(Family::Inet)
+            .unwrap_err()
+            .into_iter()
+            .collect();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, FieldError::FamilyUndeclared);
+        assert_eq!(errors[0].1.line, 2);
+        assert_eq!(errors[0].1.column, 11);
+        assert_eq!(ruleset.check_family(Family::Inet6).unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn both_family_admits_every_rule() {
+        let rs: Vec<String> = vec![
+            "allow tcp 10.0.0.0/8 any any 22".to_string(),
+            "allow tcp 2001:db8::/32 any any 22".to_string(),
+        ];
+
+        assert!(Ruleset::from_vec(&rs)
+            .unwrap()
+            .check_family(Family::Both)
+            .is_ok());
+    }
+
+    #[test]
+    fn terminal_deny_all_closes_ruleset() {
+        let rs: Vec<String> = vec![
+            "allow tcp inside any outside 22".to_string(),
+            "denylog ip any any any any".to_string(),
+            "disabled allow ip any any any any".to_string(),
+        ];
+
+        assert!(Ruleset::from_vec(&rs).unwrap().is_closed());
+        assert!(!Ruleset::from_vec(&rs[..1]).unwrap().is_closed());
+        assert!(!Ruleset::default().is_closed());
+    }
+
+    #[test]
+    fn partial_deny_leaves_ruleset_open() {
+        let rs: Vec<String> = vec![
+            "allow tcp inside any outside 22".to_string(),
+            "deny ip any any !inside any".to_string(),
+        ];
+        assert!(!Ruleset::from_vec(&rs).unwrap().is_closed());
+
+        let rs: Vec<String> = vec!["deny tcp any any any any".to_string()];
+        assert!(!Ruleset::from_vec(&rs).unwrap().is_closed());
+    }
+
+    #[test]
+    fn default_deny_closes_ruleset() {
+        let rs: Vec<String> = vec!["allow tcp inside any outside 22".to_string()];
+        let ruleset: Ruleset = Ruleset::from_vec(&rs).unwrap();
+
+        assert!(ruleset.clone().with_default(Action::Deny).is_closed());
+        assert!(!ruleset.with_default(Action::Allow).is_closed());
+    }
+
+    #[test]
+    fn default_appends_trailing_deny_all() {
+        let rs: Vec<String> = vec!["allow tcp inside any outside 22".to_string()];
+        let ruleset = Ruleset::from_vec(&rs).unwrap().with_default(Action::Deny);
+        let last = ruleset.0.last().unwrap();
+
+        assert_eq!(ruleset.0.len(), 2);
+        assert_eq!(last.to_string(), "deny ip any any any any");
+        assert!(last.implicit);
+        assert!(!ruleset.0[0].implicit);
+    }
+
+    #[test]
+    fn established_adds_tcp_return_rule() {
+        let rs: Vec<String> = vec!["allow tcp inside any outside 22".to_string()];
+        let ruleset = Ruleset::from_vec(&rs).unwrap().expand().with_established();
+
+        assert_eq!(ruleset.0.len(), 2);
+        assert_eq!(ruleset.0[1].to_string(), "allow tcp outside 22 inside any");
+        assert!(ruleset.0[1].established);
+        assert!(!ruleset.0[0].established);
+    }
+
+    #[test]
+    fn sort_orders_scrambled_rules() {
+        let rules = |lines: &[&str]| -> Ruleset {
+            Ruleset::from_vec(&lines.iter().map(|l| l.to_string()).collect::<Vec<_>>())
+                .unwrap()
+                .expand()
+        };
+        let canonical = rules(&[
+            "allow tcp 10.0.0.0/8 any 192.168.0.0/16 22",
+            "allow tcp 10.0.0.0/8 any 192.168.0.0/16 443",
+            "allow udp 10.0.0.0/8 any 192.168.0.0/16 any",
+            "allow udp 10.0.0.0/8 any 192.168.0.0/16 53",
+            "deny ip any any any any",
+            "denylog tcp any 1024 any 80",
+        ]);
+        let scrambled = rules(&[
+            "denylog tcp any 1024 any 80",
+            "allow udp 10.0.0.0/8 any 192.168.0.0/16 53",
+            "deny ip any any any any",
+            "allow tcp 10.0.0.0/8 any 192.168.0.0/16 443,22",
+            "allow udp 10.0.0.0/8 any 192.168.0.0/16 any",
+        ]);
+
+        assert_eq!(scrambled.sort().to_string(), canonical.to_string());
+    }
+
+    #[test]
+    fn compact_merges_adjacent_ports_into_range() {
+        let rules: Vec<String> = [
+            "allow tcp 10.0.0.0/8 any 192.168.0.0/16 80",
+            "allow tcp 10.0.0.0/8 any 192.168.0.0/16 81",
+            "allow tcp 10.0.0.0/8 any 192.168.0.0/16 82",
+            "allow udp 10.0.0.0/8 any 192.168.0.0/16 83",
+        ]
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+        let compacted = Ruleset::from_vec(&rules).unwrap().expand().compact();
+
+        assert_eq!(compacted.0.len(), 2);
+        assert_eq!(
+            compacted.0[0].dst_port,
+            PortType::Map(PortMap(vec![(80, 82)]))
+        );
+        assert_eq!(compacted.0[1].dst_port, PortType::Port(83));
+    }
+
+    #[test]
+    fn aggregate_merges_adjacent_halves() {
+        let rules: Vec<String> = [
+            "allow tcp 10.0.0.0/25 any inside 22",
+            "allow tcp 10.0.0.128/25 any inside 22",
+            "allow tcp 10.0.1.0/24 any inside 22",
+            "allow tcp 10.0.2.0/24 any inside 443",
+        ]
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+        let aggregated: Vec<String> = Ruleset::from_vec(&rules)
+            .unwrap()
+            .aggregate_prefixes()
+            .into_iter()
+            .map(|r| r.to_string())
+            .collect();
+
+        assert_eq!(
+            aggregated,
+            vec![
+                "allow tcp 10.0.0.0/23 any inside 22",
+                "allow tcp 10.0.2.0/24 any inside 443",
+            ]
+        );
+    }
+
+    #[test]
+    fn aggregate_absorbs_contained_subnets() {
+        let rules: Vec<String> = [
+            "deny udp any any 2001:db8::/32 53",
+            "deny udp any any 2001:db8:1::/48 53",
+            "deny udp any any 2001:db8:ffff::1 53",
+        ]
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+        let aggregated = Ruleset::from_vec(&rules).unwrap().aggregate_prefixes();
+
+        assert_eq!(aggregated.0.len(), 1);
+        assert_eq!(aggregated.0[0].dst_prefix, "2001:db8::/32");
+        assert_eq!(aggregated.0[0].source_line, Some(1));
+    }
+
+    #[test]
+    fn aggregate_keeps_non_adjacent_prefixes() {
+        let rules: Vec<String> = [
+            "allow tcp 10.0.0.0/25 any inside 22",
+            "allow tcp 10.0.1.0/25 any inside 22",
+            "allow tcp outside any inside 22",
+            "allow tcp !10.0.0.0/25 any inside 80",
+            "allow tcp !10.0.0.128/25 any inside 80",
+        ]
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+        let ruleset = Ruleset::from_vec(&rules).unwrap();
+
+        assert_eq!(ruleset.clone().aggregate_prefixes(), ruleset);
+    }
+
+    #[test]
+    fn rules_after_any_any_are_unreachable() {
+        let rules: Vec<String> = [
+            "allow tcp 10.0.0.0/8 any any 22",
+            "allow ip any any any any",
+            "deny udp 10.0.0.0/8 any 192.168.0.0/16 53",
+            "allowlog ip inside any outside any",
+        ]
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+        let ruleset = Ruleset::from_vec(&rules).unwrap();
+
+        assert_eq!(ruleset.find_unreachable(), vec![2, 3]);
+    }
+
+    #[test]
+    fn rules_outside_catch_all_scope_are_reachable() {
+        let rules: Vec<String> = [
+            "allow ip 10.0.0.0/8 any any any",
+            "deny tcp 10.1.0.0/16 any any 22",
+            "deny tcp 172.16.0.0/12 any any 22",
+            "deny tcp 10.0.0.0/8 any any 443",
+        ]
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+        let ruleset = Ruleset::from_vec(&rules)
+            .unwrap()
+            .with_default(Action::Deny);
+
+        assert_eq!(ruleset.find_unreachable(), vec![1, 3]);
+    }
+
+    #[test]
+    fn ip_shadows_tcp_but_tcp_does_not_shadow_udp() {
+        let rules: Vec<String> = [
+            "deny tcp inside any outside 22",
+            "deny udp inside any outside 22",
+            "allow ip inside any outside any",
+            "deny tcp inside any outside 22",
+            "deny icmp inside any outside 8",
+        ]
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+        let ruleset = Ruleset::from_vec(&rules).unwrap();
+
+        assert_eq!(ruleset.find_unreachable(), vec![3, 4]);
+    }
+
+    #[test]
+    fn allow_and_deny_of_the_same_match_conflict() {
+        let rules: Vec<String> = [
+            "allow tcp inside any outside 22",
+            "allowlog tcp inside any outside 22",
+            "deny udp inside any outside 22",
+            "deny tcp inside any outside 22",
+            "deny tcp inside any !outside 22",
+        ]
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+        let ruleset = Ruleset::from_vec(&rules)
+            .unwrap()
+            .with_default(Action::Deny);
+
+        assert_eq!(ruleset.find_conflicts(), vec![(0, 3), (1, 3)]);
+    }
+
+    #[test]
+    fn port_zero_is_an_unexpected_service_port() {
+        let rules: Vec<String> = [
+            "allow udp inside any outside 0",
+            "allow tcp inside any outside 22",
+            "allow tcp inside any outside 50000-50010",
+            "allow icmp inside any outside 0",
+            "allow tcp inside 0 outside any",
+        ]
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+        let ruleset = Ruleset::from_vec(&rules)
+            .unwrap()
+            .with_default(Action::Deny);
+        let ephemeral: PortType = PortType::from_str("49152-65535").unwrap();
+
+        assert_eq!(ruleset.find_unexpected_ports(&[]), vec![0]);
+        assert_eq!(ruleset.find_unexpected_ports(&[ephemeral]), vec![0, 2]);
+    }
+
+    #[test]
+    fn port_ranges_shadow_contained_ports() {
+        let rules: Vec<String> = [
+            "allow tcp inside any 10.0.0.0/8 1000-2000",
+            "deny tcp inside any 10.1.0.0/16 1500,1600-1700",
+            "deny tcp inside any 10.1.0.0/16 1500-2500",
+            "deny tcp inside 80 10.1.0.0/16 1500",
+            "deny tcp inside any !10.1.0.0/16 1500",
+        ]
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+        let ruleset = Ruleset::from_vec(&rules).unwrap();
+
+        assert_eq!(ruleset.find_unreachable(), vec![1, 3]);
+    }
+
+    #[test]
+    fn check_size_names_largest_expansion() {
+        let ports: String = (1..=12)
+            .map(|port| port.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let rules: Vec<String> = vec![
+            String::from("allow tcp inside any outside 22"),
+            format!("allow tcp inside any outside {}", ports),
+        ];
+        let ruleset = Ruleset::from_vec(&rules).unwrap().expand();
+
+        assert_eq!(
+            ruleset.check_size(10).unwrap_err(),
+            ExpansionLimitExceeded {
+                count: 13,
+                max: 10,
+                line: 2,
+                expanded: 12,
+            }
+        );
+    }
+
+    #[test]
+    fn check_size_passes_normal_ruleset() {
+        let ruleset = Ruleset::load(
+            "acls/valid.example.acl",
+            &Syntax::default(),
+            true,
+            LogLevel::None,
+        )
+        .unwrap();
+        assert!(ruleset.check_size(10000).is_ok());
+    }
+
+    #[test]
+    fn portmap_half_open_range_valid() {
+        assert_eq!(
+            PortMap::from_str("80..<90").unwrap(),
+            PortMap(vec![(80, 89)])
+        );
+        assert_eq!(
+            PortMap::from_str("22,8000..<8011").unwrap(),
+            PortMap(vec![(22, 22), (8000, 8010)])
+        );
+    }
+
+    #[test]
+    fn portmap_half_open_range_empty() {
+        assert_eq!(
+            PortMap::from_str("80..<81").unwrap_err(),
+            FieldError::PortRangeEmpty
+        );
+        assert_eq!(
+            PortMap::from_str("80..<80").unwrap_err(),
+            FieldError::PortRangeEmpty
+        );
+        assert_eq!(
+            PortMap::from_str("90..<80").unwrap_err(),
+            FieldError::PortOrderInvalid
+        );
+    }
+
+    #[test]
+    fn portmap_half_open_range_displays_inclusive() {
+        assert_eq!(PortMap::from_str("80..<90").unwrap().to_string(), "80-89");
+        assert_eq!(
+            PortMap::from_str("80..<90").unwrap(),
+            PortMap::from_str("80-89").unwrap()
+        );
+    }
+
+    #[test]
+    fn rule_parses_negated_protocol_and_prefix() {
+        let rule = Rule::from_str("allow !icmp !10.0.0.0/8 any inside any").unwrap();
+
+        assert_eq!(rule.protocol, Protocol::ICMP);
+        assert_eq!(rule.src_prefix, "10.0.0.0/8");
+        assert_eq!(
+            rule.negated,
+            Negated {
+                protocol: true,
+                src_prefix: true,
+                dst_prefix: false,
+            }
+        );
+        assert_eq!(rule.to_string(), "allow !icmp !10.0.0.0/8 any inside any");
+    }
+
+    #[test]
+    fn family_wildcards_parse() {
+        let any4 = Rule::from_str("allow tcp any4 any 10.0.0.0/8 22").unwrap();
+        let any6 = Rule::from_str("allow tcp any6 any inside 22").unwrap();
+        let any = Rule::from_str("allow tcp any any inside 22").unwrap();
+
+        assert_eq!(any4.family(), Some(AddrFamily::Ipv4));
+        assert_eq!(any6.family(), Some(AddrFamily::Ipv6));
+        assert_eq!(any.family(), None);
+        assert!(is_prefix_covered("10.0.0.0/8", &[String::from("any4")]));
+        assert!(!is_prefix_covered("fd00::/8", &[String::from("any4")]));
+    }
+
+    #[test]
+    fn family_mismatch_errs_on_dst_prefix() {
+        let errors = Rule::from_str("allow tcp 10.0.0.0/8 any any6 22").unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, FieldError::AddressFamilyMismatch);
+        assert_eq!(errors[0].1.column, 26);
+    }
+
+    #[test]
+    fn malformed_addresses_err_as_invalid_prefixes() {
+        let errors = Rule::from_str("allow tcp 10.0.0.0/33 any inside 22").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, FieldError::PrefixInvalid);
+        assert_eq!(errors[0].1.column, 11);
+
+        let errors = Rule::from_str("allow tcp inside any !300.1.1.1 22").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, FieldError::PrefixInvalid);
+        assert_eq!(errors[0].1.column, 22);
+
+        assert!(Rule::from_str("allow tcp inside any 010.0.0.1/32 22").is_ok());
+        assert!(Rule::from_str("allow tcp dmz-2 any any6 22").is_ok());
+    }
+
+    #[test]
+    fn rule_rejects_negated_unknown_protocol() {
+        let errors = Rule::from_str("allow !gre any any inside any").unwrap_err();
+        assert_eq!(errors[0].0, FieldError::ProtocolUnsupported);
+        assert_eq!(errors[0].1.column, 7);
+    }
+
+    #[test]
+    fn port_accepts_service_name() {
+        assert_eq!(
+            PortType::from_str("https").unwrap(),
+            PortType::Map(PortMap(vec![(443, 443)]))
+        );
+    }
+
+    #[test]
+    fn port_accepts_mixed_names_and_numbers() {
+        assert_eq!(
+            PortType::from_str("https,ssh,8000-8010,53").unwrap(),
+            PortType::Map(PortMap(vec![(443, 443), (22, 22), (8000, 8010), (53, 53)]))
+        );
+    }
+
+    #[test]
+    fn port_rejects_unknown_name() {
+        assert_eq!(
+            PortType::from_str("gopher").unwrap_err(),
+            FieldError::PortInvalid
+        );
+        assert_eq!(
+            PortType::from_str("http,gopher").unwrap_err(),
+            FieldError::PortInvalid
+        );
+    }
+
+    fn objects() -> ObjectMap {
+        ObjectMap::from([
+            (String::from("bastion"), vec![String::from("10.0.0.10/32")]),
+            (
+                String::from("web_servers"),
+                vec![String::from("10.0.1.0/24"), String::from("10.0.2.0/24")],
+            ),
+        ])
+    }
+
+    #[test]
+    fn objects_resolve_single_member() {
+        let ruleset = Ruleset::from_vec(&[String::from("allow tcp @bastion any inside 22")])
+            .unwrap()
+            .resolve_objects(&objects())
+            .unwrap()
+            .expand();
+
+        assert_eq!(ruleset.0.len(), 1);
+        assert_eq!(
+            ruleset.0[0].to_string(),
+            "allow tcp 10.0.0.10/32 any inside 22"
+        );
+    }
+
+    #[test]
+    fn objects_resolve_multi_member() {
+        let ruleset = Ruleset::from_vec(&[String::from("allow tcp outside any @web_servers 443")])
+            .unwrap()
+            .resolve_objects(&objects())
+            .unwrap()
+            .expand();
+
+        assert_eq!(
+            ruleset.0.iter().map(Rule::to_string).collect::<Vec<_>>(),
+            [
+                "allow tcp outside any 10.0.1.0/24 443",
+                "allow tcp outside any 10.0.2.0/24 443",
+            ]
+        );
+    }
+
+    #[test]
+    fn objects_undefined_reference_errs_with_line() {
+        let errors = Ruleset::from_vec(&[
+            String::from("allow tcp @bastion any inside 22"),
+            String::from("allow tcp outside any @db_servers 5432"),
+        ])
+        .unwrap()
+        .resolve_objects(&objects())
+        .unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors.0[0].0, FieldError::ObjectUndefined);
+        assert_eq!(errors.0[0].1.line, 2);
+        assert_eq!(errors.0[0].1.column, 23);
+    }
+
+    #[test]
+    fn load_reports_every_error_at_file_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("two-bad.acl");
+        std::fs::write(
+            &path,
+            "# ingress\nallow tcp outside any inside 22\nallow tcp outside any inside 99999\n\npermit udp outside any inside 53\n",
+        )
+        .unwrap();
+        let path: &str = path.to_str().unwrap();
+        let e = Ruleset::load(path, &Syntax::default(), true, LogLevel::None).unwrap_err();
+        let errors: &RuleErrors = e.downcast_ref::<RuleErrors>().unwrap();
+
+        assert_eq!(
+            errors.0,
+            vec![
+                (
+                    FieldError::PortInvalid,
+                    Location::new(path.to_string(), 3, 30)
+                ),
+                (
+                    FieldError::ActionInvalid,
+                    Location::new(path.to_string(), 5, 1)
+                ),
+            ]
+        );
+        assert!(e
+            .to_string()
+            .starts_with(&format!("{}:3:30\tPortInvalid", path)));
+    }
+
+    #[test]
+    fn include_splices_rules_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("base.acl"),
+            "allow icmp outside any inside 8\ndeny tcp outside any inside 23\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("site.acl"),
+            "allow tcp outside any inside 22\ninclude base # shared\nallow tcp outside any inside 443\n",
+        )
+        .unwrap();
+        let site = dir.path().join("site.acl");
+        let rules: Vec<String> = Ruleset::load(
+            site.to_str().unwrap(),
+            &Syntax::default(),
+            true,
+            LogLevel::None,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|r| r.to_string())
+        .collect();
+
+        assert_eq!(
+            rules,
+            vec![
+                "allow tcp outside any inside 22",
+                "allow icmp outside any inside 8",
+                "deny tcp outside any inside 23",
+                "allow tcp outside any inside 443",
+            ]
+        );
+
+        std::fs::write(
+            dir.path().join("base.acl"),
+            "allow tcp outside any inside 2x\n",
+        )
+        .unwrap();
+        let e = Ruleset::load(
+            site.to_str().unwrap(),
+            &Syntax::default(),
+            true,
+            LogLevel::None,
+        )
+        .unwrap_err();
+        let errors: &RuleErrors = e.downcast_ref::<RuleErrors>().unwrap();
+        let base = dir.path().join("base.acl");
+        assert_eq!(
+            errors.0,
+            vec![(
+                FieldError::PortInvalid,
+                Location::new(base.to_string_lossy().to_string(), 1, 30)
+            )]
+        );
+    }
+
+    #[test]
+    fn include_cycle_is_located_at_directive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("loop.acl");
+        std::fs::write(&path, "allow tcp outside any inside 22\ninclude  loop\n").unwrap();
+        let e = Ruleset::load(
+            path.to_str().unwrap(),
+            &Syntax::default(),
+            true,
+            LogLevel::None,
+        )
+        .unwrap_err();
+        let errors: &RuleErrors = e.downcast_ref::<RuleErrors>().unwrap();
+
+        assert_eq!(
+            errors.0,
+            vec![(
+                FieldError::IncludeCycle,
+                Location::new(path.to_str().unwrap().to_string(), 2, 10)
+            )]
+        );
+    }
+
+    #[test]
+    fn include_outside_the_directory_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let acls = dir.path().join("acls");
+        std::fs::create_dir(&acls).unwrap();
+        std::fs::write(
+            dir.path().join("x.acl"),
+            "allow tcp outside any inside 22\n",
+        )
+        .unwrap();
+        let path = acls.join("escape.acl");
+        std::fs::write(&path, "include ../x\ninclude /etc/x\n").unwrap();
+        let e = Ruleset::load(
+            path.to_str().unwrap(),
+            &Syntax::default(),
+            true,
+            LogLevel::None,
+        )
+        .unwrap_err();
+        let errors: &RuleErrors = e.downcast_ref::<RuleErrors>().unwrap();
+
+        let location = |line: usize| Location::new(path.to_str().unwrap().to_string(), line, 9);
+        assert_eq!(
+            errors.0,
+            vec![
+                (FieldError::IncludeNameInvalid, location(1)),
+                (FieldError::IncludeNameInvalid, location(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn load_names_unreadable_file() {
+        let e = Ruleset::load("acls/missing.acl", &Syntax::default(), true, LogLevel::None)
+            .unwrap_err();
+        assert!(e.to_string().starts_with("acls/missing.acl: "));
+    }
+
+    #[test]
+    fn comment_prefix_skips_comments() {
+        let rules: Vec<String> = [
+            "; management access",
+            "allow tcp outside any inside 22 ; ssh",
+            "",
+            "# hash",
+        ]
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+        let syntax: Syntax = Syntax {
+            comment_prefix: String::from(";"),
+            ..Syntax::default()
+        };
+        let errors = Ruleset::from_vec_with(&rules, &syntax).unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors.0[0].1.line, 4);
+
+        let ruleset = Ruleset::from_vec_with(&rules[..3], &syntax).unwrap();
+        assert_eq!(ruleset.0.len(), 1);
+        assert_eq!(ruleset.0[0].source_line, Some(2));
+        assert_eq!(ruleset.0[0].to_string(), "allow tcp outside any inside 22");
+    }
+
+    #[test]
+    fn desc_comment_is_kept_as_description() {
+        let rules: Vec<String> = vec![
+            String::from("allow tcp inside any outside 22 # desc: mgmt ssh"),
+            String::from("allow tcp inside any outside 443 # https"),
+        ];
+        let ruleset = Ruleset::from_vec(&rules).unwrap();
+
+        assert_eq!(ruleset.0[0].description.as_deref(), Some("mgmt ssh"));
+        assert_eq!(ruleset.0[0].to_string(), "allow tcp inside any outside 22");
+        assert_eq!(ruleset.0[1].description, None);
+        assert_eq!(
+            ruleset.to_acl(),
+            "allow tcp inside any outside 22 # desc: mgmt ssh\nallow tcp inside any outside 443\n"
+        );
+    }
+
+    #[test]
+    fn crlf_rules_parse_like_lf() {
+        let acl: &str =
+            "allow tcp inside any outside 22 # desc: mgmt ssh\n\nallow udp inside any outside 53\n";
+        let lf: Vec<String> = acl.split('\n').map(String::from).collect();
+        let crlf: Vec<String> = acl
+            .replace('\n', "\r\n")
+            .split('\n')
+            .map(String::from)
+            .collect();
+
+        assert!(crlf[0].ends_with('\r'));
+        assert_eq!(Ruleset::from_vec(&crlf), Ruleset::from_vec(&lf));
+        assert_eq!(
+            Ruleset::from_vec(&crlf).unwrap().0[0]
+                .description
+                .as_deref(),
+            Some("mgmt ssh")
+        );
+
+        let short: Vec<String> = vec![String::from("allow tcp inside any outside\r")];
+        assert_eq!(
+            Ruleset::from_vec(&short).unwrap_err().0,
+            vec![(
+                FieldError::RuleLengthErr,
+                Location::new(String::new(), 1, 29)
+            )]
+        );
+    }
+
+    #[test]
+    fn from_reader_matches_from_vec() {
+        let valid: &str = "# ingress\nallow tcp outside any inside 22 # desc: ssh\n\nseq=30 deny udp any any inside 53,161\n";
+        let invalid: &str =
+            "allow tcp outside any inside 99999\npermit udp outside any inside 53\n";
+        let lines = |acl: &str| acl.lines().map(String::from).collect::<Vec<String>>();
+
+        for acl in [valid, invalid] {
+            assert_eq!(
+                Ruleset::from_reader(std::io::Cursor::new(acl)),
+                Ruleset::from_vec(&lines(acl))
+            );
+        }
+        assert!(Ruleset::from_reader(std::io::Cursor::new(invalid)).is_err());
+    }
+
+    #[test]
+    fn from_reader_stops_at_unreadable_line() {
+        let acl: &[u8] =
+            b"allow tcp outside any inside 22\nallow \xff\nallow tcp outside any inside 80\n";
+
+        assert_eq!(
+            Ruleset::from_reader(acl).unwrap_err().0,
+            vec![(
+                FieldError::LineUnreadable,
+                Location::new(String::new(), 2, 1)
+            )]
+        );
+    }
+
+    #[test]
+    fn crlf_file_loads_like_lf() {
+        let dir = tempfile::tempdir().unwrap();
+        let (lf, crlf) = (dir.path().join("lf.acl"), dir.path().join("crlf.acl"));
+        let acl: &str =
+            "# ingress\nallow tcp outside any inside 22\nallow udp outside any inside 53";
+        std::fs::write(&lf, acl).unwrap();
+        std::fs::write(&crlf, acl.replace('\n', "\r\n") + "\r").unwrap();
+        let load = |path: &std::path::Path| {
+            Ruleset::load(
+                path.to_str().unwrap(),
+                &Syntax::default(),
+                true,
+                LogLevel::None,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(load(&crlf), load(&lf));
+    }
+
+    #[test]
+    fn keywords_read_regardless_of_case() {
+        let rules: Vec<String> = vec![
+            String::from("ALLOW TCP Outside ANY !Inside SSH LOG"),
+            String::from("Disabled SEQ=10 Deny Icmp Outside any Inside 8"),
+        ];
+
+        let ruleset = Ruleset::from_vec(&rules).unwrap();
+        assert_eq!(
+            ruleset.0[0].to_string(),
+            "allowlog tcp Outside any !Inside 22"
+        );
+        assert_eq!(
+            ruleset.0[1].to_string(),
+            "disabled seq=10 deny icmp Outside any Inside 8"
+        );
+        assert_eq!(Protocol::from_str("TCP"), Ok(Protocol::TCP));
+        assert_eq!(Action::from_str("DenyLog"), Ok(Action::DenyLog));
+    }
+
+    #[test]
+    fn case_sensitive_errs_on_uppercase_keywords() {
+        let rules: Vec<String> = vec![String::from("ALLOW TCP Outside any Inside SSH")];
+        let syntax: Syntax = Syntax {
+            case_sensitive: true,
+            ..Syntax::default()
+        };
+
+        let errors = Ruleset::from_vec_with(&rules, &syntax).unwrap_err();
+        let located: Vec<(FieldError, usize)> = errors
+            .0
+            .iter()
+            .map(|(e, loc)| (e.clone(), loc.column))
+            .collect();
+        assert_eq!(
+            located,
+            vec![
+                (FieldError::ActionInvalid, 1),
+                (FieldError::ProtocolUnsupported, 7),
+                (FieldError::PortInvalid, 30),
+            ]
+        );
+
+        let lowercase: Vec<String> = vec![String::from("allow tcp Outside any Inside ssh")];
+        let ruleset = Ruleset::from_vec_with(&lowercase, &syntax).unwrap();
+        assert_eq!(ruleset.0[0].to_string(), "allow tcp Outside any Inside 22");
+    }
+
+    #[test]
+    fn trailing_log_matches_fused_action() {
+        let fused = Rule::from_str("allowlog tcp outside any inside 22").unwrap();
+        let trailing = Rule::from_str("allow tcp outside any inside 22 log").unwrap();
+
+        assert_eq!(fused, trailing);
+        assert!(trailing.log);
+        assert_eq!(trailing.to_string(), "allowlog tcp outside any inside 22");
+        assert_eq!(
+            Rule::from_str("denylog tcp outside any inside 22 log").unwrap(),
+            Rule::from_str("deny tcp outside any inside 22 log").unwrap()
+        );
+        assert!(
+            !Rule::from_str("deny tcp outside any inside 22")
+                .unwrap()
+                .log
+        );
+        assert_eq!(
+            Rule::from_str("allow tcp outside any inside 22 logs").unwrap_err()[0].0,
+            FieldError::RuleLengthErr
+        );
+    }
+
+    #[test]
+    fn disabled_rules_parse_and_validate() {
+        for line in [
+            "disabled allow tcp inside any outside 22",
+            "!allow tcp inside any outside 22",
+            "! allow tcp inside any outside 22",
+        ] {
+            let rule = Rule::from_str(line).unwrap();
+            assert!(!rule.is_enabled());
+            assert_eq!(rule.to_string(), "disabled allow tcp inside any outside 22");
+        }
+        let rule = Rule::from_str("disabled seq=5 allow tcp inside any outside 22").unwrap();
+        assert_eq!(
+            rule.to_string(),
+            "disabled seq=5 allow tcp inside any outside 22"
+        );
+
+        let e = Rule::from_str("!allow tcp inside any outside 70000").unwrap_err();
+        assert_eq!(
+            e,
+            vec![(FieldError::PortInvalid, Location::new(String::new(), 0, 31))]
+        );
+        let e = Rule::from_str("!permit tcp inside any outside 22").unwrap_err();
+        assert_eq!(
+            e,
+            vec![(
+                FieldError::ActionInvalid,
+                Location::new(String::new(), 0, 2)
+            )]
+        );
+    }
+
+    #[test]
+    fn disabled_rules_are_dropped_and_never_shadow() {
+        let rules: Vec<String> = [
+            "disabled allow ip inside any outside any",
+            "deny tcp inside any outside 22",
+            "disabled deny tcp inside any outside 22,23",
+        ]
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+        let ruleset = Ruleset::from_vec(&rules).unwrap();
+
+        assert_eq!(ruleset.find_unreachable(), Vec::<usize>::new());
+        assert_eq!(ruleset.find_conflicts(), vec![]);
+        let enabled = ruleset.without_disabled().expand();
+        let lines: Vec<String> = enabled.0.iter().map(Rule::to_string).collect();
+        assert_eq!(lines, vec!["deny tcp inside any outside 22"]);
+    }
+
+    #[test]
+    fn expansions_keep_their_source_line() {
+        let rules: Vec<String> = [
+            "# web",
+            "",
+            "allow tcp inside any outside 22",
+            "",
+            "allow tcp inside any outside 80,443,8080",
+        ]
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+        let ruleset = Ruleset::from_vec(&rules)
+            .unwrap()
+            .with_default(Action::Deny);
+        let lines: Vec<Option<usize>> = ruleset.expand().0.iter().map(Rule::source_line).collect();
+
+        assert_eq!(lines, vec![Some(3), Some(5), Some(5), Some(5), None]);
+        assert_eq!(
+            Rule::from_str("deny ip any any any any")
+                .unwrap()
+                .source_line(),
+            None
+        );
+    }
+
+    #[test]
+    fn seq_token_numbers_rule_and_its_expansions() {
+        let rule = Rule::from_str("seq=100 allow tcp inside any outside 80,443 log").unwrap();
+        assert_eq!(rule.seq, Some(100));
+        assert_eq!(rule.action, Action::AllowLog);
+        assert_eq!(
+            rule.to_string(),
+            "seq=100 allowlog tcp inside any outside 80,443"
+        );
+
+        let seqs: Vec<Option<u32>> = rule.expand().map(|r| r.seq).collect();
+        assert_eq!(seqs, vec![Some(100), Some(102)]);
+
+        let errors = Rule::from_str("seq=x allow tcp inside any outside 8o").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                (FieldError::SeqInvalid, Location::new(String::new(), 0, 1)),
+                (FieldError::PortInvalid, Location::new(String::new(), 0, 36)),
+            ]
+        );
+    }
+
+    #[test]
+    fn sequence_numbers_rules_by_step() {
+        let rules: Vec<String> = [
+            "allow tcp inside any outside 22",
+            "allow tcp inside any outside 80,443",
+            "seq=105 deny udp inside any outside 53",
+            "deny ip inside any outside any",
+        ]
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+        let seqs: Vec<Option<u32>> = Ruleset::from_vec(&rules)
+            .unwrap()
+            .expand()
+            .sequence()
+            .into_iter()
+            .map(|r| r.seq)
+            .collect();
+
+        assert_eq!(
+            seqs,
+            vec![Some(10), Some(20), Some(30), Some(105), Some(110)]
+        );
+
+        let closed: Vec<Option<u32>> = Ruleset::from_vec(&rules[..1])
+            .unwrap()
+            .sequence()
+            .with_established()
+            .with_default(Action::Deny)
+            .into_iter()
+            .map(|r| r.seq)
+            .collect();
+        assert_eq!(closed, vec![Some(10), Some(11), Some(20)]);
+
+        // the return rule of `seq=19` takes 20, so numbering resumes at 30
+        let rules: Vec<String> = vec![
+            String::from("seq=19 allow tcp inside any outside 22"),
+            String::from("allow tcp inside any outside 443"),
+        ];
+        let closed: Vec<Option<u32>> = Ruleset::from_vec(&rules)
+            .unwrap()
+            .sequence()
+            .with_established()
+            .into_iter()
+            .map(|r| r.seq)
+            .collect();
+        assert_eq!(closed, vec![Some(19), Some(20), Some(30), Some(31)]);
+    }
+
+    #[test]
+    fn ports_require_a_ported_protocol() {
+        let errors = Rule::from_str("allow icmp inside 80 outside 443").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![(
+                FieldError::PortProtocolMismatch,
+                Location::new(String::new(), 0, 19)
+            )]
+        );
+
+        let errors = Rule::from_str("allow ip inside any outside 443").unwrap_err();
+        assert_eq!(errors[0].0, FieldError::PortProtocolMismatch);
+        assert_eq!(errors[0].1.column, 29);
+
+        let errors = Rule::from_str("deny !tcp inside any outside 22").unwrap_err();
+        assert_eq!(errors[0].0, FieldError::PortProtocolMismatch);
+
+        assert!(Rule::from_str("allow tcp inside 1024-2048 outside 443").is_ok());
+        assert!(Rule::from_str("allow icmp inside any outside 8").is_ok());
+    }
+
+    #[test]
+    fn to_acl_round_trips_canonical_text() {
+        let rules: Vec<String> = [
+            "allow  icmp\toutside any inside 8",
+            "allowlog tcp outside any !inside 80,443,8000..<8011",
+            "denylog udp 10.0.0.0/8 1024-2048 inside snmp,snmptrap",
+            "deny !tcp outside any inside any",
+        ]
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+        let acl: String = Ruleset::from_vec(&rules).unwrap().to_acl();
+
+        assert_eq!(
+            acl,
+            "allow icmp outside any inside 8\n\
+             allowlog tcp outside any !inside 80,443,8000-8010\n\
+             denylog udp 10.0.0.0/8 1024-2048 inside 161,162\n\
+             deny !tcp outside any inside any\n"
+        );
+
+        let lines: Vec<String> = acl.lines().map(String::from).collect();
+        let reparsed: Ruleset = Ruleset::from_vec(&lines).unwrap();
+        assert_eq!(reparsed, Ruleset::from_vec(&rules).unwrap());
+        assert_eq!(reparsed.to_acl(), acl);
+    }
+
+    #[test]
+    fn merge_appends_overrides_after_base() {
+        let base: Vec<String> = [
+            "allow icmp outside any inside 8",
+            "deny tcp outside any inside 22",
+        ]
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+        let overrides: Vec<String> = vec![String::from("allow tcp outside any inside 443")];
+        let mut ruleset: Ruleset = Ruleset::from_vec(&base).unwrap();
+        ruleset.merge(Ruleset::from_vec(&overrides).unwrap());
+        let rules: Vec<String> = ruleset
+            .expand()
+            .into_iter()
+            .map(|r| r.to_string())
+            .collect();
+
+        assert_eq!(
+            rules,
+            vec![
+                "allow icmp outside any inside 8",
+                "deny tcp outside any inside 22",
+                "allow tcp outside any inside 443",
+            ]
+        );
+    }
+
+    #[test]
+    fn stats_count_expanded_rules() {
+        let stats = Ruleset::load(
+            "acls/valid.example.acl",
+            &Syntax::default(),
+            true,
+            LogLevel::None,
+        )
+        .unwrap()
+        .stats();
+
+        assert_eq!(stats.total, 7);
+        assert_eq!(
+            stats.actions,
+            BTreeMap::from([
+                (String::from("allow"), 1),
+                (String::from("allowlog"), 2),
+                (String::from("deny"), 2),
+                (String::from("denylog"), 2),
+            ])
+        );
+        assert_eq!(
+            stats.protocols,
+            BTreeMap::from([
+                (String::from("icmp"), 1),
+                (String::from("ip"), 1),
+                (String::from("tcp"), 3),
+                (String::from("udp"), 2),
+            ])
+        );
+    }
+
+    #[test]
+    fn established_skips_udp_rules() {
+        let rs: Vec<String> = vec!["allow udp inside any outside 53".to_string()];
+        let ruleset = Ruleset::from_vec(&rs).unwrap();
+
+        assert_eq!(ruleset.clone().with_established(), ruleset);
+    }
+
+    #[test]
+    fn prefix_inside_filter_passes() {
+        let rs: Vec<String> = vec![
+            "allow tcp outside any 10.0.1.0/24 22".to_string(),
+            "allow tcp outside any 10.0.2.1 443".to_string(),
+        ];
+        let src: Vec<String> = vec!["outside".to_string()];
+        let dst: Vec<String> = vec!["10.0.0.0/16".to_string()];
+        assert!(Ruleset::from_vec(&rs)
+            .unwrap()
+            .check_filters(&src, &dst)
+            .is_ok());
+    }
+
+    #[test]
+    fn prefix_outside_filter_fails() {
+        let rs: Vec<String> = vec![
+            "allow tcp outside any 10.0.1.0/24 22".to_string(),
+            "allow tcp outside any 192.168.0.0/24 443".to_string(),
+        ];
+        let src: Vec<String> = vec!["outside".to_string()];
+        let dst: Vec<String> = vec!["10.0.0.0/16".to_string()];
+        let errors: Vec<(FieldError, Location)> = Ruleset::from_vec(&rs)
+            .unwrap()
+            .check_filters(&src, &dst)
+            .unwrap_err()
+            .into_iter()
+            .collect();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, FieldError::PrefixOutOfScope);
+        assert_eq!(errors[0].1.line, 2);
+        assert_eq!(errors[0].1.column, 23);
+    }
+
+    #[test]
+    fn negated_prefix_is_covered_only_by_any() {
+        let rs: Vec<String> = vec!["allow tcp !10.0.0.0/8 any 10.1.0.0/24 443".to_string()];
+        let ruleset: Ruleset = Ruleset::from_vec(&rs).unwrap();
+        let dst: Vec<String> = vec!["10.1.0.0/16".to_string()];
+
+        let errors = ruleset
+            .check_filters(&["10.0.0.0/8".to_string()], &dst)
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(ruleset.check_filters(&["any".to_string()], &dst).is_ok());
+    }
+
+    #[test]
+    fn supernet_contains_subnet() {
+        let supernet = Prefix::from("10.0.0.0/8");
+        let subnet = Prefix::from("10.1.0.0/16");
+
+        assert!(supernet.contains(&subnet) && !subnet.contains(&supernet));
+        assert!(supernet.overlaps(&subnet) && subnet.overlaps(&supernet));
+        assert!(Prefix::from("any").contains(&supernet));
+        assert!(Prefix::from("any4").contains(&supernet));
+        assert!(!Prefix::from("any6").contains(&supernet));
+        assert_eq!(subnet.to_string(), "10.1.0.0/16");
+    }
+
+    #[test]
+    fn builder_matches_parsed_rule() {
+        let built: Rule = RuleBuilder::new()
+            .action(Action::AllowLog)
+            .protocol(Protocol::TCP)
+            .src_prefix(Prefix::from("10.0.0.0/24"))
+            .dst_prefix(Prefix::from("inside"))
+            .dst_port("80,443".parse().unwrap())
+            .negated(Negated {
+                dst_prefix: true,
+                ..Negated::default()
+            })
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            built,
+            "allowlog tcp 10.0.0.0/24 any !inside 80,443"
+                .parse::<Rule>()
+                .unwrap()
+        );
+        assert_eq!(built.src_port, PortType::Any);
+    }
+
+    #[test]
+    fn builder_errs_on_invalid_fields() {
+        let rule = RuleBuilder::new()
+            .action(Action::Allow)
+            .protocol(Protocol::TCP);
+
+        assert_eq!("70000".parse::<PortType>(), Err(FieldError::PortInvalid));
+        assert_eq!(
+            rule.clone()
+                .src_prefix(Prefix::from("10.0.0.0/8"))
+                .dst_prefix(Prefix::from("fd00::/8"))
+                .build(),
+            Err(FieldError::AddressFamilyMismatch)
+        );
+        assert_eq!(
+            rule.clone().dst_prefix(Prefix::from("10.0.0.0/33")).build(),
+            Err(FieldError::PrefixInvalid)
+        );
+        assert_eq!(
+            rule.clone().dst_prefix(Prefix::from("inside any")).build(),
+            Err(FieldError::PrefixInvalid)
+        );
+        assert_eq!(
+            rule.clone()
+                .negated(Negated {
+                    protocol: true,
+                    ..Negated::default()
+                })
+                .dst_port(PortType::Port(22))
+                .build(),
+            Err(FieldError::PortProtocolMismatch)
+        );
+        assert_eq!(
+            rule.protocol(Protocol::ICMP)
+                .src_port(PortType::Port(22))
+                .build(),
+            Err(FieldError::PortProtocolMismatch)
+        );
+        assert_eq!(RuleBuilder::new().build(), Err(FieldError::ActionInvalid));
+    }
+
+    #[test]
+    fn describe_reads_ranges_and_any_ports() {
+        let rule: Rule = "allowlog tcp 10.0.0.0/24 any 10.1.0.0/24 8000-8010"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            rule.describe(),
+            "Permit and log TCP from 10.0.0.0/24 (any source port) to 10.1.0.0/24 ports 8000 through 8010"
+        );
+    }
+
+    #[test]
+    fn describe_lists_ports() {
+        let rule: Rule = "deny udp !inside 53 any4 67,68,1000-2000".parse().unwrap();
+
+        assert_eq!(
+            rule.describe(),
+            "Deny UDP from anything except inside port 53 to any IPv4 address ports 67, 68 and 1000 through 2000"
+        );
+    }
+
+    #[test]
+    fn describe_names_icmp_types() {
+        let rule: Rule = "allow icmp any any 10.0.0.0/8 8".parse().unwrap();
+        assert_eq!(
+            rule.describe(),
+            "Permit ICMP from any address to 10.0.0.0/8 ICMP type 8"
+        );
+
+        let rule: Rule = "deny ip any any any any".parse().unwrap();
+        assert_eq!(rule.describe(), "Deny IP from any address to any address");
+    }
+
+    #[test]
+    fn addresses_normalize_on_parse() {
+        assert_eq!(Prefix::from("010.000.000.001"), "10.0.0.1");
+        assert_eq!(Prefix::from("010.000.000.000/008"), "10.0.0.0/8");
+        assert_eq!(Prefix::from("2001:DB8:0:0:0:0:0:1/128"), "2001:db8::1/128");
+        assert_eq!(Prefix::from("10.0.0.1/32"), "10.0.0.1/32");
+        assert_eq!(Prefix::from("inside"), "inside");
+        assert_eq!(Prefix::from("10.0.0.256"), "10.0.0.256");
+        assert_eq!(Prefix::from("10.0.0.0/33"), "10.0.0.0/33");
+
+        let rule: Rule = "allow tcp 010.001.000.000/16 any 010.000.000.001 443"
+            .parse()
+            .unwrap();
+        assert!(rule.to_string().contains("10.1.0.0/16 any 10.0.0.1 443"));
+    }
+
+    #[test]
+    fn host_is_in_network() {
+        let network = Prefix::from("2001:db8::/32");
+
+        assert!(network.contains(&Prefix::from("2001:db8::1")));
+        assert!(Prefix::from("10.0.0.0/24").contains(&Prefix::from("10.0.0.5/32")));
+        assert!(!Prefix::from("10.0.0.5").contains(&Prefix::from("10.0.0.0/24")));
+    }
+
+    #[test]
+    fn disjoint_prefixes_neither_contain_nor_overlap() {
+        let (a, b) = (Prefix::from("10.0.0.0/8"), Prefix::from("172.16.0.0/12"));
+
+        assert!(!a.contains(&b) && !a.overlaps(&b));
+        assert!(!a.overlaps(&Prefix::from("::/0")));
+        assert!(!Prefix::from("inside").overlaps(&Prefix::from("outside")));
+        assert!(Prefix::from("inside").contains(&Prefix::from("inside")));
+        assert!(!Prefix::from("inside").contains(&a));
+    }
+
+    #[test]
+    fn transform_rewrites_mapped_src_prefix() {
+        let rule = Rule::from_str("allow tcp 10.0.0.0/24 any outside 22").unwrap();
+        let src: PrefixMap =
+            PrefixMap::from([(String::from("10.0.0.0/24"), String::from("192.168.0.0/24"))]);
+
+        assert_eq!(
+            rule.transform(Some(&src), None).src_prefix,
+            Prefix::from("192.168.0.0/24")
+        );
+    }
+
+    #[test]
+    fn transform_ignores_unmapped_src_prefix() {
+        let rule = Rule::from_str("allow tcp 10.1.0.0/24 any outside 22").unwrap();
+        let src: PrefixMap =
+            PrefixMap::from([(String::from("10.0.0.0/24"), String::from("192.168.0.0/24"))]);
+
+        assert_eq!(rule.clone().transform(Some(&src), None), rule);
+    }
+
+    #[test]
+    fn rule_lengths_invalid() {
+        let ss: &str = "short rule.";
+        assert_eq!(
+            Rule::from_str(ss).unwrap_err()[0].0,
+            FieldError::RuleLengthErr
+        );
+
+        let ls: &str = "this is an extra long rule, ok.";
+        assert_eq!(
+            Rule::from_str(ls).unwrap_err()[0].0,
+            FieldError::RuleLengthErr
+        );
+    }
+
+    #[test]
+    fn rule_reports_every_field_error() {
+        let s: &str = "[bad] [bad] inside [bad] outside [bad]";
+        let errors: Vec<FieldError> = Rule::from_str(s)
+            .unwrap_err()
+            .into_iter()
+            .map(|(e, _)| e)
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                FieldError::ActionInvalid,
+                FieldError::ProtocolUnsupported,
+                FieldError::PortInvalid,
+                FieldError::PortInvalid,
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_sort_structure_first_then_by_column() {
+        let mut errors: RuleErrors = RuleErrors::new();
+        let at = |line: usize, column: usize| Location::new(String::from("a.acl"), line, column);
+        errors.push(FieldError::PrefixOutOfScope, at(2, 0));
+        errors.push(FieldError::PortInvalid, at(1, 20));
+        errors.push(FieldError::PortProtocolMismatch, at(1, 7));
+        errors.push(FieldError::ActionInvalid, at(1, 7));
+        errors.push(FieldError::RuleLengthErr, at(1, 31));
+        errors.push(FieldError::ActionInvalid, at(1, 1));
+
+        let sorted: Vec<(&str, usize)> = errors
+            .sorted()
+            .into_iter()
+            .map(|(e, loc)| (e.code(), loc.line))
+            .collect();
+        assert_eq!(
+            sorted,
+            vec![
+                ("RuleLengthErr", 1),
+                ("ActionInvalid", 1),
+                ("ActionInvalid", 1),
+                ("PortProtocolMismatch", 1),
+                ("PortInvalid", 1),
+                ("PrefixOutOfScope", 2),
+            ]
+        );
+        assert!(errors.to_string().starts_with("a.acl:1:31\tRuleLengthErr"));
+        assert_eq!(errors.diagnostics()[5].code, "PrefixOutOfScope");
+    }
+
+    #[test]
+    fn protocol_column_tab_separated() {
+        let s: &str = "deny\t[failhere]\tinside\tany\toutside\tany";
+        assert_eq!(Rule::from_str(s).unwrap_err()[0].1.column, 6);
+    }
+
+    #[test]
+    fn port_column_counts_multibyte_prefix_as_characters() {
+        let s: &str = "allow tcp zoné any intérieur 99999";
+        assert_eq!(Rule::from_str(s).unwrap_err()[0].1.column, 30);
+
+        let s: &str = "allow tcp zoné any intérieur";
+        assert_eq!(Rule::from_str(s).unwrap_err()[0].1.column, 29);
+    }
+
+    #[test]
+    fn protocol_column_double_space_separated() {
+        let s: &str = "deny  [failhere]  inside  any  outside  any";
+        assert_eq!(Rule::from_str(s).unwrap_err()[0].1.column, 7);
+    }
+
+    #[test]
+    fn action_parse_err() {
+        let s: &str = "[failhere] ip inside any outside any";
+        assert_eq!(
+            Rule::from_str(s).unwrap_err()[0].0,
+            FieldError::ActionInvalid
+        );
     }
 
     #[test]
     fn protocol_parse_err() {
         let s: &str = "deny [failhere] inside any outside any";
         assert_eq!(
-            Rule::from_str(s).unwrap_err().0,
+            Rule::from_str(s).unwrap_err()[0].0,
             FieldError::ProtocolUnsupported
         );
     }
@@ -648,12 +4234,12 @@ mod tests {
     #[test]
     fn src_port_invalid() {
         let s: &str = "deny ip inside [failhere] outside any";
-        assert_eq!(Rule::from_str(s).unwrap_err().0, FieldError::PortInvalid);
+        assert_eq!(Rule::from_str(s).unwrap_err()[0].0, FieldError::PortInvalid);
     }
 
     #[test]
     fn dst_port_invalid() {
         let s: &str = "deny ip inside any outside [failhere]";
-        assert_eq!(Rule::from_str(s).unwrap_err().0, FieldError::PortInvalid);
+        assert_eq!(Rule::from_str(s).unwrap_err()[0].0, FieldError::PortInvalid);
     }
 }
@@ -0,0 +1,81 @@
+//! polls the files a build reads, for `--watch` to re-render as they change
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// modification times of every watched file, keyed by path
+pub type Snapshot = BTreeMap<PathBuf, SystemTime>;
+
+/// interval between polls of the watched files
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// records the modification time of every file under the provided paths
+/// - directories are walked recursively, as rulesets include files beside them
+/// - paths that cannot be read are left out, so their creation counts as a change
+pub fn snapshot(paths: &[PathBuf]) -> Snapshot {
+    let mut seen: Snapshot = Snapshot::new();
+    for path in paths {
+        record(path, &mut seen);
+    }
+    seen
+}
+
+fn record(path: &Path, seen: &mut Snapshot) {
+    let Ok(meta) = fs::metadata(path) else {
+        return;
+    };
+    if meta.is_dir() {
+        for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+            record(&entry.path(), seen);
+        }
+    } else if let Ok(modified) = meta.modified() {
+        seen.insert(path.to_path_buf(), modified);
+    }
+}
+
+/// blocks until a file under the provided paths is modified, added, or removed
+/// - returns the snapshot taken once the change is seen
+pub fn wait_for_change(paths: &[PathBuf], last: &Snapshot) -> Snapshot {
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let seen: Snapshot = snapshot(paths);
+        if seen != *last {
+            return seen;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_sees_added_and_removed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("acls")).unwrap();
+        std::fs::write(
+            dir.path().join("acls/web.acl"),
+            "allow tcp any any any 80\n",
+        )
+        .unwrap();
+        let paths: Vec<PathBuf> = vec![dir.path().join("site.yaml"), dir.path().join("acls")];
+
+        let before: Snapshot = snapshot(&paths);
+        assert_eq!(
+            before.keys().collect::<Vec<_>>(),
+            vec![&dir.path().join("acls/web.acl")]
+        );
+
+        std::fs::write(dir.path().join("site.yaml"), "deployment: {}\n").unwrap();
+        let added: Snapshot = snapshot(&paths);
+        assert_ne!(added, before);
+
+        std::fs::remove_file(dir.path().join("site.yaml")).unwrap();
+        assert_eq!(snapshot(&paths), before);
+    }
+}
@@ -0,0 +1,385 @@
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+fn am3k(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_am3k"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap()
+}
+
+fn am3k_stdin(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_am3k"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn stdin_prints_expanded_rules() {
+    let output = am3k_stdin(
+        &["--stdin"],
+        "allow tcp inside any outside 22\nallow udp outside any inside 161,162\n",
+    );
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "allow tcp inside any outside 22\n\
+         allow udp outside any inside 161\n\
+         allow udp outside any inside 162\n"
+    );
+}
+
+#[test]
+fn list_platforms_prints_makes_and_models() {
+    let output = am3k(&["--list-platforms"]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "arista\n  7050sx3-48yc8\t3 interface patterns\n  7280sr3-48yc8\t3 interface patterns\n\
+         juniper\n  srx1500\t3 interface patterns\n  qfx5200-32c\t3 interface patterns\n\
+         \x20 qfx5200-48y\t3 interface patterns\n"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_am3k"))
+        .arg("--list-platforms")
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env("AM3K_PLATFORMS_PATH", "./missing")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("No supported platforms found in [./missing]"));
+}
+
+#[test]
+fn count_prints_rules_before_and_after_expansion() {
+    let output = am3k(&["site/example.yaml", "-q", "--count"]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "valid.example\t5\t7\ntotal\t5\t7\n"
+    );
+}
+
+#[test]
+fn json_schema_describes_config() {
+    let output = am3k(&["--json-schema"]);
+    let schema: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(schema["properties"]["deployment"].is_object());
+    assert!(schema["properties"]["defaults"].is_object());
+}
+
+#[test]
+fn explain_describes_exit_code() {
+    let output = am3k(&["--explain", "3"]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "3\tdevices or rulesets failed validation\n\
+         3\trules provided on stdin failed to parse\n"
+    );
+
+    let output = am3k(&["--explain", "errors"]);
+    let table: String = String::from_utf8(output.stdout).unwrap();
+    assert!(table.starts_with("1\tconfiguration or stdin could not be read\n"));
+    assert!(table.ends_with("9\tconfigs checked by --config-dir failed validation\n"));
+}
+
+#[test]
+fn explain_rule_describes_a_rule() {
+    let output = am3k(&[
+        "--explain-rule",
+        "allow tcp 10.0.0.0/24 any 10.1.0.0/24 443",
+    ]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Permit TCP from 10.0.0.0/24 (any source port) to 10.1.0.0/24 port 443\n"
+    );
+
+    let output = am3k(&["--explain-rule", "allow tcp any any any 99999"]);
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn report_size_prints_to_stderr() {
+    let plain = am3k(&["site/example.yaml"]);
+    let output = am3k(&["site/example.yaml", "--report-size"]);
+    let report: String = String::from_utf8(output.stderr).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, plain.stdout);
+    assert!(report.starts_with("output 1\t"));
+    assert!(report.lines().last().unwrap().starts_with("total\t"));
+}
+
+#[test]
+fn stdin_reports_empty_input() {
+    let output = am3k_stdin(&["--stdin"], "");
+
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("No rules provided on stdin."));
+}
+
+#[test]
+fn log_timestamps_prefix_lines() {
+    let output = Command::new(env!("CARGO_BIN_EXE_am3k"))
+        .args(["site/example.yaml"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env("AM3K_LOG_TIMESTAMPS", "1")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let line = stdout
+        .lines()
+        .find(|line| line.contains("Loading configuration file"))
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(line[20..].starts_with(" [INFO] Loading configuration file"));
+    assert_eq!(&line[4..5], "-");
+    assert_eq!(&line[10..11], "T");
+    assert_eq!(&line[19..20], "Z");
+}
+
+#[test]
+fn quiet_prints_only_rendered_output() {
+    let output = am3k(&["site/example.yaml", "-q"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    assert!(stdout.trim_start().starts_with("rsk101-ext-fw1:"));
+    assert!(!stdout.contains("Loading"));
+}
+
+#[test]
+fn templates_path_renders_custom_template() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("custom.tera"),
+        "custom {% for device in devices %}{{ device.model }} {{ device.name }}, {% endfor %}",
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_am3k"))
+        .args(["site/example.yaml", "-q", "--template", "custom.tera"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env("AM3K_TEMPLATES_PATH", dir.path())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "\ncustom srx1500 rsk101-ext-fw1, srx1500 rsk101-ext-fw2, \
+         srx1500 rsk101-ext-fw3, srx1500 rsk101-ext-fw4, \n"
+    );
+}
+
+#[test]
+fn no_expand_keeps_port_ranges_as_one_rule() {
+    let args: [&str; 4] = ["site/example.yaml", "-q", "--format", "nftables"];
+    let expanded: String = String::from_utf8(am3k(&args).stdout).unwrap();
+    let output = am3k(&[&args[..], &["--no-expand"]].concat());
+    let kept: String = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(expanded.contains("udp dport 161 log drop\n"));
+    assert!(expanded.contains("udp dport 162 log drop\n"));
+    assert!(!expanded.contains("udp dport 161-162"));
+    assert!(kept.contains("udp dport 161-162 log drop\n"));
+    assert!(!kept.contains("udp dport 161 log drop"));
+}
+
+#[test]
+fn rulesets_path_reads_either_spelling() {
+    let dir = tempfile::tempdir().unwrap();
+    let rulesets = |name: &str| {
+        Command::new(env!("CARGO_BIN_EXE_am3k"))
+            .args(["site/example.yaml", "-q"])
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .env_remove("AM3K_RULESETS_PATH")
+            .env_remove("AM3K_RULESET_PATH")
+            .env(name, dir.path())
+            .output()
+            .unwrap()
+    };
+
+    // the empty directory has no `valid.example.acl`, so honoring it fails the config
+    assert_eq!(rulesets("AM3K_RULESETS_PATH").status.code(), Some(2));
+    assert_eq!(rulesets("AM3K_RULESET_PATH").status.code(), Some(2));
+    assert!(rulesets("AM3K_RULESETS").status.success());
+}
+
+#[test]
+fn config_dir_summarizes_each_config() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::copy("site/example.yaml", dir.path().join("good.yml")).unwrap();
+    std::fs::write(dir.path().join("broken.yml"), "deployment: [\n").unwrap();
+    std::fs::write(dir.path().join("notes.txt"), "not a config").unwrap();
+    let at = |file: &str| dir.path().join(file).display().to_string();
+
+    let output = am3k(&["--config-dir", dir.path().to_str().unwrap(), "-q"]);
+    let summary: String = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(output.status.code(), Some(9));
+    assert!(summary.starts_with(&format!(
+        "{}\tfail\tYamlMalformed: {}",
+        at("broken.yml"),
+        at("broken.yml")
+    )));
+    assert!(summary.contains(&format!("\n{}\tpass\n", at("good.yml"))));
+    assert!(summary.ends_with("\ntotal\t1 passed\t1 failed\n"));
+
+    std::fs::remove_file(dir.path().join("broken.yml")).unwrap();
+    let output = am3k(&["--config-dir", dir.path().to_str().unwrap(), "-q"]);
+    assert!(output.status.success());
+}
+
+#[test]
+fn device_name_overrides_devicelist() {
+    let output = am3k(&["site/example.yaml", "-q", "--device-name", "rsk202-int-fw1"]);
+    let rendered: String = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(rendered.contains("\nrsk202-int-fw1:\n  type: juniper\n"));
+    assert!(!rendered.contains("rsk101-ext-fw1"));
+
+    let output = am3k(&["site/example.yaml", "-q", "--device-name", "firewall1"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("firewall1"));
+}
+
+#[test]
+fn missing_template_lists_available() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("other.tera"), "other").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_am3k"))
+        .args(["site/example.yaml", "-q"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env("AM3K_TEMPLATES_PATH", dir.path())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(output.status.code(), Some(6));
+    assert!(output.stdout.is_empty());
+    assert!(stderr.contains(&format!(
+        "template 'ruleset.tera' not found in {}; available: other.tera",
+        dir.path().display()
+    )));
+}
+
+#[test]
+fn diagnostics_json_reports_rule_errors() {
+    let output = am3k_stdin(
+        &["--stdin", "--diagnostics", "json"],
+        "allow tcp inside any outside 22\nallow tcp inside any outside 22s\n",
+    );
+    let diagnostics: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(
+        diagnostics,
+        serde_json::json!([{
+            "file": "stdin",
+            "line": 2,
+            "column": 30,
+            "code": "PortInvalid",
+            "message": "PortInvalid: expected a port (0-65535) or service name, range of ports, comma-separated list of ports, or 'any'",
+        }])
+    );
+}
+
+#[test]
+fn diagnostics_json_reports_ruleset_files() {
+    let output = am3k(&["site/invalid.example.yaml", "--diagnostics", "json"]);
+    let diagnostics: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(diagnostics[0]["file"], "./acls/invalid.example.acl");
+    assert_eq!(diagnostics[0]["line"], 1);
+    assert_eq!(diagnostics[0]["column"], 7);
+    assert_eq!(diagnostics[0]["code"], "ProtocolUnsupported");
+}
+
+#[test]
+fn diff_reports_changed_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let changed = dir.path().join("changed.yaml");
+    let example: String =
+        std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/site/example.yaml")).unwrap();
+    std::fs::write(
+        &changed,
+        example.replace("rsk101-ext-fw4", "rsk101-ext-fw5"),
+    )
+    .unwrap();
+
+    let output = am3k(&["-q", "diff", "site/example.yaml", changed.to_str().unwrap()]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert_eq!(output.status.code(), Some(7));
+    assert!(stdout.starts_with("--- site/example.yaml\n+++ "));
+    assert!(stdout.contains("\n-rsk101-ext-fw4:\n+rsk101-ext-fw5:\n"));
+    assert!(!stdout.contains("\n-rsk101-ext-fw3:\n"));
+}
+
+#[test]
+fn diff_names_the_failing_side() {
+    let output = am3k(&[
+        "-q",
+        "diff",
+        "site/example.yaml",
+        "site/invalid.example.yaml",
+    ]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr.contains("new config site/invalid.example.yaml could not be rendered"));
+}
+
+#[test]
+fn unmatched_ruleset_glob_errs_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let config: String = std::fs::read_to_string("site/example.yaml")
+        .unwrap()
+        .replace(
+            "rulesets: [valid.example]",
+            "rulesets: [edge-*, valid.example]",
+        );
+    let path = dir.path().join("glob.yaml");
+    std::fs::write(&path, config).unwrap();
+
+    let output = am3k(&[path.to_str().unwrap()]);
+    let errors: String = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(errors.matches("RulesetFileDNE").count(), 1);
+    assert!(errors.contains("RulesetFileDNE: failed to find matching ruleset file: edge-*"));
+}
@@ -0,0 +1,671 @@
+use am3k::cli::{Args, EnvVars};
+use am3k::{AppError, Layout, LogLevel};
+use tempfile::TempDir;
+
+fn env() -> EnvVars {
+    EnvVars::default()
+}
+
+fn args(config: &str) -> Args {
+    Args {
+        config: Some(String::from(config)),
+        loglevel: LogLevel::None,
+        ..Args::default()
+    }
+}
+
+/// writes `rules` as the `site` ruleset of a copy of `example` edited by `edit`,
+/// returning the directory holding both, and args reading the copy
+fn site(example: &str, rules: &str, edit: impl Fn(String) -> String) -> (TempDir, Args) {
+    let dir: TempDir = tempfile::tempdir().unwrap();
+    let acls = dir.path().join("acls");
+    std::fs::create_dir(&acls).unwrap();
+    std::fs::write(acls.join("site.acl"), rules).unwrap();
+    let config = dir.path().join("site.yaml");
+    let example: String = std::fs::read_to_string(example).unwrap();
+    std::fs::write(&config, edit(example.replace("valid.example", "site"))).unwrap();
+
+    let mut args: Args = args(config.to_str().unwrap());
+    args.env.rulesets = acls.to_str().unwrap().to_string();
+    (dir, args)
+}
+
+/// builds the config `args` reads, concatenating every rendered output
+fn build(args: &Args) -> Result<String, AppError> {
+    am3k::build(args.config.as_deref().unwrap(), args).map(|output| output.concat())
+}
+
+#[test]
+fn compile_returns_expanded_rulesets() {
+    let output = am3k::compile("site/example.yaml", &env(), 10000, None, LogLevel::None).unwrap();
+    let rules: usize = output
+        .rulesets
+        .into_iter()
+        .map(|rs| rs.expand().into_iter().count())
+        .sum();
+
+    let names: Vec<&str> = output.devices.iter().map(|d| d.name.as_str()).collect();
+    assert_eq!(names, output.config.deployment.devicelist);
+    assert_eq!(rules, 7);
+}
+
+#[test]
+fn compile_errs_on_rulesets_over_model_acl_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let juniper: String = std::fs::read_to_string("platform/juniper.yaml").unwrap();
+    let compile = |max: usize| {
+        let capped: String = juniper.replace(
+            "  - name: srx1500\n",
+            &format!("  - name: srx1500\n    max_acl_entries: {}\n", max),
+        );
+        std::fs::write(dir.path().join("juniper.yaml"), capped).unwrap();
+        let env = EnvVars {
+            platforms: dir.path().to_str().unwrap().to_string(),
+            ..env()
+        };
+        am3k::compile("site/example.yaml", &env, 10000, None, LogLevel::None)
+    };
+
+    // valid.example deploys as 7 expanded rules, 2 established return rules, and the default
+    assert!(compile(10).is_ok());
+    assert!(matches!(compile(9), Err(AppError::Unbuildable(_))));
+}
+
+#[test]
+fn compile_errs_on_unbuildable_config() {
+    let err = am3k::compile(
+        "site/invalid.example.yaml",
+        &env(),
+        10000,
+        None,
+        LogLevel::None,
+    )
+    .unwrap_err();
+    assert!(matches!(err, AppError::Unbuildable(_)));
+}
+
+#[test]
+fn run_errs_on_missing_config() {
+    let err = am3k::run(&args("site/missing.yaml")).unwrap_err();
+    assert!(matches!(err, AppError::ConfigLoad(_)));
+    assert_eq!(err.code(), 1);
+}
+
+#[test]
+fn run_errs_on_unset_config() {
+    let args = Args {
+        loglevel: LogLevel::None,
+        ..Args::default()
+    };
+    let err = am3k::run(&args).unwrap_err();
+    assert!(matches!(err, AppError::ConfigLoad(_)));
+    assert_eq!(err.code(), 1);
+}
+
+#[test]
+fn run_errs_on_invalid_rulesets() {
+    let err = am3k::run(&args("site/invalid.example.yaml")).unwrap_err();
+    assert!(matches!(err, AppError::Unbuildable(_)));
+    assert_eq!(err.code(), 3);
+}
+
+#[test]
+fn size_report_counts_rendered_lines_and_bytes() {
+    let rendered: Vec<String> =
+        am3k::build("site/example.yaml", &args("site/example.yaml")).unwrap();
+    let report: String = am3k::size_report(&rendered);
+    let lines: Vec<&str> = report.lines().collect();
+
+    assert_eq!(lines.len(), rendered.len() + 1);
+    for (i, output) in rendered.iter().enumerate() {
+        assert_eq!(
+            lines[i],
+            format!(
+                "output {}\t{} lines\t{} bytes",
+                i + 1,
+                output.lines().count(),
+                output.len()
+            )
+        );
+    }
+    let all: String = rendered.concat();
+    assert_eq!(
+        lines[rendered.len()],
+        format!(
+            "total\t{} lines\t{} bytes",
+            rendered.iter().map(|o| o.lines().count()).sum::<usize>(),
+            all.len()
+        )
+    );
+}
+
+#[test]
+fn render_is_byte_stable() {
+    let render = || {
+        let output =
+            am3k::compile("site/example.yaml", &env(), 10000, None, LogLevel::None).unwrap();
+        am3k::render(
+            output,
+            Some("nftables.tera"),
+            None,
+            "./tmpl",
+            &Layout::default(),
+            LogLevel::None,
+        )
+        .unwrap()
+    };
+    let first: Vec<String> = render();
+
+    assert!(!first.is_empty());
+    assert_eq!(first, render());
+}
+
+#[test]
+fn build_is_repeatable_across_failures() {
+    let valid: Args = args("site/example.yaml");
+    let first: Vec<String> = am3k::build("site/example.yaml", &valid).unwrap();
+
+    let invalid: Args = args("site/invalid.example.yaml");
+    assert!(am3k::build("site/invalid.example.yaml", &invalid).is_err());
+    assert_eq!(am3k::build("site/example.yaml", &valid).unwrap(), first);
+}
+
+#[test]
+fn indent_sets_leading_whitespace_per_level() {
+    let render = |spaces: usize| {
+        let output =
+            am3k::compile("site/example.yaml", &env(), 10000, None, LogLevel::None).unwrap();
+        let layout = Layout {
+            indent: Some(spaces),
+            line_width: None,
+        };
+        am3k::render(
+            output,
+            Some("nftables.tera"),
+            None,
+            "./tmpl",
+            &layout,
+            LogLevel::None,
+        )
+        .unwrap()
+    };
+    let (two, four): (String, String) = (render(2).concat(), render(4).concat());
+
+    assert!(two.contains("\n  chain forward {\n    type filter"));
+    assert!(four.contains("\n    chain forward {\n        type filter"));
+}
+
+#[test]
+fn strict_fails_on_unreachable_rules() {
+    let (_dir, mut args) = site(
+        "site/example.yaml",
+        "deny ip outside any inside any\nallow tcp outside any inside 22\n",
+        |example| example,
+    );
+    assert!(am3k::run(&args).is_ok());
+
+    args.strict = true;
+    let err = am3k::run(&args).unwrap_err();
+    assert!(matches!(err, AppError::WarningsFatal { count: 1 }));
+    assert_eq!(err.code(), 8);
+}
+
+#[test]
+fn strict_fails_on_conflicting_rules() {
+    let (_dir, mut args) = site(
+        "site/example.yaml",
+        "deny tcp outside any inside 22\nallow tcp outside any inside 22\n",
+        |example| example,
+    );
+    assert!(am3k::run(&args).is_ok());
+
+    // the conflicting allow is also shadowed by the deny
+    args.strict = true;
+    let err = am3k::run(&args).unwrap_err();
+    assert!(matches!(err, AppError::WarningsFatal { count: 2 }));
+}
+
+#[test]
+fn strict_fails_on_unexpected_service_ports() {
+    let (_dir, mut args) = site(
+        "site/example.yaml",
+        "allow udp outside any inside 0\nallow tcp outside any inside 22\n",
+        |example| example,
+    );
+    assert!(am3k::run(&args).is_ok());
+
+    args.strict = true;
+    let err = am3k::run(&args).unwrap_err();
+    assert!(matches!(err, AppError::WarningsFatal { count: 1 }));
+}
+
+#[test]
+fn strict_fails_on_open_rulesets() {
+    let (dir, mut args) = site(
+        "site/example.yaml",
+        "allow tcp outside any inside 22\n",
+        |example| {
+            example
+                .replace("default: deny", "default: allow")
+                .replace("[outside]", "[any]")
+                .replace("[inside]", "[any]")
+        },
+    );
+    args.strict = true;
+    let err = am3k::run(&args).unwrap_err();
+    assert!(matches!(err, AppError::WarningsFatal { count: 2 }));
+
+    std::fs::write(
+        dir.path().join("acls/site.acl"),
+        "allow tcp outside any inside 22\ndeny ip any any any any\n",
+    )
+    .unwrap();
+    assert!(am3k::run(&args).is_ok());
+}
+
+#[test]
+fn declared_family_fails_rules_of_the_other() {
+    let declare = |family: &'static str| {
+        site(
+            "site/example.yaml",
+            "allow tcp 10.0.0.0/8 any any 22\nallow tcp 2001:db8::/32 any any 22\n",
+            move |example| {
+                let families: String =
+                    format!("rulesets: [site]\n  families: {{ site: {} }}", family);
+                example
+                    .replace("[outside]", "[any]")
+                    .replace("[inside]", "[any]")
+                    .replace("rulesets: [site]", &families)
+            },
+        )
+    };
+
+    let (_dir, args) = declare("inet");
+    let err = am3k::run(&args).unwrap_err();
+    assert!(matches!(&err, AppError::Unbuildable(e) if e.len() == 1));
+
+    let (_dir, args) = declare("both");
+    assert!(am3k::run(&args).is_ok());
+}
+
+#[test]
+fn formats_without_negation_refuse_negated_rules() {
+    let (_dir, mut args) = site(
+        "site/example.yaml",
+        "deny !icmp outside any inside any\nallow tcp outside any inside 443\n",
+        |example| example,
+    );
+    args.format = Some(String::from("arista"));
+    let err = build(&args).unwrap_err();
+    assert!(matches!(&err, AppError::Render(_)));
+    assert!(err
+        .to_string()
+        .contains("arista format cannot express negated rules"));
+
+    args.format = Some(String::from("cisco"));
+    let err = build(&args).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("cisco format cannot express negated rules, found: deny !icmp"));
+
+    args.format = Some(String::from("nftables"));
+    assert!(build(&args).unwrap().contains("meta l4proto != icmp"));
+}
+
+#[test]
+fn arista_renders_unexpanded_port_maps() {
+    let (_dir, mut args) = site(
+        "site/arista.example.yaml",
+        "allow tcp outside any inside 8000-8100\nallow tcp outside any inside 80,443\n",
+        |example| example,
+    );
+    args.no_expand = true;
+    let rendered: String = build(&args).unwrap();
+    assert!(rendered.contains("10 permit tcp outside inside range 8000 8100\n"));
+    assert!(rendered.contains("30 permit tcp outside inside eq 80 443\n"));
+
+    args.no_expand = false;
+    let rendered: String = build(&args).unwrap();
+    assert!(rendered.contains("10 permit tcp outside inside eq 8000\n"));
+    assert!(rendered.contains(" permit tcp outside inside eq 80\n"));
+}
+
+#[test]
+fn arista_renders_established_and_icmp_types() {
+    let rendered: String = build(&args("site/arista.example.yaml")).unwrap();
+    assert!(rendered.contains("10 permit icmp outside inside 8\n"));
+    assert!(rendered.contains("40 permit tcp inside eq 80 outside established log\n"));
+
+    let (_dir, mut args) = site(
+        "site/arista.example.yaml",
+        "allow icmp outside any inside 0,8\n",
+        |example| example,
+    );
+    args.no_expand = true;
+    assert!(matches!(build(&args), Err(AppError::Render(_))));
+}
+
+#[test]
+fn seed_rules_precede_each_ruleset() {
+    let (dir, mut args) = site(
+        "site/example.yaml",
+        "allow tcp outside any inside 443\n",
+        |example| example.replace("[site]", "[site, dns]"),
+    );
+    std::fs::write(
+        dir.path().join("acls/dns.acl"),
+        "allow udp outside any inside 53\n",
+    )
+    .unwrap();
+    let seed = dir.path().join("seed.acl");
+    std::fs::write(
+        &seed,
+        "allow tcp outside any inside 22\nallow icmp outside any inside 8\n",
+    )
+    .unwrap();
+    let tmpl = dir.path().join("tmpl");
+    std::fs::create_dir(&tmpl).unwrap();
+    std::fs::write(
+        tmpl.join("ports.tera"),
+        "{% for ruleset in rulesets %}{% for rule in ruleset %}{{ rule.dst_port }} {% endfor %}\n{% endfor %}",
+    )
+    .unwrap();
+
+    args.env.templates = tmpl.to_str().unwrap().to_string();
+    args.template = Some(String::from("ports.tera"));
+    args.seed_rules = Some(seed.to_str().unwrap().to_string());
+    assert_eq!(build(&args).unwrap(), "22 8 443 \n22 8 53 \n");
+
+    std::fs::write(&seed, "allow tcp outside any inside 22\nallow tcp any\n").unwrap();
+    let err = build(&args).unwrap_err();
+    let AppError::Unbuildable(errors) = err else {
+        panic!("expected Unbuildable, found {:?}", err);
+    };
+    assert!(errors.to_string().contains("seed.acl (prologue):2:"));
+
+    // errors found after parsing name the prologue too, not the ruleset's own line
+    for (rule, field) in [
+        ("allow tcp 192.0.2.0/24 any inside 22", "PrefixOutOfScope"),
+        ("allow tcp @missing any inside 22", "ObjectUndefined"),
+    ] {
+        std::fs::write(
+            &seed,
+            format!("allow tcp outside any inside 22\n{}\n", rule),
+        )
+        .unwrap();
+        let err = build(&args).unwrap_err();
+        let AppError::Unbuildable(errors) = err else {
+            panic!("expected Unbuildable, found {:?}", err);
+        };
+        let errors: String = errors.to_string();
+        assert!(errors.contains("seed.acl (prologue):2:11"), "{}", errors);
+        assert!(errors.contains(field), "{}", errors);
+        assert!(!errors.contains(".acl:2:"), "{}", errors);
+    }
+}
+
+#[test]
+fn included_rule_errors_name_the_included_file() {
+    let (dir, args) = site(
+        "site/example.yaml",
+        "include child\nallow tcp outside any inside 443\n",
+        |example| example,
+    );
+    std::fs::write(
+        dir.path().join("acls/child.acl"),
+        "allow tcp outside any inside 22\nallow tcp outside any inside 80\nallow tcp 192.0.2.0/24 any inside 8080\n",
+    )
+    .unwrap();
+
+    let err = build(&args).unwrap_err();
+    let AppError::Unbuildable(errors) = err else {
+        panic!("expected Unbuildable, found {:?}", err);
+    };
+    let errors: String = errors.to_string();
+    assert!(errors.contains("child.acl:3:11"), "{}", errors);
+    assert!(!errors.contains("site.acl:3:"), "{}", errors);
+}
+
+#[test]
+fn unanchored_interface_patterns_warn_or_fail() {
+    let dir = tempfile::tempdir().unwrap();
+    let juniper: String = std::fs::read_to_string("platform/juniper.yaml").unwrap();
+    std::fs::write(
+        dir.path().join("juniper.yaml"),
+        juniper.replace(r"- ^(ae|lo)\d{1,3}(\.\d{1,3})?$", "- (ae|lo)"),
+    )
+    .unwrap();
+
+    let mut args: Args = args("site/example.yaml");
+    args.strict = true;
+    assert!(am3k::run(&args).is_ok());
+
+    args.env.platforms = dir.path().to_str().unwrap().to_string();
+    let err = am3k::run(&args).unwrap_err();
+    assert!(matches!(err, AppError::WarningsFatal { count: 1 }));
+
+    args.strict = false;
+    assert!(am3k::run(&args).is_ok());
+    args.strict_anchors = true;
+    assert!(matches!(am3k::run(&args), Err(AppError::Unbuildable(_))));
+}
+
+#[test]
+fn disabled_rules_render_only_when_included() {
+    let (dir, mut args) = site(
+        "site/example.yaml",
+        "allow tcp outside any inside 443\ndisabled allow tcp outside any inside 8080\n",
+        |example| example,
+    );
+    let tmpl = dir.path().join("tmpl");
+    std::fs::create_dir(&tmpl).unwrap();
+    std::fs::write(
+        tmpl.join("rules.tera"),
+        "{% for rule in rulesets[0] %}{{ rule.dst_port }}:{{ rule.enabled }} {% endfor %}",
+    )
+    .unwrap();
+
+    args.format = Some(String::from("nftables"));
+    let nftables: String = build(&args).unwrap();
+    assert!(nftables.contains("tcp dport 443 accept"));
+    assert!(!nftables.contains("8080"));
+
+    args.include_disabled = true;
+    assert_eq!(build(&args).unwrap(), nftables);
+
+    args.env.templates = tmpl.to_str().unwrap().to_string();
+    args.format = None;
+    args.template = Some(String::from("rules.tera"));
+    assert_eq!(build(&args).unwrap(), "443:true 8080:false ");
+    args.include_disabled = false;
+    assert_eq!(build(&args).unwrap(), "443:true ");
+}
+
+#[test]
+fn strict_fails_on_asymmetric_defaults() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = dir.path().join("asymmetric.yaml");
+    let example: String = std::fs::read_to_string("site/example.yaml").unwrap();
+    let (ingress, egress) = example.split_once("  egress:").unwrap();
+    let egress: String = egress.replacen("default: deny", "default: allow", 1);
+    std::fs::write(&config, format!("{}  egress:{}", ingress, egress)).unwrap();
+
+    let mut args: Args = args("site/example.yaml");
+    args.strict = true;
+    assert!(am3k::run(&args).is_ok());
+
+    // an allowing egress default also leaves the ruleset open, failing on its own
+    args.config = Some(config.to_str().unwrap().to_string());
+    let err = am3k::run(&args).unwrap_err();
+    assert!(matches!(err, AppError::WarningsFatal { count: 2 }));
+
+    args.allow_asymmetric_default = true;
+    let err = am3k::run(&args).unwrap_err();
+    assert!(matches!(err, AppError::WarningsFatal { count: 1 }));
+}
+
+#[test]
+fn compile_warns_on_empty_egress_interfaces() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = dir.path().join("empty.yaml");
+    let example: String = std::fs::read_to_string("site/example.yaml").unwrap();
+    std::fs::write(&config, example.replace("[ae201, ae202]", "[]")).unwrap();
+
+    let output = am3k::compile(
+        config.to_str().unwrap(),
+        &env(),
+        10000,
+        None,
+        LogLevel::None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        output.warnings,
+        vec![String::from(
+            "juniper srx1500: egress has no interfaces, so its rulesets apply nowhere"
+        )]
+    );
+}
+
+#[test]
+fn compile_warns_on_zone_interface_missing_from_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = dir.path().join("zones.yaml");
+    let example: String = std::fs::read_to_string("site/example.yaml").unwrap();
+    let zoned: String = example.replacen(
+        "    interfaces: [ae101, ae102]\n",
+        "    interfaces: [ae101, ae102]\n    zones: { trust: [ae101, ae102, ae109] }\n",
+        1,
+    );
+    std::fs::write(&config, zoned).unwrap();
+
+    let output = am3k::compile(
+        config.to_str().unwrap(),
+        &env(),
+        10000,
+        None,
+        LogLevel::None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        output.warnings,
+        vec![String::from(
+            "juniper srx1500: ingress zone trust interface ae109 is not among the device ingress paths"
+        )]
+    );
+}
+
+#[test]
+fn bundle_renders_inline_rulesets() {
+    let output = am3k::compile_bundle(
+        "site/bundle.example.yaml",
+        &env(),
+        10000,
+        None,
+        LogLevel::None,
+    )
+    .unwrap();
+    let rules: Vec<String> = output.rulesets[0]
+        .clone()
+        .into_iter()
+        .map(|rule| rule.to_string())
+        .collect();
+
+    assert_eq!(
+        rules,
+        vec![
+            "allow tcp outside any inside 80",
+            "allow tcp outside any inside 443",
+            "allowlog udp outside any inside 53",
+        ]
+    );
+
+    let rendered: Vec<String> = am3k::render(
+        output,
+        Some("nftables.tera"),
+        None,
+        "./tmpl",
+        &Layout::default(),
+        LogLevel::None,
+    )
+    .unwrap();
+    assert!(rendered[0].contains("chain web-ingress"));
+}
+
+#[test]
+fn iptables_skips_ipv6_rules() {
+    let (_dir, mut args) = site(
+        "site/example.yaml",
+        "allow tcp 10.0.0.0/8 any any 22\nallow tcp 2001:db8::/32 any 2001:db8:1::/48 443\n",
+        |example| {
+            example
+                .replace("[outside]", "[any]")
+                .replace("[inside]", "[any]")
+        },
+    );
+    args.format = Some(String::from("iptables"));
+    let rendered: String = build(&args).unwrap();
+    assert!(rendered.contains("-p tcp -s 10.0.0.0/8 --dport 22 -j ACCEPT\n"));
+    assert!(!rendered.contains("2001:db8"));
+}
+
+#[test]
+fn arista_skips_ipv6_rules() {
+    let (_dir, mut args) = site(
+        "site/arista.example.yaml",
+        "allow tcp 10.0.0.0/8 any any 22\nallow tcp 2001:db8::/32 any 2001:db8:1::/48 443\n",
+        |example| {
+            example
+                .replace("[outside]", "[any]")
+                .replace("[inside]", "[any]")
+        },
+    );
+    args.format = Some(String::from("arista"));
+    let rendered: String = build(&args).unwrap();
+    assert!(rendered.contains("10 permit tcp 10.0.0.0/8 any eq 22\n"));
+    assert!(!rendered.contains("2001:db8"));
+}
+
+#[test]
+fn transforms_match_prefixes_however_written() {
+    let (_dir, mut args) = site(
+        "site/example.yaml",
+        "allow tcp 2001:DB8::/32 any any 443\n",
+        |example| {
+            example
+                .replacen("[outside]", "[2001:DB8:0::/32]", 1)
+                .replace("[outside]", "[any]")
+                .replace("[inside]", "[any]")
+                .replacen(
+                    "src: false",
+                    "src: { 2001:0DB8::/32: 2001:db8:ffff::/48 }",
+                    1,
+                )
+        },
+    );
+    args.format = Some(String::from("nftables"));
+    let rendered: String = build(&args).unwrap();
+    assert!(rendered.contains("ip6 saddr 2001:db8:ffff::/48 tcp dport 443 accept"));
+}
+
+#[test]
+fn numbered_expansions_leave_room_for_return_rules() {
+    let (_dir, mut args) = site(
+        "site/arista.example.yaml",
+        "seq=10 allow tcp outside any inside 22,80\nallow tcp outside any inside 443\n",
+        |example| example,
+    );
+    args.auto_seq = true;
+    let rendered: String = build(&args).unwrap();
+    let acl: &str = rendered.split("ip access-list ").nth(1).unwrap();
+    let seqs: Vec<&str> = acl
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|token| token.parse::<u32>().is_ok())
+        .collect();
+    assert_eq!(seqs, vec!["10", "11", "12", "13", "20", "21", "30"]);
+    assert!(acl.contains("\n   11 permit tcp inside eq 22 outside established\n"));
+    assert!(acl.contains("\n   12 permit tcp outside inside eq 80\n"));
+}